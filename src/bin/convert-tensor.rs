@@ -1,12 +1,56 @@
+use std::path::Path;
+
+use thistle::model::checkpoint::convert_pytorch_checkpoint;
+
 pub fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    if !args.len() == 3 {
-        eprintln!("usage: {} source.npz destination.ot", args[0]);
+    let mut args: Vec<_> = std::env::args().collect();
+    let skip_validation = args.iter().any(|arg| arg == "--no-validate");
+    args.retain(|arg| arg != "--no-validate");
+    if args.len() != 3 {
+        eprintln!("usage: {} [--no-validate] source.(bin|npz) destination.ot", args[0]);
         std::process::exit(1);
     }
 
     let source_file = &args[1];
     let destination_file = &args[2];
-    let tensors = tch::Tensor::read_npz(source_file).unwrap();
-    tch::Tensor::save_multi(&tensors, destination_file).unwrap();
+    if source_file.ends_with(".npz") {
+        let tensors = tch::Tensor::read_npz(source_file).unwrap();
+        tch::Tensor::save_multi(&tensors, destination_file).unwrap();
+        return;
+    }
+
+    let report = match convert_pytorch_checkpoint(Path::new(source_file), Path::new(destination_file), skip_validation) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    for (name, kind, size) in report.tensors.iter() {
+        println!("{:<60} {:?} {:?}", name, kind, size);
+    }
+    println!("{} tensors", report.tensors.len());
+    if !report.validated {
+        println!("validation skipped, the tensors are not checked against a config.json");
+    }
+    let check = &report.check;
+    for name in check.unexpected.iter() {
+        println!("unexpected (ignored when loading): {}", name);
+    }
+    for name in check.missing.iter() {
+        println!("missing: {}", name);
+    }
+    for (name, found, expected) in check.mismatched.iter() {
+        println!("shape mismatch: {} is {:?}, expected {:?}", name, found, expected);
+    }
+    if !check.is_valid() {
+        eprintln!(
+            "{} missing and {} mismatched tensors, {} not written",
+            check.missing.len(),
+            check.mismatched.len(),
+            destination_file
+        );
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,42 @@
+use clap::{App, Arg, SubCommand};
+use thistle::hnswlib::hnswio::load_hnsw_any;
+
+// cargo run --bin thistle-hnsw -- stats dumps/msmarco
+pub fn main() {
+    let matches = App::new("thistle-hnsw")
+        .about("Checks HNSW dumps")
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Reports layer sizes, degree distribution, unreachable points and full neighbour lists of a dump")
+                .arg(Arg::with_name("basename").required(true).help("dump name, without .hnsw.graph or .hnsw.data")),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("stats", Some(stats)) => {
+            let basename = stats.value_of("basename").unwrap();
+            match load_hnsw_any(basename) {
+                Ok(hnsw) => {
+                    let stats = hnsw.graph_stats();
+                    println!("type: {}", hnsw.type_name());
+                    print!("{}", stats);
+                    let problems = stats.problems();
+                    for problem in problems.iter() {
+                        println!("problem: {}", problem);
+                    }
+                    if !problems.is_empty() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(error) => {
+                    eprintln!("cannot load {:?}: {}", basename, error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", matches.usage());
+            std::process::exit(1);
+        }
+    }
+}
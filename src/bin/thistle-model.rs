@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use clap::{App, Arg, SubCommand};
+use thistle::model::inspect_model;
+
+// cargo run --bin thistle-model -- inspect models/bert-base-nli-stsb-mean-tokens
+pub fn main() {
+    let matches = App::new("thistle-model")
+        .about("Checks converted sentence transformer models")
+        .subcommand(
+            SubCommand::with_name("inspect")
+                .about("Reports the model configuration, checks rust_model.ot and runs a smoke encode")
+                .arg(Arg::with_name("dir").required(true).help("sentence transformer directory")),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("inspect", Some(inspect)) => {
+            let model_path = Path::new(inspect.value_of("dir").unwrap());
+            match inspect_model(model_path) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.is_ok() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(error) => {
+                    eprintln!("cannot inspect {:?}: {}", model_path, error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", matches.usage());
+            std::process::exit(1);
+        }
+    }
+}
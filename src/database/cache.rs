@@ -0,0 +1,463 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use fnv::FnvHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::role::EncodeRole;
+
+pub const DEFAULT_CACHE_MAX_ENTRIES: usize = 100_000;
+
+/// Starts the log file.
+const CACHE_MAGIC: &[u8; 8] = b"thcache1";
+
+/// Identifies an embedding: the model, the encode role and the normalized text, with their hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheKey {
+    hash: u64,
+    /// compared on lookup, so that two texts with the same hash are never mixed up
+    identity: String,
+}
+
+/// Where the embedding cache lives and how many embeddings it keeps.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub path: PathBuf,
+    /// least recently used embeddings are evicted past this number
+    pub max_entries: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "hits {} misses {} hit rate {:.3} evictions {} entries {}",
+            self.hits,
+            self.misses,
+            self.hit_rate(),
+            self.evictions,
+            self.entries
+        )
+    }
+}
+
+/// One line of the log file.
+#[derive(Serialize, Deserialize)]
+struct CacheRecord {
+    hash: u64,
+    identity: String,
+    embedding: Vec<f32>,
+}
+
+/// Counts the bytes read, to find where the last complete record of the log ends.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+struct CacheEntry {
+    identity: String,
+    embedding: Vec<f32>,
+    last_used: u64,
+}
+
+/// Embeddings already computed, persisted as an append-only bincode log.
+///
+/// Keys are the model identity, the encode role and the normalized text (trimmed, with
+/// whitespace runs collapsed), so one file can be shared by several models. Entries are
+/// indexed by the hash of the key and store the key itself, checked on lookup. The log is
+/// replayed when the cache is opened and rewritten with the live entries only once it holds
+/// twice as many records as `max_entries`.
+pub struct EmbeddingCache {
+    path: PathBuf,
+    model_id: String,
+    max_entries: usize,
+    /// by key hash, a key colliding with an entry replaces it
+    entries: HashMap<u64, CacheEntry>,
+    /// last_used tick -> key, oldest first
+    recency: BTreeMap<u64, u64>,
+    clock: u64,
+    log: BufWriter<File>,
+    log_records: usize,
+    stats: CacheStats,
+}
+
+impl EmbeddingCache {
+    pub fn open(config: &CacheConfig, model_id: &str) -> std::io::Result<EmbeddingCache> {
+        if let Some(parent) = config.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let mut magic = [0u8; 8];
+        let has_magic = match File::open(&config.path) {
+            Ok(mut file) => file.read_exact(&mut magic).is_ok() && &magic == CACHE_MAGIC,
+            Err(_) => false,
+        };
+        if !has_magic {
+            if config.path.exists() && fs::metadata(&config.path)?.len() > 0 {
+                log::warn!("EmbeddingCache {:?} has an unknown format, it is cleared", config.path);
+            }
+            File::create(&config.path)?.write_all(CACHE_MAGIC)?;
+        }
+
+        let mut cache = EmbeddingCache {
+            path: config.path.clone(),
+            model_id: model_id.to_string(),
+            max_entries: config.max_entries.max(1),
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            log: BufWriter::new(OpenOptions::new().append(true).open(&config.path)?),
+            log_records: 0,
+            stats: CacheStats::default(),
+        };
+
+        let mut reader = CountingReader {
+            inner: BufReader::new(File::open(&config.path)?),
+            count: 0,
+        };
+        reader.read_exact(&mut magic)?;
+        let mut valid_len = reader.count;
+        while let Ok(record) = bincode::deserialize_from::<_, CacheRecord>(&mut reader) {
+            valid_len = reader.count;
+            cache.log_records += 1;
+            cache.insert_entry(record.hash, record.identity, record.embedding);
+        }
+        // a truncated last record (interrupted run) is cut off, the next records are
+        // appended after the last complete one
+        if valid_len < fs::metadata(&config.path)?.len() {
+            log::warn!(
+                "EmbeddingCache {:?} ends with an incomplete record, truncated to {} bytes",
+                config.path,
+                valid_len
+            );
+            OpenOptions::new().write(true).open(&config.path)?.set_len(valid_len)?;
+        }
+        cache.stats.evictions = 0;
+        log::info!(
+            "EmbeddingCache {:?} loaded {} entries from {} records",
+            cache.path,
+            cache.entries.len(),
+            cache.log_records
+        );
+        Ok(cache)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            ..self.stats
+        }
+    }
+
+    pub fn key(&self, text: &str, role: EncodeRole) -> CacheKey {
+        let mut identity = String::with_capacity(self.model_id.len() + text.len() + 3);
+        identity.push_str(&self.model_id);
+        identity.push('\0');
+        identity.push(match role {
+            EncodeRole::Query => 'q',
+            EncodeRole::Document => 'd',
+        });
+        identity.push('\0');
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                identity.push(' ');
+            }
+            identity.push_str(word);
+        }
+        let mut hasher = FnvHasher::default();
+        hasher.write(identity.as_bytes());
+        CacheKey {
+            hash: hasher.finish(),
+            identity,
+        }
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<Vec<f32>> {
+        self.clock += 1;
+        let clock = self.clock;
+        match self.entries.get_mut(&key.hash) {
+            Some(entry) if entry.identity == key.identity => {
+                self.recency.remove(&entry.last_used);
+                self.recency.insert(clock, key.hash);
+                entry.last_used = clock;
+                self.stats.hits += 1;
+                Some(entry.embedding.clone())
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Adds an embedding and appends it to the log.
+    pub fn insert(&mut self, key: CacheKey, embedding: Vec<f32>) -> std::io::Result<()> {
+        let record = CacheRecord {
+            hash: key.hash,
+            identity: key.identity,
+            embedding,
+        };
+        bincode::serialize_into(&mut self.log, &record)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error))?;
+        self.log_records += 1;
+        self.insert_entry(record.hash, record.identity, record.embedding);
+        if self.log_records > 2 * self.max_entries {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.log.flush()
+    }
+
+    fn insert_entry(&mut self, key: u64, identity: String, embedding: Vec<f32>) {
+        self.clock += 1;
+        if let Some(previous) = self.entries.insert(
+            key,
+            CacheEntry {
+                identity,
+                embedding,
+                last_used: self.clock,
+            },
+        ) {
+            self.recency.remove(&previous.last_used);
+        }
+        self.recency.insert(self.clock, key);
+
+        while self.entries.len() > self.max_entries {
+            let oldest = match self.recency.keys().next() {
+                Some(tick) => *tick,
+                None => break,
+            };
+            if let Some(key) = self.recency.remove(&oldest) {
+                self.entries.remove(&key);
+                self.stats.evictions += 1;
+            }
+        }
+    }
+
+    /// Rewrites the log with the live entries, oldest first so that replaying it keeps the
+    /// recency order.
+    fn compact(&mut self) -> std::io::Result<()> {
+        self.log.flush()?;
+        let tmp_path = self.path.with_extension("compact");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(CACHE_MAGIC)?;
+            for key in self.recency.values() {
+                let entry = &self.entries[key];
+                let record = CacheRecord {
+                    hash: *key,
+                    identity: entry.identity.clone(),
+                    embedding: entry.embedding.clone(),
+                };
+                bincode::serialize_into(&mut writer, &record)
+                    .map_err(|error| std::io::Error::new(std::io::ErrorKind::Other, error))?;
+            }
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        self.log = BufWriter::new(OpenOptions::new().append(true).open(&self.path)?);
+        self.log_records = self.entries.len();
+        Ok(())
+    }
+}
+
+impl Drop for EmbeddingCache {
+    fn drop(&mut self) {
+        if self.flush().is_ok() {
+            log::info!("EmbeddingCache {:?} {}", self.path, self.stats());
+        }
+    }
+}
+
+/// Removes the cache file, e.g. after changing the weights of a model at the same path.
+pub fn clear_cache(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_cache_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("thistle-{}-{}.cache", name, std::process::id()));
+        clear_cache(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_cache_hits_and_persistence() {
+        let config = CacheConfig {
+            path: temp_cache_path("persistence"),
+            max_entries: 10,
+        };
+        {
+            let mut cache = EmbeddingCache::open(&config, "model-a").unwrap();
+            let key = cache.key("  hello   world ", EncodeRole::Document);
+            assert_eq!(key, cache.key("hello world", EncodeRole::Document));
+            assert_ne!(key, cache.key("hello world", EncodeRole::Query));
+            assert_eq!(None, cache.get(&key));
+            cache.insert(key.clone(), vec![1., 2., 3.]).unwrap();
+            assert_eq!(Some(vec![1., 2., 3.]), cache.get(&key));
+            assert_eq!(1, cache.stats().hits);
+            assert_eq!(1, cache.stats().misses);
+        }
+        let mut cache = EmbeddingCache::open(&config, "model-a").unwrap();
+        let key = cache.key("hello world", EncodeRole::Document);
+        assert_eq!(Some(vec![1., 2., 3.]), cache.get(&key));
+
+        let other_model = EmbeddingCache::open(&config, "model-b").unwrap();
+        assert_ne!(key, other_model.key("hello world", EncodeRole::Document));
+        drop(other_model);
+        drop(cache);
+        clear_cache(&config.path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_lru_eviction_and_compaction() {
+        let config = CacheConfig {
+            path: temp_cache_path("eviction"),
+            max_entries: 3,
+        };
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        let key = |cache: &EmbeddingCache, i: usize| cache.key(&format!("text {}", i), EncodeRole::Document);
+        for i in 0..3 {
+            cache.insert(key(&cache, i), vec![i as f32]).unwrap();
+        }
+        // 0 becomes the most recently used, 1 is evicted next
+        assert!(cache.get(&key(&cache, 0)).is_some());
+        cache.insert(key(&cache, 3), vec![3.]).unwrap();
+        assert!(cache.get(&key(&cache, 1)).is_none());
+        assert!(cache.get(&key(&cache, 0)).is_some());
+        assert_eq!(1, cache.stats().evictions);
+
+        for i in 4..20 {
+            cache.insert(key(&cache, i), vec![i as f32]).unwrap();
+        }
+        assert_eq!(3, cache.stats().entries);
+        drop(cache);
+
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        assert_eq!(3, cache.stats().entries);
+        assert_eq!(Some(vec![19.]), cache.get(&key(&cache, 19)));
+        drop(cache);
+        clear_cache(&config.path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_hash_collision() {
+        let config = CacheConfig {
+            path: temp_cache_path("collision"),
+            max_entries: 10,
+        };
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        let key = cache.key("hello world", EncodeRole::Document);
+        // another text with the same hash
+        let colliding = CacheKey {
+            hash: key.hash,
+            identity: "model\0d\0goodbye world".to_string(),
+        };
+        cache.insert(key.clone(), vec![1.]).unwrap();
+        assert_eq!(None, cache.get(&colliding));
+        cache.insert(colliding.clone(), vec![2.]).unwrap();
+        assert_eq!(Some(vec![2.]), cache.get(&colliding));
+        assert_eq!(None, cache.get(&key));
+        drop(cache);
+
+        // replayed from the log with the same checks
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        assert_eq!(Some(vec![2.]), cache.get(&colliding));
+        assert_eq!(None, cache.get(&key));
+        drop(cache);
+        clear_cache(&config.path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_unknown_format_cleared() {
+        let config = CacheConfig {
+            path: temp_cache_path("format"),
+            max_entries: 10,
+        };
+        fs::write(&config.path, b"not an embedding cache").unwrap();
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        assert_eq!(0, cache.stats().entries);
+        let key = cache.key("hello", EncodeRole::Query);
+        cache.insert(key.clone(), vec![1.]).unwrap();
+        drop(cache);
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        assert_eq!(Some(vec![1.]), cache.get(&key));
+        drop(cache);
+        clear_cache(&config.path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_truncated_record() {
+        let config = CacheConfig {
+            path: temp_cache_path("truncated"),
+            max_entries: 10,
+        };
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        let first = cache.key("first", EncodeRole::Document);
+        let second = cache.key("second", EncodeRole::Document);
+        cache.insert(first.clone(), vec![1., 2.]).unwrap();
+        cache.insert(second.clone(), vec![3., 4.]).unwrap();
+        drop(cache);
+
+        // an interrupted run leaves half of the last record
+        let len = fs::metadata(&config.path).unwrap().len();
+        OpenOptions::new().write(true).open(&config.path).unwrap().set_len(len - 5).unwrap();
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        assert_eq!(Some(vec![1., 2.]), cache.get(&first));
+        assert_eq!(None, cache.get(&second));
+        let third = cache.key("third", EncodeRole::Document);
+        cache.insert(third.clone(), vec![5., 6.]).unwrap();
+        drop(cache);
+
+        // records appended after the truncation are replayed
+        let mut cache = EmbeddingCache::open(&config, "model").unwrap();
+        assert_eq!(Some(vec![1., 2.]), cache.get(&first));
+        assert_eq!(Some(vec![5., 6.]), cache.get(&third));
+        assert_eq!(2, cache.stats().entries);
+        drop(cache);
+        clear_cache(&config.path).unwrap();
+    }
+}
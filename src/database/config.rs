@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use crate::database::cache::{CacheConfig, DEFAULT_CACHE_MAX_ENTRIES};
+use crate::database::hashing::DEFAULT_HASHING_DIMENSION;
+
+pub const DEFAULT_MODEL_PATH: &str = "models/bert-base-nli-stsb-mean-tokens";
+pub const DEFAULT_NPROBE: usize = 8;
+/// Seed of the randomized index builds, fixed so that two loads of the same data give the same index.
+pub const DEFAULT_SEED: u64 = 1;
+
+#[derive(Debug, Clone)]
+pub enum EmbedderConfig {
+    /// sentence transformer directory, as prepared in the README
+    Bert { model_path: String },
+    /// feature hashing into `dimension` buckets, needs no model files
+    Hashing { dimension: usize },
+}
+
+impl EmbedderConfig {
+    /// Identifies the model in embedding cache keys. The model path is used as is, so the
+    /// cache must be cleared when the weights at that path change.
+    pub fn model_id(&self) -> String {
+        match self {
+            EmbedderConfig::Bert { model_path } => format!("bert:{}", model_path),
+            EmbedderConfig::Hashing { dimension } => format!("hashing:{}", dimension),
+        }
+    }
+}
+
+/// Everything `database::new_with_config` needs to build a backend.
+///
+/// # Example
+///
+/// ```
+/// let config = DBConfig::new("Hnsw_Cosine").hashing(256);
+/// let mut db = thistle::database::new_with_config(&config)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct DBConfig {
+    /// backend name, as accepted by `database::new`
+    pub db_method: String,
+    pub embedder: EmbedderConfig,
+    /// on-disk embedding cache in front of the embedder, off by default
+    pub cache: Option<CacheConfig>,
+    /// `Hnsw_Sq8` only: file where full precision vectors are kept to re-score candidates
+    pub rescore_path: Option<PathBuf>,
+    /// `IVF_Flat` and `IVF_PQ` only: number of inverted lists scanned per query
+    pub nprobe: usize,
+    /// `IVF_Flat` and `IVF_PQ` only: seed of the training sample and of k-means, 0 seeds from the OS
+    pub seed: u64,
+}
+
+impl DBConfig {
+    /// Config for `db_method` with the default sentence transformer. Without the `bert`
+    /// feature, building it fails unless `hashing` picks the model-free embedder.
+    pub fn new(db_method: &str) -> DBConfig {
+        DBConfig {
+            db_method: db_method.to_string(),
+            embedder: EmbedderConfig::Bert {
+                model_path: DEFAULT_MODEL_PATH.to_string(),
+            },
+            cache: None,
+            rescore_path: None,
+            nprobe: DEFAULT_NPROBE,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    pub fn model_path(mut self, model_path: &str) -> DBConfig {
+        self.embedder = EmbedderConfig::Bert {
+            model_path: model_path.to_string(),
+        };
+        self
+    }
+
+    /// Use the model-free hashing embedder. `dimension == 0` picks `DEFAULT_HASHING_DIMENSION`.
+    pub fn hashing(mut self, dimension: usize) -> DBConfig {
+        let dimension = if dimension == 0 { DEFAULT_HASHING_DIMENSION } else { dimension };
+        self.embedder = EmbedderConfig::Hashing { dimension };
+        self
+    }
+
+    /// Caches embeddings in the file at `path`, keeping at most `max_entries` of them.
+    /// `max_entries == 0` picks `DEFAULT_CACHE_MAX_ENTRIES`.
+    pub fn cache(mut self, path: &str, max_entries: usize) -> DBConfig {
+        let max_entries = if max_entries == 0 { DEFAULT_CACHE_MAX_ENTRIES } else { max_entries };
+        self.cache = Some(CacheConfig {
+            path: PathBuf::from(path),
+            max_entries,
+        });
+        self
+    }
+
+    /// Re-scores the `Hnsw_Sq8` candidates with the full vectors, stored in the file at `path`.
+    pub fn rescore_from_disk(mut self, path: &str) -> DBConfig {
+        self.rescore_path = Some(PathBuf::from(path));
+        self
+    }
+
+    /// Number of inverted lists the `IVF_Flat` and `IVF_PQ` backends scan per query, more is slower and
+    /// more accurate.
+    pub fn nprobe(mut self, nprobe: usize) -> DBConfig {
+        self.nprobe = nprobe.max(1);
+        self
+    }
+
+    /// Seed of the `IVF_Flat` and `IVF_PQ` training, `DEFAULT_SEED` by default. With 0 every load
+    /// draws a different training sample and k-means initialization.
+    pub fn seed(mut self, seed: u64) -> DBConfig {
+        self.seed = seed;
+        self
+    }
+}
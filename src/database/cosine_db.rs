@@ -1,14 +1,17 @@
-use crate::database::embedding::get_embedding;
-use crate::database::db::{Operations, Doc};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::db::{check_dimensions, sort_by_score, Operations, Doc};
+use crate::database::error::Result;
 
 pub struct CosineDB {
     pub docs: Vec<Doc>,
+    pub embedder: Embedder,
 }
 
 impl Operations for CosineDB {
     fn load(&mut self, texts: Vec<String>) {
         for text in texts {
-            let vect = get_embedding(&text);
+            let vect = self.embedder.embed(&text, EncodeRole::Document);
             self.docs.push(Doc {
                 text: text,
                 embedding: vect,
@@ -17,9 +20,21 @@ impl Operations for CosineDB {
         }
     }
 
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        check_dimensions(&items, self.embedder.dimension())?;
+        for (text, vect) in items {
+            self.docs.push(Doc {
+                text: text,
+                embedding: vect,
+                score: 0.0,
+            });
+        }
+        Ok(())
+    }
+
     fn query(&self, query: String, n: u32) -> Vec<Doc> {
         let mut result = Vec::new();
-        let query_embedding = get_embedding(&query);
+        let query_embedding = self.embedder.embed(&query, EncodeRole::Query);
         for doc in self.docs.iter() {
             let score = cosine(&doc.embedding, &query_embedding);
             result.push(Doc {
@@ -29,29 +44,42 @@ impl Operations for CosineDB {
             });
         }
 
-        result.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        sort_by_score(&mut result);
+        result.truncate(n as usize);
+        result
+    }
 
-        result.drain(..n as usize).collect()
+    fn query_threshold(&self, query: String, min_similarity: f32) -> Result<Vec<Doc>> {
+        let query_embedding = self.embedder.embed(&query, EncodeRole::Query);
+        let mut result: Vec<Doc> = self
+            .docs
+            .iter()
+            .map(|doc| Doc {
+                text: doc.text.clone(),
+                embedding: doc.embedding.clone(),
+                score: cosine(&doc.embedding, &query_embedding),
+            })
+            .filter(|doc| doc.score >= min_similarity as f64)
+            .collect();
+        sort_by_score(&mut result);
+        Ok(result)
     }
 }
 
-fn cosine(vec1: &Vec<f64>, vec2: &Vec<f64>) -> f64 {
+fn cosine(vec1: &Vec<f32>, vec2: &Vec<f32>) -> f64 {
     let norms = norm(vec1) * norm(vec2);
     if norms > 0. {
-        let res = dot(vec1, vec2) / (norm(vec1) * norm(vec2));
-        // For dev purposes
-        println!("Cosine {}", res);
-        return res;
+        return dot(vec1, vec2) / norms;
     }
     return 0.;
 }
 
-fn dot(vec1: &Vec<f64>, vec2: &Vec<f64>) -> f64 {
+fn dot(vec1: &Vec<f32>, vec2: &Vec<f32>) -> f64 {
     vec1.iter()
         .zip(vec2.iter())
-        .fold(0.0, |sum, (&v1, &v2)| sum + (v1 * v2))
+        .fold(0.0, |sum, (&v1, &v2)| sum + (v1 as f64 * v2 as f64))
 }
 
-fn norm(a: &Vec<f64>) -> f64 {
+fn norm(a: &Vec<f32>) -> f64 {
     dot(a, a).sqrt()
 }
@@ -1,10 +1,19 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use uuid::Uuid;
 use crate::database::cosine_db::CosineDB;
 use crate::database::euclidean_db::EuclideanDB;
 use crate::database::hnsw_euclidean_db::HnswEuclideanDB;
 use crate::database::hnsw_cosine_db::HnswCosineDB;
+use crate::database::hnsw_sq8_db::HnswSq8DB;
+use crate::database::ivf_flat_db::IvfFlatDB;
+use crate::database::ivf_pq_db::IvfPqDB;
 use crate::database::lsh_db::LshDB;
+use crate::database::config::DBConfig;
+use crate::database::embedder::Embedder;
+use crate::database::error::{Error, Result};
 use crate::hnswlib::*;
+use crate::ivf::{IvfFlat, IvfPq};
 use crate::lsh::prelude::LshMem;
 
 pub fn database_module_uuid() -> String {
@@ -14,7 +23,7 @@ pub fn database_module_uuid() -> String {
 #[derive(Debug)]
 pub struct Doc {
     pub text: String,
-    pub embedding: Vec<f64>,
+    pub embedding: Vec<f32>,
     pub score: f64,
 }
 
@@ -23,23 +32,85 @@ pub enum DB {
     EuclideanDB(EuclideanDB),
     HnswEuclideanDB(HnswEuclideanDB),
     HnswCosineDB(HnswCosineDB),
+    HnswSq8DB(HnswSq8DB),
+    IvfFlatDB(IvfFlatDB),
+    IvfPqDB(IvfPqDB),
     LshDB(LshDB),
 }
 
-pub fn new(db_method: &str) -> DB {
-    match db_method {
-        "Cosine" => DB::CosineDB(CosineDB { docs: Vec::new() }),
-        "Euclidean" => DB::EuclideanDB(EuclideanDB { docs: Vec::new() }),
-        "Hnsw_Euclidean" => DB::HnswEuclideanDB(HnswEuclideanDB { docs: Vec::new(), hnsw: Hnsw::new(1, 1, 1, 1, DistL2 {}) }),
-        "Hnsw_Cosine" => DB::HnswCosineDB(HnswCosineDB { docs: Vec::new(), hnsw: Hnsw::new(1, 1, 1, 1, DistCosine {}) }),
-        "LSH" => DB::LshDB(LshDB{ docs: Vec::new(), lsh: LshMem::new(1, 1, 1) }),
-        _ => DB::CosineDB(CosineDB { docs: Vec::new() }),
+pub fn new(db_method: &str) -> Result<DB> {
+    new_with_config(&DBConfig::new(db_method))
+}
+
+/// Builds the backend named by `config.db_method`. Fails if the embedding model or the
+/// embedding cache cannot be loaded.
+pub fn new_with_config(config: &DBConfig) -> Result<DB> {
+    let embedder = match &config.cache {
+        Some(cache) => Embedder::cached(Embedder::new(&config.embedder)?, &config.embedder, cache)?,
+        None => Embedder::new(&config.embedder)?,
+    };
+    let db = match config.db_method.as_str() {
+        "Cosine" => DB::CosineDB(CosineDB { docs: Vec::new(), embedder: embedder }),
+        "Euclidean" => DB::EuclideanDB(EuclideanDB { docs: Vec::new(), embedder: embedder }),
+        "Hnsw_Euclidean" => DB::HnswEuclideanDB(HnswEuclideanDB { docs: Vec::new(), embedder: embedder, hnsw: Hnsw::new(1, 1, 1, 1, DistL2 {}) }),
+        "Hnsw_Cosine" => DB::HnswCosineDB(HnswCosineDB { docs: Vec::new(), embedder: embedder, hnsw: Hnsw::new(1, 1, 1, 1, DistDot {}) }),
+        "Hnsw_Sq8" => DB::HnswSq8DB(HnswSq8DB {
+            texts: Vec::new(),
+            embedder: embedder,
+            hnsw: Hnsw::new(1, 1, 1, 1, DistDotSq8 {}),
+            rescore_path: config.rescore_path.clone(),
+            vector_file: None,
+        }),
+        "IVF_Flat" => DB::IvfFlatDB(IvfFlatDB {
+            texts: HashMap::new(),
+            next_id: 0,
+            embedder: embedder,
+            index: IvfFlat::new(1, 1).nprobe(config.nprobe).seed(config.seed),
+        }),
+        "IVF_PQ" => DB::IvfPqDB(IvfPqDB {
+            texts: Vec::new(),
+            embedder: embedder,
+            index: IvfPq::new(1, 1, 1).nprobe(config.nprobe).seed(config.seed),
+        }),
+        "LSH" => DB::LshDB(LshDB{ docs: Vec::new(), embedder: embedder, lsh: LshMem::new(1, 1, 1) }),
+        _ => DB::CosineDB(CosineDB { docs: Vec::new(), embedder: embedder }),
+    };
+    Ok(db)
+}
+
+/// Checks that every imported embedding has the dimension of the embedder, which will
+/// encode the queries run against them.
+pub fn check_dimensions(items: &[(String, Vec<f32>)], expected: usize) -> Result<()> {
+    for (index, (_, embedding)) in items.iter().enumerate() {
+        if embedding.len() != expected {
+            return Err(Error::DimensionMismatch {
+                index,
+                found: embedding.len(),
+                expected,
+            });
+        }
     }
+    Ok(())
+}
+
+/// Highest score first, NaN scores last.
+pub fn sort_by_score(docs: &mut [Doc]) {
+    docs.sort_by(|a, b| match (a.score.is_nan(), b.score.is_nan()) {
+        (false, false) => b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    });
 }
 
 pub trait Operations {
     fn load(&mut self, texts: Vec<String>);
+    /// Loads texts with embeddings computed elsewhere, the embedder is not called.
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()>;
     fn query(&self, query: String, n: u32) -> Vec<Doc>;
+    /// Every document whose cosine similarity to `query` is at least `min_similarity`, most
+    /// similar first. The scores are those `query` returns.
+    fn query_threshold(&self, _query: String, _min_similarity: f32) -> Result<Vec<Doc>> {
+        Err(Error::Unsupported("query_threshold"))
+    }
 }
 
 impl Operations for DB {
@@ -50,17 +121,49 @@ impl Operations for DB {
             DB::EuclideanDB(db) => db.load(texts),
             DB::HnswEuclideanDB(db) => db.load(texts),
             DB::HnswCosineDB(db) => db.load(texts),
+            DB::HnswSq8DB(db) => db.load(texts),
+            DB::IvfFlatDB(db) => db.load(texts),
+            DB::IvfPqDB(db) => db.load(texts),
             DB::LshDB(db) => db.load(texts),
         }
     }
 
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        match self {
+            DB::CosineDB(db) => db.load_with_embeddings(items),
+            DB::EuclideanDB(db) => db.load_with_embeddings(items),
+            DB::HnswEuclideanDB(db) => db.load_with_embeddings(items),
+            DB::HnswCosineDB(db) => db.load_with_embeddings(items),
+            DB::HnswSq8DB(db) => db.load_with_embeddings(items),
+            DB::IvfFlatDB(db) => db.load_with_embeddings(items),
+            DB::IvfPqDB(db) => db.load_with_embeddings(items),
+            DB::LshDB(db) => db.load_with_embeddings(items),
+        }
+    }
+
     fn query(&self, query: String, n: u32) -> Vec<Doc> {
         match self {
             DB::CosineDB(db) => db.query(query, n),
             DB::EuclideanDB(db) => db.query(query, n),
             DB::HnswEuclideanDB(db) => db.query(query, n),
             DB::HnswCosineDB(db) => db.query(query, n),
+            DB::HnswSq8DB(db) => db.query(query, n),
+            DB::IvfFlatDB(db) => db.query(query, n),
+            DB::IvfPqDB(db) => db.query(query, n),
             DB::LshDB(db) => db.query(query, n),
         }
     }
+
+    fn query_threshold(&self, query: String, min_similarity: f32) -> Result<Vec<Doc>> {
+        match self {
+            DB::CosineDB(db) => db.query_threshold(query, min_similarity),
+            DB::EuclideanDB(db) => db.query_threshold(query, min_similarity),
+            DB::HnswEuclideanDB(db) => db.query_threshold(query, min_similarity),
+            DB::HnswCosineDB(db) => db.query_threshold(query, min_similarity),
+            DB::HnswSq8DB(db) => db.query_threshold(query, min_similarity),
+            DB::IvfFlatDB(db) => db.query_threshold(query, min_similarity),
+            DB::IvfPqDB(db) => db.query_threshold(query, min_similarity),
+            DB::LshDB(db) => db.query_threshold(query, min_similarity),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+#[cfg(feature = "bert")]
+use std::path::Path;
+#[cfg(feature = "bert")]
+use tch::Device;
+use parking_lot::Mutex;
+
+use crate::database::cache::{CacheConfig, CacheStats, EmbeddingCache};
+use crate::database::config::EmbedderConfig;
+use crate::database::error::{Error, Result};
+use crate::database::hashing::HashingEmbedder;
+pub use crate::role::EncodeRole;
+#[cfg(feature = "bert")]
+use crate::model::EncoderPool;
+
+/// Turns texts into vectors for the DB backends. Built once from an `EmbedderConfig`
+/// and owned by the backend.
+pub enum Embedder {
+    /// sentence transformer loaded once and shared through a pool
+    #[cfg(feature = "bert")]
+    Bert(EncoderPool),
+    /// model-free feature hashing, see `HashingEmbedder`
+    Hashing(HashingEmbedder),
+    /// any of the above behind an on-disk `EmbeddingCache`
+    Cached(Box<Embedder>, Mutex<EmbeddingCache>),
+}
+
+impl Embedder {
+    pub fn new(config: &EmbedderConfig) -> Result<Embedder> {
+        match config {
+            #[cfg(feature = "bert")]
+            EmbedderConfig::Bert { model_path } => {
+                // libtorch keeps its default of one intra-op thread per core: one worker
+                let pool = EncoderPool::new(Path::new(model_path), Device::Cpu, 0, num_cpus::get())
+                    .map_err(|error| Error::Model(format!("{}: {}", model_path, error)))?;
+                Ok(Embedder::Bert(pool))
+            }
+            #[cfg(not(feature = "bert"))]
+            EmbedderConfig::Bert { model_path } => Err(Error::Model(format!(
+                "{}: thistle was built without the `bert` feature, use `DBConfig::hashing`",
+                model_path
+            ))),
+            EmbedderConfig::Hashing { dimension } => Ok(Embedder::Hashing(HashingEmbedder::new(*dimension))),
+        }
+    }
+
+    /// Wraps `embedder`, built from `config`, with the cache described by `cache_config`.
+    pub fn cached(embedder: Embedder, config: &EmbedderConfig, cache_config: &CacheConfig) -> Result<Embedder> {
+        let cache = EmbeddingCache::open(cache_config, &config.model_id())?;
+        Ok(Embedder::Cached(Box::new(embedder), Mutex::new(cache)))
+    }
+
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        match self {
+            Embedder::Cached(_, cache) => Some(cache.lock().stats()),
+            _ => None,
+        }
+    }
+
+    pub fn embed(&self, text: &str, role: EncodeRole) -> Vec<f32> {
+        match self {
+            #[cfg(feature = "bert")]
+            Embedder::Bert(pool) => pool.encode_with_role(text, role),
+            // lexical hashing has no notion of query or document
+            Embedder::Hashing(hashing) => hashing.embed(text),
+            Embedder::Cached(embedder, cache) => {
+                let key = cache.lock().key(text, role);
+                if let Some(embedding) = cache.lock().get(&key) {
+                    return embedding;
+                }
+                // the lock is not held while encoding, other threads can use the cache
+                let embedding = embedder.embed(text, role);
+                if let Err(error) = cache.lock().insert(key, embedding.clone()) {
+                    log::warn!("could not write to the embedding cache: {}", error);
+                }
+                embedding
+            }
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        match self {
+            #[cfg(feature = "bert")]
+            Embedder::Bert(pool) => pool.get_output_dimension() as usize,
+            Embedder::Hashing(hashing) => hashing.dimension,
+            Embedder::Cached(embedder, _) => embedder.dimension(),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("embedding {index} has dimension {found}, the index expects {expected}")]
+    DimensionMismatch {
+        index: usize,
+        found: usize,
+        expected: usize,
+    },
+    #[error("{0} is not supported by this backend")]
+    Unsupported(&'static str),
+    #[error("could not load the embedding model: {0}")]
+    Model(String),
+    #[error("Import failed: {0}")]
+    Import(String),
+    #[error(transparent)]
+    Ivf(#[from] crate::ivf::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Polars(#[from] polars::prelude::PolarsError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
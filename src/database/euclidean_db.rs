@@ -1,14 +1,17 @@
-use crate::database::embedding::get_embedding;
-use crate::database::db::{Operations, Doc};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::db::{check_dimensions, Operations, Doc};
+use crate::database::error::Result;
 
 pub struct EuclideanDB {
     pub docs: Vec<Doc>,
+    pub embedder: Embedder,
 }
 
 impl Operations for EuclideanDB {
     fn load(&mut self, texts: Vec<String>) {
         for text in texts {
-            let vect = get_embedding(&text);
+            let vect = self.embedder.embed(&text, EncodeRole::Document);
             self.docs.push(Doc {
                 text: text,
                 embedding: vect,
@@ -17,9 +20,21 @@ impl Operations for EuclideanDB {
         }
     }
 
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        check_dimensions(&items, self.embedder.dimension())?;
+        for (text, vect) in items {
+            self.docs.push(Doc {
+                text: text,
+                embedding: vect,
+                score: 0.0,
+            });
+        }
+        Ok(())
+    }
+
     fn query(&self, query: String, n: u32) -> Vec<Doc> {
         let mut result = Vec::new();
-        let query_embedding = get_embedding(&query);
+        let query_embedding = self.embedder.embed(&query, EncodeRole::Query);
         for doc in self.docs.iter() {
             let score = euclidean(&doc.embedding, &query_embedding);
             result.push(Doc {
@@ -35,10 +50,10 @@ impl Operations for EuclideanDB {
     }
 }
 
-fn euclidean(v1: &Vec<f64>, v2: &Vec<f64>) -> f64 {
+fn euclidean(v1: &Vec<f32>, v2: &Vec<f32>) -> f64 {
     let res = v1.iter()
                 .zip(v2.iter())
-                .map(|(x, y)| (x - y).powi(2))
+                .map(|(x, y)| (*x as f64 - *y as f64).powi(2))
                 .sum::<f64>()
                 .sqrt();
     // For dev purposes
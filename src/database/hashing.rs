@@ -0,0 +1,88 @@
+use std::hash::Hasher;
+use fnv::FnvHasher;
+
+pub const DEFAULT_HASHING_DIMENSION: usize = 256;
+
+/// A model-free embedder: words and character n-grams of words are hashed into a fixed
+/// number of buckets (the "hashing trick"), with a hash-derived sign to limit collisions bias,
+/// then the vector is L2 normalized.
+///
+/// It only captures lexical overlap, but it is deterministic, needs no files and is fast,
+/// which makes it suited to tests and benchmarks of the index layers.
+#[derive(Debug, Clone)]
+pub struct HashingEmbedder {
+    pub dimension: usize,
+    pub ngram_min: usize,
+    pub ngram_max: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimension: usize) -> HashingEmbedder {
+        HashingEmbedder {
+            dimension: dimension.max(1),
+            ngram_min: 3,
+            ngram_max: 5,
+        }
+    }
+
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.; self.dimension];
+        let lowercase = text.to_lowercase();
+        for word in lowercase.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            self.add_feature(&mut embedding, "w", word);
+            let chars: Vec<char> = format!("<{}>", word).chars().collect();
+            for n in self.ngram_min..=self.ngram_max {
+                if n > chars.len() {
+                    break;
+                }
+                for ngram in chars.windows(n) {
+                    let ngram: String = ngram.iter().collect();
+                    self.add_feature(&mut embedding, "c", &ngram);
+                }
+            }
+        }
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0. {
+            embedding.iter_mut().for_each(|x| *x /= norm);
+        }
+        embedding
+    }
+
+    fn add_feature(&self, embedding: &mut Vec<f32>, kind: &str, feature: &str) {
+        let mut hasher = FnvHasher::default();
+        hasher.write(kind.as_bytes());
+        hasher.write(feature.as_bytes());
+        let hash = hasher.finish();
+        let sign = if hash >> 63 == 1 { -1. } else { 1. };
+        embedding[(hash % self.dimension as u64) as usize] += sign;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn test_hashing_embedder_deterministic() {
+        let embedder = HashingEmbedder::new(64);
+        let a = embedder.embed("Do not go gentle into that good night");
+        let b = HashingEmbedder::new(64).embed("Do not go gentle into that good night");
+        assert_eq!(a, b);
+        assert_eq!(64, a.len());
+        assert!((cosine(&a, &a) - 1.).abs() < 1e-6);
+        assert!(embedder.embed("").iter().all(|x| *x == 0.));
+    }
+
+    #[test]
+    fn test_hashing_embedder_lexical_similarity() {
+        let embedder = HashingEmbedder::new(DEFAULT_HASHING_DIMENSION);
+        let query = embedder.embed("Don't go into the night");
+        let close = embedder.embed("Do not go gentle into that good night");
+        let far = embedder.embed("Shall I compare thee to a summer's day");
+        assert!(cosine(&query, &close) > cosine(&query, &far));
+    }
+}
@@ -1,36 +1,56 @@
-use crate::database::embedding::get_embedding;
-use crate::database::db::{Operations, Doc};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::db::{check_dimensions, Operations, Doc};
+use crate::database::error::Result;
 use crate::hnswlib::*;
 
+/// Cosine distance computed as `DistDot` (and its f32 SIMD kernels) on vectors that are L2
+/// normalized before insertion and before search.
 pub struct HnswCosineDB {
     pub docs: Vec<Doc>,
-    pub hnsw: Hnsw<f64, DistCosine>,
+    pub embedder: Embedder,
+    pub hnsw: Hnsw<f32, DistDot>,
 }
 
 impl Operations for HnswCosineDB {
     fn load(&mut self, texts: Vec<String>) {
-        let nb_elem = texts.len();
+        let items = texts
+            .into_iter()
+            .map(|text| {
+                let vect = self.embedder.embed(&text, EncodeRole::Document);
+                (text, vect)
+            })
+            .collect();
+        self.load_with_embeddings(items).unwrap();
+    }
+
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        check_dimensions(&items, self.embedder.dimension())?;
+        let nb_elem = items.len();
         let mut data = Vec::new();
-        for text in texts {
-            let vect = get_embedding(&text);
+        for (text, vect) in items {
             self.docs.push(Doc {
                 text: text,
                 embedding: vect.clone(),
                 score: 0.0,
             });
+            let mut vect = vect;
+            l2_normalize(&mut vect);
             data.push(vect);
         }
         let data_with_id: Vec<_> = data.iter().zip(0..nb_elem).collect();
         let ef_c = 200;
         let max_nb_connection = 15;
         let nb_layer = 16.min((nb_elem as f32).ln().trunc() as usize);
-        let hnsw = Hnsw::<f64, DistCosine>::new(max_nb_connection, nb_elem, nb_layer, ef_c, DistCosine {});
+        let hnsw = Hnsw::<f32, DistDot>::new(max_nb_connection, nb_elem, nb_layer, ef_c, DistDot {});
         hnsw.parallel_insert(&data_with_id);
         self.hnsw = hnsw;
+        Ok(())
     }
 
     fn query(&self, query: String, n: u32) -> Vec<Doc> {
-        let query_embedding = get_embedding(&query);
+        let mut query_embedding = self.embedder.embed(&query, EncodeRole::Query);
+        l2_normalize(&mut query_embedding);
         let max_nb_connection = 15;
         let ef_arg = max_nb_connection * 2;
         let neighbors = self.hnsw.search(&query_embedding, n as usize, ef_arg);
@@ -45,4 +65,21 @@ impl Operations for HnswCosineDB {
         }
         res
     }
+
+    /// Range search in the graph: `DistDot` is `1 - cos` on normalized vectors, so the radius
+    /// is `1 - min_similarity`.
+    fn query_threshold(&self, query: String, min_similarity: f32) -> Result<Vec<Doc>> {
+        let mut query_embedding = self.embedder.embed(&query, EncodeRole::Query);
+        l2_normalize(&mut query_embedding);
+        let max_nb_connection = 15;
+        let neighbors = self.hnsw.search_range(&query_embedding, 1. - min_similarity, max_nb_connection * 2);
+        Ok(neighbors
+            .into_iter()
+            .map(|neighbor| Doc {
+                text: self.docs[neighbor.d_id].text.clone(),
+                embedding: self.docs[neighbor.d_id].embedding.clone(),
+                score: neighbor.distance as f64,
+            })
+            .collect())
+    }
 }
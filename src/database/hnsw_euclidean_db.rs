@@ -1,18 +1,32 @@
-use crate::database::embedding::get_embedding;
-use crate::database::db::{Operations, Doc};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::db::{check_dimensions, Operations, Doc};
+use crate::database::error::Result;
 use crate::hnswlib::*;
 
 pub struct HnswEuclideanDB {
     pub docs: Vec<Doc>,
-    pub hnsw: Hnsw<f64, DistL2>,
+    pub embedder: Embedder,
+    pub hnsw: Hnsw<f32, DistL2>,
 }
 
 impl Operations for HnswEuclideanDB {
     fn load(&mut self, texts: Vec<String>) {
-        let nb_elem = texts.len();
+        let items = texts
+            .into_iter()
+            .map(|text| {
+                let vect = self.embedder.embed(&text, EncodeRole::Document);
+                (text, vect)
+            })
+            .collect();
+        self.load_with_embeddings(items).unwrap();
+    }
+
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        check_dimensions(&items, self.embedder.dimension())?;
+        let nb_elem = items.len();
         let mut data = Vec::new();
-        for text in texts {
-            let vect = get_embedding(&text);
+        for (text, vect) in items {
             self.docs.push(Doc {
                 text: text,
                 embedding: vect.clone(),
@@ -24,13 +38,14 @@ impl Operations for HnswEuclideanDB {
         let ef_c = 200;
         let max_nb_connection = 15;
         let nb_layer = 16.min((nb_elem as f32).ln().trunc() as usize);
-        let hnsw = Hnsw::<f64, DistL2>::new(max_nb_connection, nb_elem, nb_layer, ef_c, DistL2 {});
+        let hnsw = Hnsw::<f32, DistL2>::new(max_nb_connection, nb_elem, nb_layer, ef_c, DistL2 {});
         hnsw.parallel_insert(&data_with_id);
         self.hnsw = hnsw;
+        Ok(())
     }
 
     fn query(&self, query: String, n: u32) -> Vec<Doc> {
-        let query_embedding = get_embedding(&query);
+        let query_embedding = self.embedder.embed(&query, EncodeRole::Query);
         let max_nb_connection = 15;
         let ef_arg = max_nb_connection * 2;
         let neighbors = self.hnsw.search(&query_embedding, n as usize, ef_arg);
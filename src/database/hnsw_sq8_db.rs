@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+
+use crate::database::db::{check_dimensions, Doc, Operations};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::error::Result;
+use crate::database::vector_file::VectorFile;
+use crate::hnswlib::*;
+
+/// Candidates fetched from the graph per requested result when re-scoring.
+pub const RESCORE_FACTOR: usize = 4;
+
+/// Cosine HNSW over int8 scalar quantized vectors (`sq8_encode`), about 4 times less memory
+/// than `HnswCosineDB` for the graph points. The full vectors are not kept in memory: the
+/// returned `Doc`s have an empty `embedding`, unless `rescore_path` is set. In that case the
+/// normalized f32 vectors are written to that file at load time, `RESCORE_FACTOR * n`
+/// candidates are read back from it on each query and ranked by their exact distance.
+pub struct HnswSq8DB {
+    pub texts: Vec<String>,
+    pub embedder: Embedder,
+    pub hnsw: Hnsw<u8, DistDotSq8>,
+    pub rescore_path: Option<PathBuf>,
+    pub vector_file: Option<VectorFile>,
+}
+
+impl Operations for HnswSq8DB {
+    fn load(&mut self, texts: Vec<String>) {
+        let items = texts
+            .into_iter()
+            .map(|text| {
+                let vect = self.embedder.embed(&text, EncodeRole::Document);
+                (text, vect)
+            })
+            .collect();
+        self.load_with_embeddings(items).unwrap();
+    }
+
+    /// Replaces the content of the index with `items`.
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        let dimension = self.embedder.dimension();
+        check_dimensions(&items, dimension)?;
+        let nb_elem = items.len();
+        let mut vectors = Vec::with_capacity(nb_elem);
+        self.texts = Vec::with_capacity(nb_elem);
+        for (text, mut vect) in items {
+            l2_normalize(&mut vect);
+            self.texts.push(text);
+            vectors.push(vect);
+        }
+
+        let data: Vec<Vec<u8>> = vectors.iter().map(|vect| sq8_encode(vect)).collect();
+        let data_with_id: Vec<_> = data.iter().zip(0..nb_elem).collect();
+        let ef_c = 200;
+        let max_nb_connection = 15;
+        let nb_layer = 16.min((nb_elem as f32).ln().trunc() as usize);
+        let hnsw = Hnsw::<u8, DistDotSq8>::new(max_nb_connection, nb_elem, nb_layer, ef_c, DistDotSq8 {});
+        hnsw.parallel_insert(&data_with_id);
+        self.hnsw = hnsw;
+
+        self.vector_file = match &self.rescore_path {
+            Some(path) => Some(VectorFile::create(path, dimension, &vectors)?),
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn query(&self, query: String, n: u32) -> Vec<Doc> {
+        let mut query_embedding = self.embedder.embed(&query, EncodeRole::Query);
+        l2_normalize(&mut query_embedding);
+        let nb_candidates = match self.vector_file {
+            Some(_) => n as usize * RESCORE_FACTOR,
+            None => n as usize,
+        };
+        let max_nb_connection = 15;
+        let ef_arg = (max_nb_connection * 2).max(nb_candidates);
+        let neighbors = self.hnsw.search(&sq8_query(&query_embedding), nb_candidates, ef_arg);
+
+        let mut res = Vec::new();
+        for neighbor in neighbors {
+            let (embedding, score) = match &self.vector_file {
+                Some(vector_file) => {
+                    let embedding = vector_file.get(neighbor.d_id).unwrap();
+                    let score = DistDot.eval(&query_embedding, &embedding) as f64;
+                    (embedding, score)
+                }
+                None => (Vec::new(), neighbor.distance as f64),
+            };
+            res.push(Doc {
+                text: self.texts[neighbor.d_id].clone(),
+                embedding,
+                score,
+            })
+        }
+        res.sort_by(|a, b| a.score.partial_cmp(&b.score).unwrap());
+        res.truncate(n as usize);
+        res
+    }
+}
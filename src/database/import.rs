@@ -0,0 +1,177 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use polars::prelude::*;
+
+use crate::database::error::{Error, Result};
+
+/// Reads a 2-d `.npy` matrix (`<f4` or `<f8`, C order) and a text file with one line per row.
+pub fn read_npy_with_texts(npy_path: &Path, texts_path: &Path) -> Result<Vec<(String, Vec<f32>)>> {
+    let embeddings = read_npy_matrix(npy_path)?;
+    let texts: Vec<String> = BufReader::new(File::open(texts_path)?)
+        .lines()
+        .collect::<std::io::Result<Vec<String>>>()?;
+    if texts.len() != embeddings.len() {
+        return Err(Error::Import(format!(
+            "{:?} has {} rows but {:?} has {} lines",
+            npy_path,
+            embeddings.len(),
+            texts_path,
+            texts.len()
+        )));
+    }
+    Ok(texts.into_iter().zip(embeddings.into_iter()).collect())
+}
+
+/// Reads the rows of a 2-d `.npy` file, format versions 1 to 3.
+pub fn read_npy_matrix(path: &Path) -> Result<Vec<Vec<f32>>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    if data.len() < 10 || &data[..6] != b"\x93NUMPY" {
+        return Err(Error::Import(format!("{:?} is not a npy file", path)));
+    }
+    let (header_start, header_length) = match data[6] {
+        1 => (10, u16::from_le_bytes([data[8], data[9]]) as usize),
+        _ if data.len() >= 12 => (12, u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize),
+        _ => return Err(Error::Import(format!("{:?} has a truncated header", path))),
+    };
+    if data.len() < header_start + header_length {
+        return Err(Error::Import(format!("{:?} has a truncated header", path)));
+    }
+    let header = String::from_utf8_lossy(&data[header_start..header_start + header_length]).to_string();
+
+    if header.contains("'fortran_order': True") {
+        return Err(Error::Import(format!("{:?} is in fortran order", path)));
+    }
+    let element_size = if header.contains("'descr': '<f8'") {
+        8
+    } else if header.contains("'descr': '<f4'") {
+        4
+    } else {
+        return Err(Error::Import(format!("{:?} is not a little endian float32/float64 matrix", path)));
+    };
+    let shape = header
+        .find("'shape': (")
+        .map(|start| &header[start + 10..])
+        .and_then(|shape| shape.find(')').map(|end| &shape[..end]));
+    let shape: Vec<usize> = match shape {
+        Some(shape) => shape
+            .split(',')
+            .map(|dim| dim.trim())
+            .filter(|dim| !dim.is_empty())
+            .map(|dim| dim.parse::<usize>())
+            .collect::<std::result::Result<Vec<usize>, _>>()
+            .map_err(|error| Error::Import(format!("{:?} has a bad shape: {}", path, error)))?,
+        _ => return Err(Error::Import(format!("{:?} has no shape", path))),
+    };
+    if shape.len() != 2 {
+        return Err(Error::Import(format!("{:?} has shape {:?}, expected a matrix", path, shape)));
+    }
+    let (nb_rows, dimension) = (shape[0], shape[1]);
+
+    let body = &data[header_start + header_length..];
+    if body.len() < nb_rows * dimension * element_size {
+        return Err(Error::Import(format!("{:?} is truncated", path)));
+    }
+    let values: Vec<f32> = body[..nb_rows * dimension * element_size]
+        .chunks_exact(element_size)
+        .map(|bytes| match element_size {
+            4 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            _ => {
+                let mut array = [0u8; 8];
+                array.copy_from_slice(bytes);
+                f64::from_le_bytes(array) as f32
+            }
+        })
+        .collect();
+    Ok(values.chunks(dimension.max(1)).take(nb_rows).map(|row| row.to_vec()).collect())
+}
+
+/// Reads one JSON object per line, e.g. `{"text": "...", "embedding": [0.1, ...]}`.
+pub fn read_jsonl(path: &Path, text_field: &str, vector_field: &str) -> Result<Vec<(String, Vec<f32>)>> {
+    let content = fs::read_to_string(path)?;
+    let mut items = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: serde_json::Value = serde_json::from_str(line)?;
+        let text = value[text_field].as_str();
+        let vector = value[vector_field].as_array().map(|values| {
+            values.iter().map(|value| value.as_f64().map(|v| v as f32)).collect::<Option<Vec<f32>>>()
+        });
+        match (text, vector) {
+            (Some(text), Some(Some(vector))) => items.push((text.to_string(), vector)),
+            _ => {
+                return Err(Error::Import(format!(
+                    "{:?} line {}: expected a string {:?} and a number array {:?}",
+                    path,
+                    line_number + 1,
+                    text_field,
+                    vector_field
+                )))
+            }
+        }
+    }
+    Ok(items)
+}
+
+/// Reads a Parquet file with a utf8 text column and a list of float32/float64 vector column.
+pub fn read_parquet(path: &Path, text_column: &str, vector_column: &str) -> Result<Vec<(String, Vec<f32>)>> {
+    let data = ParquetReader::new(File::open(path)?).finish()?;
+    let texts = data.column(text_column)?.utf8()?;
+    let vectors = data.column(vector_column)?.list()?;
+
+    let mut items = Vec::with_capacity(data.height());
+    for (row, (text, vector)) in texts.into_iter().zip(vectors.into_iter()).enumerate() {
+        let (text, vector) = match (text, vector) {
+            (Some(text), Some(vector)) => (text, vector),
+            _ => return Err(Error::Import(format!("{:?} row {} has a null value", path, row))),
+        };
+        let values: Vec<Option<f32>> = match vector.dtype() {
+            DataType::Float64 => vector.f64()?.into_iter().map(|value| value.map(|v| v as f32)).collect(),
+            DataType::Float32 => vector.f32()?.into_iter().collect(),
+            dtype => {
+                return Err(Error::Import(format!(
+                    "{:?} column {:?} holds {:?}, expected floats",
+                    path, vector_column, dtype
+                )))
+            }
+        };
+        match values.into_iter().collect::<Option<Vec<f32>>>() {
+            Some(values) => items.push((text.to_string(), values)),
+            None => return Err(Error::Import(format!("{:?} row {} has a null value", path, row))),
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_read_npy_matrix() {
+        let header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }";
+        let mut header = header.to_string();
+        // the header is padded so that the data starts on a 64 bytes boundary
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut data = b"\x93NUMPY\x01\x00".to_vec();
+        data.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        data.extend_from_slice(header.as_bytes());
+        for value in [1f32, 2., 3., 4., 5., 6.].iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let path = std::env::temp_dir().join(format!("thistle-import-{}.npy", std::process::id()));
+        File::create(&path).unwrap().write_all(&data).unwrap();
+        let matrix = read_npy_matrix(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(vec![vec![1., 2., 3.], vec![4., 5., 6.]], matrix);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use ndarray::{Array2, Axis};
+use rand::seq::index::sample;
+
+use crate::database::db::{check_dimensions, Doc, Operations};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::error::Result;
+use crate::database::ivf_pq_db::TRAINING_SAMPLE_SIZE;
+use crate::hnswlib::l2_normalize;
+use crate::ivf::IvfFlat;
+use crate::lsh::utils::create_rng;
+
+/// Cosine search over an IVF-Flat index (`ivf::IvfFlat`): about `sqrt(n)` k-means lists,
+/// scanned exactly. Unlike the HNSW backends, documents can be inserted and deleted after
+/// `load` without rebuilding anything; call `retrain` once many of them have changed.
+/// The score is `1 - cos`.
+pub struct IvfFlatDB {
+    /// doc id -> text
+    pub texts: HashMap<usize, String>,
+    pub next_id: usize,
+    pub embedder: Embedder,
+    pub index: IvfFlat,
+}
+
+impl IvfFlatDB {
+    /// Embeds and adds `text`, returns its doc id.
+    pub fn insert(&mut self, text: String) -> Result<usize> {
+        let vect = self.embedder.embed(&text, EncodeRole::Document);
+        self.insert_with_embedding(text, vect)
+    }
+
+    /// Adds `text` with an embedding computed elsewhere, returns its doc id. The first
+    /// document inserted in an empty database trains a single list.
+    pub fn insert_with_embedding(&mut self, text: String, mut vect: Vec<f32>) -> Result<usize> {
+        let dimension = self.embedder.dimension();
+        check_dimensions(&[(String::new(), vect.clone())], dimension)?;
+        l2_normalize(&mut vect);
+        if !self.index.is_trained() {
+            let mut index = IvfFlat::new(dimension, 1)
+                .nprobe(self.index.nprobe)
+                .seed(self.index.get_seed());
+            index.train(Array2::from_shape_vec((1, dimension), vect.clone()).unwrap().view())?;
+            self.index = index;
+        }
+        let id = self.next_id;
+        self.index.insert(id, &vect)?;
+        self.texts.insert(id, text);
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// Removes a document. Returns false if there is no document `id`.
+    pub fn delete(&mut self, id: usize) -> bool {
+        self.texts.remove(&id);
+        self.index.delete(id)
+    }
+
+    /// Recomputes the lists from the documents currently stored.
+    pub fn retrain(&mut self) -> Result<()> {
+        let nlist = (self.index.len() as f32).sqrt().round() as usize;
+        self.index.retrain(nlist, TRAINING_SAMPLE_SIZE)?;
+        Ok(())
+    }
+}
+
+impl Operations for IvfFlatDB {
+    fn load(&mut self, texts: Vec<String>) {
+        let items = texts
+            .into_iter()
+            .map(|text| {
+                let vect = self.embedder.embed(&text, EncodeRole::Document);
+                (text, vect)
+            })
+            .collect();
+        self.load_with_embeddings(items).unwrap();
+    }
+
+    /// Replaces the content of the index with `items`, doc ids start at 0.
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        let dimension = self.embedder.dimension();
+        check_dimensions(&items, dimension)?;
+        let nb_elem = items.len();
+        let mut data = Vec::with_capacity(nb_elem * dimension);
+        self.texts = HashMap::with_capacity(nb_elem);
+        for (id, (text, mut vect)) in items.into_iter().enumerate() {
+            l2_normalize(&mut vect);
+            self.texts.insert(id, text);
+            data.extend_from_slice(&vect);
+        }
+        self.next_id = nb_elem;
+        let mut index = IvfFlat::new(dimension, 1)
+            .nprobe(self.index.nprobe)
+            .seed(self.index.get_seed());
+        if nb_elem == 0 {
+            self.index = index;
+            return Ok(());
+        }
+
+        let data = Array2::from_shape_vec((nb_elem, dimension), data).unwrap();
+        let mut rng = create_rng(self.index.get_seed());
+        let training = data.select(
+            Axis(0),
+            &sample(&mut rng, nb_elem, TRAINING_SAMPLE_SIZE.min(nb_elem)).into_vec(),
+        );
+        index.nlist = (nb_elem as f32).sqrt().round().max(1.) as usize;
+        index.train(training.view())?;
+        for (id, vect) in data.outer_iter().enumerate() {
+            index.insert(id, vect.as_slice().unwrap())?;
+        }
+        self.index = index;
+        Ok(())
+    }
+
+    fn query(&self, query: String, n: u32) -> Vec<Doc> {
+        if self.index.is_empty() {
+            return Vec::new();
+        }
+        let mut query_embedding = self.embedder.embed(&query, EncodeRole::Query);
+        l2_normalize(&mut query_embedding);
+        let neighbors = self.index.search(&query_embedding, n as usize).unwrap();
+        neighbors
+            .into_iter()
+            .map(|neighbor| Doc {
+                text: self.texts[&neighbor.id].clone(),
+                embedding: self.index.get(neighbor.id).unwrap().to_vec(),
+                // |a - b|^2 = 2 - 2 cos for unit vectors
+                score: neighbor.distance as f64 / 2.,
+            })
+            .collect()
+    }
+}
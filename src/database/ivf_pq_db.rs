@@ -0,0 +1,86 @@
+use crate::database::db::{check_dimensions, Doc, Operations};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::error::Result;
+use crate::hnswlib::l2_normalize;
+use crate::ivf::IvfPq;
+
+/// Embeddings the quantizers are trained on, sampled from the loaded ones.
+pub const TRAINING_SAMPLE_SIZE: usize = 10_000;
+/// Dimensions per PQ sub-vector aimed for, i.e. one byte per `DIMENSIONS_PER_CODE` floats.
+pub const DIMENSIONS_PER_CODE: usize = 8;
+
+/// Number of subquantizers: `dim / dsub` for the smallest `dsub >= DIMENSIONS_PER_CODE`
+/// dividing `dim`.
+pub fn pick_n_subquantizers(dim: usize) -> usize {
+    (DIMENSIONS_PER_CODE.min(dim.max(1))..=dim.max(1))
+        .find(|dsub| dim % dsub == 0)
+        .map(|dsub| dim / dsub)
+        .unwrap_or(1)
+}
+
+/// Cosine search over an IVF-PQ index (`ivf::IvfPq`), trained at load time on a sample of the
+/// normalized embeddings: about `sqrt(n)` inverted lists and one byte per 8 dimensions. Like
+/// `HnswSq8DB`, the full vectors are not kept, the returned `Doc`s have an empty `embedding`.
+/// The score is `1 - cos`, approximated from the codes.
+pub struct IvfPqDB {
+    pub texts: Vec<String>,
+    pub embedder: Embedder,
+    pub index: IvfPq,
+}
+
+impl Operations for IvfPqDB {
+    fn load(&mut self, texts: Vec<String>) {
+        let items = texts
+            .into_iter()
+            .map(|text| {
+                let vect = self.embedder.embed(&text, EncodeRole::Document);
+                (text, vect)
+            })
+            .collect();
+        self.load_with_embeddings(items).unwrap();
+    }
+
+    /// Replaces the content of the index with `items`.
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        let dimension = self.embedder.dimension();
+        check_dimensions(&items, dimension)?;
+        let mut vectors = Vec::with_capacity(items.len());
+        self.texts = Vec::with_capacity(items.len());
+        for (text, mut vect) in items {
+            l2_normalize(&mut vect);
+            self.texts.push(text);
+            vectors.push(vect);
+        }
+        if vectors.is_empty() {
+            return Ok(());
+        }
+
+        let nlist = (vectors.len() as f32).sqrt().round() as usize;
+        let mut index = IvfPq::new(dimension, nlist, pick_n_subquantizers(dimension))
+            .nprobe(self.index.nprobe)
+            .seed(self.index.get_seed());
+        index.train(&vectors, TRAINING_SAMPLE_SIZE)?;
+        index.add_batch(&vectors)?;
+        self.index = index;
+        Ok(())
+    }
+
+    fn query(&self, query: String, n: u32) -> Vec<Doc> {
+        if !self.index.is_trained() {
+            return Vec::new();
+        }
+        let mut query_embedding = self.embedder.embed(&query, EncodeRole::Query);
+        l2_normalize(&mut query_embedding);
+        let neighbors = self.index.search(&query_embedding, n as usize).unwrap();
+        neighbors
+            .into_iter()
+            .map(|neighbor| Doc {
+                text: self.texts[neighbor.id].clone(),
+                embedding: Vec::new(),
+                // |a - b|^2 = 2 - 2 cos for unit vectors
+                score: neighbor.distance as f64 / 2.,
+            })
+            .collect()
+    }
+}
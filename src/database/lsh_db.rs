@@ -1,20 +1,35 @@
 use crate::lsh::prelude::LshMem;
-use crate::database::embedding::{get_embedding, EMBEDDING_SIZE};
-use crate::database::db::{Operations, Doc};
+use crate::database::embedder::Embedder;
+use crate::role::EncodeRole;
+use crate::database::db::{check_dimensions, Operations, Doc};
+use crate::database::error::Result;
 
 pub struct LshDB {
     pub docs: Vec<Doc>,
-    pub lsh: crate::lsh::lsh::LSH<crate::lsh::hash::SignRandomProjections<f64>,
-                                    f64,
-                                    crate::lsh::table::mem::MemoryTable<f64, i8>>,
+    pub embedder: Embedder,
+    pub lsh: crate::lsh::lsh::LSH<crate::lsh::hash::SignRandomProjections<f32>,
+                                    f32,
+                                    crate::lsh::table::mem::MemoryTable<f32, i8>>,
 }
 
 impl Operations for LshDB {
-    #[allow(unused)]
     fn load(&mut self, texts: Vec<String>) {
+        let items = texts
+            .into_iter()
+            .map(|text| {
+                let vect = self.embedder.embed(&text, EncodeRole::Document);
+                (text, vect)
+            })
+            .collect();
+        self.load_with_embeddings(items).unwrap();
+    }
+
+    #[allow(unused)]
+    fn load_with_embeddings(&mut self, items: Vec<(String, Vec<f32>)>) -> Result<()> {
+        let dim = self.embedder.dimension();
+        check_dimensions(&items, dim)?;
         let mut vecs = Vec::new();
-        for text in texts {
-            let vect = get_embedding(&text);
+        for (text, vect) in items {
             self.docs.push(Doc {
                 text: text,
                 embedding: vect.clone(),
@@ -24,17 +39,17 @@ impl Operations for LshDB {
         }
         let n_projections = 9;
         let n_hash_tables = 30;
-        let dim = EMBEDDING_SIZE;
         let mut lsh = LshMem::new(n_projections, n_hash_tables, dim as usize)
         .srp()
         .unwrap();
         lsh.store_vecs(&vecs);
         self.lsh = lsh;
         println!("loaded");
+        Ok(())
     }
 
     fn query(&self, query: String, n: u32) -> Vec<Doc> {
-        let query_embedding = get_embedding(&query);
+        let query_embedding = self.embedder.embed(&query, EncodeRole::Query);
         let mut matching = self.lsh.query_bucket_ids(&query_embedding).unwrap();
         let mut output = Vec::new();
         if matching.len() == 0 {
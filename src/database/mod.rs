@@ -1,9 +1,23 @@
 pub mod db;
-pub mod embedding;
+pub mod cache;
+pub mod config;
+pub mod embedder;
+pub mod error;
+pub mod hashing;
+pub mod import;
 pub mod cosine_db;
 pub mod euclidean_db;
 pub mod hnsw_euclidean_db;
 pub mod hnsw_cosine_db;
+pub mod hnsw_sq8_db;
+pub mod ivf_flat_db;
+pub mod ivf_pq_db;
 pub mod lsh_db;
+pub mod vector_file;
+#[cfg(feature = "bert")]
+pub mod rerank;
 
-pub use db::{Operations, new};
\ No newline at end of file
+pub use db::{Operations, new, new_with_config};
+pub use config::DBConfig;
+#[cfg(feature = "bert")]
+pub use rerank::{get_cross_encoder, rerank_with};
@@ -0,0 +1,47 @@
+use std::path::Path;
+use tch::Device;
+use crate::database::db::{sort_by_score, Doc};
+use crate::model::CrossEncoder;
+
+pub const CROSS_ENCODER_PATH: &str = "models/ms-marco-cross-encoder";
+
+/// Loads the cross-encoder at `CROSS_ENCODER_PATH`. Load it once and pass it to every
+/// `rerank_with` call.
+pub fn get_cross_encoder() -> failure::Fallible<CrossEncoder> {
+    let device = Device::Cpu;
+
+    CrossEncoder::new(Path::new(CROSS_ENCODER_PATH), None, device)
+}
+
+/// Second stage of a query: rescores the docs returned by `Operations::query` with a
+/// cross-encoder and keeps the `top_n` best. `Doc::score` is replaced by the cross-encoder
+/// score, higher is more relevant.
+pub fn rerank_with(cross_encoder: &CrossEncoder, query: &str, docs: Vec<Doc>, top_n: usize) -> Vec<Doc> {
+    let pairs: Vec<(&str, &str)> = docs.iter().map(|doc| (query, doc.text.as_str())).collect();
+    let scores = cross_encoder.predict(&pairs);
+
+    let mut result: Vec<Doc> = docs
+        .into_iter()
+        .zip(scores.into_iter())
+        .map(|(doc, score)| Doc { score: score, ..doc })
+        .collect();
+    sort_by_score(&mut result);
+    result.truncate(top_n);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_by_score_nan() {
+        let mut docs: Vec<Doc> = [0.5, f64::NAN, 2., -1.]
+            .iter()
+            .map(|score| Doc { text: score.to_string(), embedding: Vec::new(), score: *score })
+            .collect();
+        sort_by_score(&mut docs);
+        let texts: Vec<&str> = docs.iter().map(|doc| doc.text.as_str()).collect();
+        assert_eq!(vec!["2", "0.5", "-1", "NaN"], texts);
+    }
+}
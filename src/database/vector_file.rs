@@ -0,0 +1,47 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+
+/// Full precision vectors kept on disk, one fixed size little endian f32 row per vector,
+/// read back by row index.
+pub struct VectorFile {
+    pub path: PathBuf,
+    pub dimension: usize,
+    file: Mutex<File>,
+}
+
+impl VectorFile {
+    /// Writes `vectors` to `path`, replacing its content.
+    pub fn create(path: &Path, dimension: usize, vectors: &[Vec<f32>]) -> std::io::Result<VectorFile> {
+        {
+            let mut writer = BufWriter::new(File::create(path)?);
+            for vector in vectors {
+                for x in vector {
+                    writer.write_all(&x.to_le_bytes())?;
+                }
+            }
+            writer.flush()?;
+        }
+        let file = OpenOptions::new().read(true).open(path)?;
+        Ok(VectorFile {
+            path: path.to_path_buf(),
+            dimension,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn get(&self, row: usize) -> std::io::Result<Vec<f32>> {
+        let mut bytes = vec![0u8; 4 * self.dimension];
+        {
+            let mut file = self.file.lock();
+            file.seek(SeekFrom::Start((row * bytes.len()) as u64))?;
+            file.read_exact(&mut bytes)?;
+        }
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+}
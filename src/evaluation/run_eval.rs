@@ -1,9 +1,12 @@
 use polars::prelude::*;
 use std::fs::File;
 use std::time::Instant;
-use crate::database::Operations;
+use crate::database::{DBConfig, Operations};
 
-pub fn time_and_accuracy() -> () {
+/// Loads and queries every backend, printing its accuracy and time. With `embedding_cache`,
+/// embeddings are cached in that file: the methods after the first one skip the encoding, so
+/// their times are not comparable with a run without cache.
+pub fn time_and_accuracy(embedding_cache: Option<&str>) -> () {
     let path = "/Users/bradwindsor/ms_projects/thistle/thistle/data/data_cleaned.tsv";
     let file = File::open(path).expect("could not open file");
 
@@ -15,12 +18,17 @@ pub fn time_and_accuracy() -> () {
             .unwrap();
     let row_count = data.shape().0;
 
-    let db_methods = vec!["Cosine", "Euclidean", "Hnsw_Euclidean", "Hnsw_Cosine", "LSH"];
+    let db_methods = vec!["Cosine", "Euclidean", "Hnsw_Euclidean", "Hnsw_Cosine", "Hnsw_Sq8", "IVF_Flat", "IVF_PQ", "LSH"];
     for method in db_methods.iter() {
         let texts = get_texts(&data, "column_2".to_string());
         let references = texts.clone();
     
-        let mut db = crate::database::new(method);
+        let config = match embedding_cache {
+            // every method embeds the same texts, only the first one runs the model
+            Some(path) => DBConfig::new(method).cache(path, 0),
+            None => DBConfig::new(method),
+        };
+        let mut db = crate::database::new_with_config(&config).unwrap();
     
         let start_time = Instant::now();
         db.load(texts);
@@ -11,6 +11,7 @@ use serde::{Serialize, de::DeserializeOwned};
 
 use crate::hnswlib::hnsw::*;
 use crate::hnswlib::hnswio::*;
+use crate::hnswlib::stats::GraphStats;
 
 
 pub trait AnnT {
@@ -28,6 +29,9 @@ pub trait AnnT {
     /// dumps a data and graph in 2 files. 
     /// Datas are dumped in file filename.hnsw.data and graph in filename.hnsw.graph
     fn file_dump(&self, filename: &String) -> Result<i32, String>;
+    ///
+    /// statistics on the graph, see Hnsw::get_graph_stats
+    fn graph_stats(&self) -> GraphStats;
  }
 
 
@@ -75,12 +79,16 @@ impl <T,D> AnnT for Hnsw<T,D>  where T:Serialize+DeserializeOwned+Clone+Send+Syn
         let datafile = fileres.unwrap();
         let mut graphbufw = BufWriter::with_capacity(10000000 , graphfile);
         let mut databufw = BufWriter::with_capacity(10000000 , datafile);
-        let res = self.dump(DumpMode::Full, &mut graphbufw, &mut databufw);
+        let res = self.dump(DumpMode::Full, &mut graphbufw, &mut databufw).map_err(|e| e.to_string());
         graphbufw.flush().unwrap();
         databufw.flush().unwrap();
         log::debug!("\n end of dump");
         return res;
    }   // end of dump
+
+   fn graph_stats(&self) -> GraphStats {
+       self.get_graph_stats()
+   }
 } // end of impl block AnnT for Hnsw<T,D>
 
 
@@ -91,6 +99,8 @@ macro_rules! mapdist_t(
     ("DistL2")       => (crate::dist::DistL2);
     ("DistL2")       => (crate::dist::DistL2);
     ("DistDot")      => (crate::dist::DistDot);
+    ("DistL2Sq8")    => (crate::dist::DistL2Sq8);
+    ("DistDotSq8")   => (crate::dist::DistDotSq8);
     ("DistHamming")  => (crate::dist::DistHamming);
     ("DistJaccard")  => (crate::dist::DistJaccard);
     ("DistPtr")      => (crate::dist::DistPtr);
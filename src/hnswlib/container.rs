@@ -0,0 +1,491 @@
+//! Single file dump of Hnsw, whose vectors can be memory mapped at reload.
+//!
+//! A container holds the graph as in a graph file of hnswio, then the vectors stored as raw rows
+//! of `dimension` values, so that they can be used in place : a large index opens without reading
+//! its vectors and processes opening the same container share its pages.
+//! The neighbours of the points are decoded at load, or on first access to each point.
+//!
+//! ```text
+//! dump_hnsw_container(&hnsw, path)?;
+//! let options = LoadOptions::new().mmap_vectors(true).adjacency(AdjacencyMode::Lazy);
+//! let hnsw : Hnsw<f32, DistCosine> = load_hnsw_container(path, &options)?;
+//! ```
+//!
+//! Rows are written in the byte order of the machine, containers are only supported on little endian targets.
+//
+// header   : MAGICCONTAINER : u32, FORMAT_VERSION : u32, size of T : u32, graph offset : u64, graph length : u64,
+//            vectors offset : u64, vectors length : u64, crc32 of the vectors : u32, crc32 of the header
+// graph    : description, layers, entry point and deleted points as in a graph file of hnswio (format version 3)
+// vectors  : at an offset multiple of VECTOR_ALIGN, one row by point in the order of the graph file
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::{Arc, Weak};
+
+use parking_lot::RwLock;
+
+use crate::hnswlib::dist::Distance;
+use crate::hnswlib::hnsw::*;
+use crate::hnswlib::hnswio::*;
+
+// magic at beginning of a container
+const MAGICCONTAINER : u32 = 0x000a673f;
+// length of the header, checksum included
+const HEADER_LEN : usize = 52;
+// alignment of the vector section in the file
+const VECTOR_ALIGN : u64 = 64;
+
+
+/// How the neighbours of the points are reloaded from a container.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdjacencyMode {
+    /// all neighbours are decoded by the load
+    Eager,
+    /// the neighbours of a point are decoded when a search or an insertion first visits it.
+    /// The container is memory mapped.
+    Lazy,
+}
+
+/// Options of load_hnsw_container.
+///
+/// Can be initialized following the Builder pattern, default is vectors in memory and eager adjacency.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    mmap_vectors : bool,
+    adjacency : AdjacencyMode,
+}
+
+impl LoadOptions {
+    pub fn new() -> Self {
+        LoadOptions{mmap_vectors : false, adjacency : AdjacencyMode::Eager}
+    }
+
+    /// Vectors are used in place in the memory mapped container instead of being copied in memory.
+    /// Their checksum is then not verified.
+    pub fn mmap_vectors(mut self, mmap_vectors : bool) -> Self {
+        self.mmap_vectors = mmap_vectors;
+        self
+    }
+
+    pub fn adjacency(mut self, adjacency : AdjacencyMode) -> Self {
+        self.adjacency = adjacency;
+        self
+    }
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions::new()
+    }
+}
+
+
+// the bytes of a slice of values
+fn as_bytes<T:MappableValue>(v : &[T]) -> &[u8] {
+    // a MappableValue has no padding
+    unsafe { std::slice::from_raw_parts(v.as_ptr() as *const u8, v.len() * size_of::<T>()) }
+}
+
+// copies a row of bytes, possibly not aligned, into a vector of values
+fn from_bytes<T:MappableValue>(bytes : &[u8]) -> Vec<T> {
+    let len = bytes.len() / size_of::<T>();
+    let mut v = Vec::<T>::with_capacity(len);
+    // every bit pattern is a valid MappableValue
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), v.as_mut_ptr() as *mut u8, len * size_of::<T>());
+        v.set_len(len);
+    }
+    v
+}
+
+
+/// Dumps hnsw in a single file at path.
+///
+/// As with file_dump, there must not be insertions while the dump runs.
+pub fn dump_hnsw_container<T, D>(hnsw : &Hnsw<T, D>, path : &Path) -> Result<(), HnswIoError>
+        where T:MappableValue+Serialize+DeserializeOwned, D:Distance<T>+Send+Sync {
+    if cfg!(target_endian = "big") {
+        return Err(HnswIoError::BigEndianTarget);
+    }
+    let mut file = File::create(path)?;
+    // the header is written last, when offsets are known
+    file.write_all(&[0u8; HEADER_LEN])?;
+    let graph_offset = HEADER_LEN as u64;
+    let description = make_description(hnsw, DumpMode::Full);
+    let points = {
+        let mut graphout = io::BufWriter::new(&mut file);
+        description.dump(DumpMode::Full, &mut graphout)?;
+        let points = dump_point_indexation(&hnsw.layer_indexed_points, &mut graphout, None)?;
+        graphout.flush()?;
+        points
+    };
+    let graph_end = file.seek(SeekFrom::Current(0))?;
+    let vectors_offset = (graph_end + VECTOR_ALIGN - 1) / VECTOR_ALIGN * VECTOR_ALIGN;
+    file.write_all(&vec![0u8; (vectors_offset - graph_end) as usize])?;
+    let mut vectors_hasher = crc32fast::Hasher::new();
+    {
+        let mut vectorout = io::BufWriter::new(&mut file);
+        for point in &points {
+            if point.get_v().len() != description.dimension {
+                return Err(HnswIoError::Corrupted(format!("point {:?} has dimension {}, not {}", point.get_origin_id(),
+                                point.get_v().len(), description.dimension)));
+            }
+            let row = as_bytes(point.get_v());
+            vectors_hasher.update(row);
+            vectorout.write_all(row)?;
+        }
+        vectorout.flush()?;
+    }
+    let vectors_end = file.seek(SeekFrom::Current(0))?;
+    //
+    let mut header = io::BufWriter::new(Vec::<u8>::with_capacity(HEADER_LEN));
+    {
+        let mut header = SectionWriter::new(&mut header);
+        header.write_u32(MAGICCONTAINER)?;
+        header.write_u32(FORMAT_VERSION)?;
+        header.write_u32(size_of::<T>() as u32)?;
+        header.write_u64(graph_offset)?;
+        header.write_u64(graph_end - graph_offset)?;
+        header.write_u64(vectors_offset)?;
+        header.write_u64(vectors_end - vectors_offset)?;
+        header.write_u32(vectors_hasher.finalize())?;
+        header.end_section()?;
+    }
+    let header = header.into_inner().map_err(|e| e.into_error())?;
+    assert_eq!(header.len(), HEADER_LEN);
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)?;
+    file.sync_all()?;
+    log::info!("dumped container {:?}, {} points", path, points.len());
+    Ok(())
+} // end of dump_hnsw_container
+
+
+
+// the header of a container
+struct ContainerHeader {
+    graph_offset : usize,
+    graph_len : usize,
+    vectors_offset : usize,
+    vectors_len : usize,
+    vectors_crc : u32,
+}
+
+fn read_header<T:MappableValue>(input : &mut dyn Read, file_len : u64) -> Result<ContainerHeader, HnswIoError> {
+    let mut header = SectionReader::new(input, "container header");
+    header.check_magic(MAGICCONTAINER)?;
+    let version = header.read_u32()?;
+    if version != FORMAT_VERSION {
+        return Err(HnswIoError::UnsupportedVersion(version));
+    }
+    let value_size = header.read_u32()? as usize;
+    let graph_offset = header.read_u64()?;
+    let graph_len = header.read_u64()?;
+    let vectors_offset = header.read_u64()?;
+    let vectors_len = header.read_u64()?;
+    let vectors_crc = header.read_u32()?;
+    header.end_section("graph")?;
+    if value_size != size_of::<T>() {
+        return Err(HnswIoError::Corrupted(format!("values of {} bytes, {} asked", value_size, size_of::<T>())));
+    }
+    let graph_end = graph_offset.checked_add(graph_len);
+    let vectors_end = vectors_offset.checked_add(vectors_len);
+    match (graph_end, vectors_end) {
+        (Some(graph_end), Some(vectors_end)) if graph_offset >= HEADER_LEN as u64 && graph_end <= vectors_offset
+                    && vectors_offset % VECTOR_ALIGN == 0 && vectors_end <= file_len => (),
+        (_, Some(vectors_end)) if vectors_end > file_len => return Err(HnswIoError::Truncated("vectors")),
+        _ => return Err(HnswIoError::Corrupted(String::from("section offsets in container header"))),
+    }
+    Ok(ContainerHeader{graph_offset : graph_offset as usize, graph_len : graph_len as usize,
+                vectors_offset : vectors_offset as usize, vectors_len : vectors_len as usize, vectors_crc})
+}
+
+
+// vectors used in place in the mapped container
+struct MappedRows {
+    map : Arc<memmap2::Mmap>,
+    offset : usize,
+    dimension : usize,
+    end : usize,
+}
+
+impl <T:MappableValue> VectorIn<T> for MappedRows {
+    fn next_vector(&mut self, _origin_id : DataId) -> Result<PointData<T>, HnswIoError> {
+        let row_len = self.dimension * size_of::<T>();
+        if self.offset + row_len > self.end {
+            return Err(HnswIoError::Truncated("vectors"));
+        }
+        // the vector section is aligned and in the mapping, checked by read_header, and rows are a multiple of size_of T
+        let v = unsafe { PointData::mapped(Arc::clone(&self.map), self.offset, self.dimension) };
+        self.offset += row_len;
+        Ok(v)
+    }
+
+    fn end_layer(&mut self) -> Result<(), HnswIoError> {
+        Ok(())
+    }
+}
+
+
+// vectors copied in memory, checksum verified by finish
+struct CopiedRows<'a> {
+    input : &'a mut dyn Read,
+    dimension : usize,
+    remaining : usize,
+    hasher : crc32fast::Hasher,
+    row : Vec<u8>,
+}
+
+impl <'a> CopiedRows<'a> {
+    fn finish(self, stored : u32) -> Result<(), HnswIoError> {
+        if self.remaining != 0 {
+            return Err(HnswIoError::Corrupted(format!("{} bytes of vectors without point", self.remaining)));
+        }
+        let computed = self.hasher.finalize();
+        if stored != computed {
+            return Err(HnswIoError::ChecksumMismatch{section : "vectors", stored, computed});
+        }
+        Ok(())
+    }
+}
+
+impl <'a, T:MappableValue> VectorIn<T> for CopiedRows<'a> {
+    fn next_vector(&mut self, _origin_id : DataId) -> Result<PointData<T>, HnswIoError> {
+        let row_len = self.dimension * size_of::<T>();
+        if row_len > self.remaining {
+            return Err(HnswIoError::Truncated("vectors"));
+        }
+        self.row.resize(row_len, 0);
+        match self.input.read_exact(&mut self.row) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(HnswIoError::Truncated("vectors")),
+            Err(e) => return Err(HnswIoError::Io(e)),
+        }
+        self.hasher.update(&self.row);
+        self.remaining -= row_len;
+        Ok(PointData::owned(from_bytes(&self.row)))
+    }
+
+    fn end_layer(&mut self) -> Result<(), HnswIoError> {
+        Ok(())
+    }
+}
+
+
+/// Decodes neighbours from the graph section of a mapped container, for AdjacencyMode::Lazy.
+///
+/// Neighbours are resolved through a table of the loaded points, filled once at the end of the load,
+/// so that decoding takes no lock of PointIndexation.
+pub(crate) struct MappedNeighbours<T:Clone+Send+Sync> {
+    map : Arc<memmap2::Mmap>,
+    // offset in map of the position 0 of the graph reader
+    base : usize,
+    points : RwLock<Vec<Vec<Weak<Point<T>>>>>,
+}
+
+impl <T:Clone+Send+Sync> MappedNeighbours<T> {
+    fn new(map : Arc<memmap2::Mmap>, base : usize) -> Self {
+        MappedNeighbours{map, base, points : RwLock::new(Vec::new())}
+    }
+
+    pub(crate) fn set_points(&self, points_by_layer : &Vec<Vec<Arc<Point<T>>>>) {
+        *self.points.write() = points_by_layer.iter()
+                .map(|layer| layer.iter().map(Arc::downgrade).collect())
+                .collect();
+    }
+}
+
+impl <T:Clone+Send+Sync> NeighbourSource<T> for MappedNeighbours<T> {
+    // the graph section was read through at load, its checksums verified and the neighbour ids checked
+    fn load_neighbours(&self, p_id : PointId, offset : usize) -> Vec<Vec<Arc<PointWithOrder<T>>>> {
+        let mut input = &self.map[self.base + offset ..];
+        let mut graph_in = SectionReader::new(&mut input, "graph");
+        let points = self.points.read();
+        let mut neighbours = Vec::with_capacity(p_id.0 as usize + 1);
+        for _l in 0..=p_id.0 {
+            let nb_neighbours = graph_in.read_u32().expect("neighbours checked at load") as usize;
+            let mut neighbours_l = Vec::with_capacity(nb_neighbours);
+            for _j in 0..nb_neighbours {
+                let (_, n_p_id) = graph_in.read_point_id().expect("neighbours checked at load");
+                let distance = graph_in.read_f32().expect("neighbours checked at load");
+                let n_point = points[n_p_id.0 as usize][n_p_id.1 as usize].upgrade()
+                        .expect("neighbour of a point of a live Hnsw");
+                neighbours_l.push(Arc::new(PointWithOrder::new(&n_point, distance)));
+            }
+            neighbours_l.sort_unstable();
+            neighbours.push(neighbours_l);
+        }
+        neighbours
+    }
+}
+
+
+/// Reloads a Hnsw dumped by dump_hnsw_container.
+///
+/// The distance and type of the container must be D and T. The header and graph checksums are always
+/// verified, the vectors checksum only when vectors are copied in memory.
+pub fn load_hnsw_container<T, D>(path : &Path, options : &LoadOptions) -> Result<Hnsw<T, D>, HnswIoError>
+        where T:MappableValue+Serialize+DeserializeOwned, D:Distance<T>+Default+Send+Sync {
+    if cfg!(target_endian = "big") {
+        return Err(HnswIoError::BigEndianTarget);
+    }
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let header = read_header::<T>(&mut file, file_len)?;
+    let lazy = options.adjacency == AdjacencyMode::Lazy;
+    //
+    let indexation;
+    let description;
+    if options.mmap_vectors || lazy {
+        // the file must not be modified while mapped
+        let map = Arc::new(unsafe { memmap2::Mmap::map(&file)? });
+        let mut graph = &map[header.graph_offset .. header.graph_offset + header.graph_len];
+        description = load_description(&mut graph)?;
+        check_description::<T, D>(&description)?;
+        check_vectors_len::<T>(&description, &header)?;
+        let base = header.graph_offset + header.graph_len - graph.len();
+        let source = if lazy { Some(Arc::new(MappedNeighbours::<T>::new(Arc::clone(&map), base))) } else { None };
+        let mut graph_in = SectionReader::new(&mut graph, "graph");
+        if options.mmap_vectors {
+            let mut vectors = MappedRows{map : Arc::clone(&map), offset : header.vectors_offset, dimension : description.dimension,
+                        end : header.vectors_offset + header.vectors_len};
+            indexation = load_point_indexation(&mut graph_in, &description, &mut vectors, source.as_ref())?;
+        }
+        else {
+            let mut input = &map[header.vectors_offset .. header.vectors_offset + header.vectors_len];
+            let mut vectors = CopiedRows{input : &mut input, dimension : description.dimension, remaining : header.vectors_len,
+                        hasher : crc32fast::Hasher::new(), row : Vec::new()};
+            indexation = load_point_indexation(&mut graph_in, &description, &mut vectors, source.as_ref())?;
+            vectors.finish(header.vectors_crc)?;
+        }
+    }
+    else {
+        file.seek(SeekFrom::Start(header.graph_offset as u64))?;
+        let mut graph = io::BufReader::new(file.try_clone()?.take(header.graph_len as u64));
+        description = load_description(&mut graph)?;
+        check_description::<T, D>(&description)?;
+        check_vectors_len::<T>(&description, &header)?;
+        let mut vector_file = File::open(path)?;
+        vector_file.seek(SeekFrom::Start(header.vectors_offset as u64))?;
+        let mut input = io::BufReader::new(vector_file.take(header.vectors_len as u64));
+        let mut vectors = CopiedRows{input : &mut input, dimension : description.dimension, remaining : header.vectors_len,
+                    hasher : crc32fast::Hasher::new(), row : Vec::new()};
+        let mut graph_in = SectionReader::new(&mut graph, "graph");
+        indexation = load_point_indexation(&mut graph_in, &description, &mut vectors, None)?;
+        vectors.finish(header.vectors_crc)?;
+    }
+    log::info!("loaded container {:?}, {} points", path, description.nb_point);
+    Ok(hnsw_from_indexation(&description, indexation))
+} // end of load_hnsw_container
+
+
+fn check_vectors_len<T>(description : &Description, header : &ContainerHeader) -> Result<(), HnswIoError> {
+    let expected = description.nb_point.checked_mul(description.dimension).and_then(|n| n.checked_mul(size_of::<T>()));
+    if expected != Some(header.vectors_len) {
+        return Err(HnswIoError::Corrupted(format!("{} bytes of vectors for {} points of dimension {}", header.vectors_len,
+                        description.nb_point, description.dimension)));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+use crate::hnswlib::dist;
+use rand::distributions::{Distribution, Uniform};
+
+fn build_hnsw(nbcolumn : usize, nbrow : usize) -> (Hnsw<f32, dist::DistL1>, Vec<Vec<f32>>) {
+    let mut rng = rand::thread_rng();
+    let unif = Uniform::<f32>::new(0.,1.);
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| unif.sample(&mut rng)).collect()).collect();
+    let hnsw = Hnsw::<f32, dist::DistL1>::new(16, nbcolumn, 16, 200, dist::DistL1{});
+    let data_with_id : Vec<(&Vec<f32>, usize)> = data.iter().zip(0..data.len()).collect();
+    hnsw.parallel_insert(&data_with_id);
+    (hnsw, data)
+}
+
+fn container_path(name : &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("thistle-{}-{}.hnsw", name, std::process::id()))
+}
+
+#[test]
+fn test_container_reload_modes() {
+    let (hnsw, data) = build_hnsw(500, 20);
+    hnsw.delete(7);
+    let path = container_path("modes");
+    dump_hnsw_container(&hnsw, &path).unwrap();
+    let knbn = 10;
+    // searches in the graph reloaded in each mode, answers must not depend on the mode
+    let mut expected : Option<Vec<Vec<DataId>>> = None;
+    for mmap_vectors in &[false, true] {
+        for adjacency in &[AdjacencyMode::Eager, AdjacencyMode::Lazy] {
+            let options = LoadOptions::new().mmap_vectors(*mmap_vectors).adjacency(*adjacency);
+            let reloaded : Hnsw<f32, dist::DistL1> = load_hnsw_container(&path, &options).unwrap();
+            let point = reloaded.get_point_indexation().get_point(0).unwrap();
+            assert_eq!(point.is_mapped(), *mmap_vectors);
+            assert_eq!(point.get_v(), &data[0][..]);
+            let found : Vec<Vec<DataId>> = data.iter().take(50)
+                    .map(|v| reloaded.search(v, knbn, 48).iter().map(|n| n.d_id).collect())
+                    .collect();
+            assert!(found.iter().all(|ids| !ids.contains(&7)));
+            match &expected {
+                Some(expected) => assert_eq!(&found, expected, "options {:?}", options),
+                None => expected = Some(found),
+            }
+            check_graph_equality(&reloaded, &hnsw);
+            // a reloaded graph can grow, lazy neighbours are decoded before being updated
+            let new_point = vec![0.5f32; 20];
+            reloaded.insert((&new_point, 1000));
+            assert_eq!(reloaded.search(&new_point, 1, 48)[0].d_id, 1000);
+        }
+    }
+    std::fs::remove_file(&path).unwrap();
+} // end of test_container_reload_modes
+
+
+#[test]
+fn test_container_corruption() {
+    let (hnsw, _) = build_hnsw(100, 8);
+    let path = container_path("corruption");
+    dump_hnsw_container(&hnsw, &path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    let eager = LoadOptions::new();
+    // a flipped byte in the last vector
+    let mut corrupted = bytes.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    std::fs::write(&path, &corrupted).unwrap();
+    match load_hnsw_container::<f32, dist::DistL1>(&path, &eager) {
+        Err(HnswIoError::ChecksumMismatch{section, ..}) => assert_eq!(section, "vectors"),
+        _ => panic!("corrupted vectors not detected"),
+    }
+    // a flipped byte in the graph, detected in lazy mode too
+    let mut corrupted = bytes.clone();
+    corrupted[HEADER_LEN + 200] ^= 0xff;
+    std::fs::write(&path, &corrupted).unwrap();
+    let lazy = LoadOptions::new().mmap_vectors(true).adjacency(AdjacencyMode::Lazy);
+    assert!(load_hnsw_container::<f32, dist::DistL1>(&path, &lazy).is_err());
+    // truncated
+    std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    match load_hnsw_container::<f32, dist::DistL1>(&path, &eager) {
+        Err(HnswIoError::Truncated(_)) => (),
+        _ => panic!("truncation not detected"),
+    }
+    // another distance
+    std::fs::write(&path, &bytes).unwrap();
+    match load_hnsw_container::<f32, dist::DistL2>(&path, &eager) {
+        Err(HnswIoError::DistanceMismatch{..}) => (),
+        _ => panic!("distance mismatch not detected"),
+    }
+    std::fs::remove_file(&path).unwrap();
+} // end of test_container_corruption
+
+} // end of mod tests
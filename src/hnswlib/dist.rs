@@ -753,6 +753,117 @@ impl Distance<u16> for DistLevenshtein {
 
 
 
+//=======================================================================================
+
+/// Scalar quantized (int8) vectors, stored as `Vec<u8>` so that the Hnsw structure needs no change.
+///
+/// A data vector is `[SQ8_DATA, scale as 4 bytes LE, codes...]` where each code is an i8 and
+/// the value it stands for is `scale * code`, the scale being `max |x| / 127` for the vector.
+/// A query vector is `[SQ8_QUERY, f32 values as 4 bytes LE...]`: queries are not quantized,
+/// so the distance between a query and the data is asymmetric (f32 against int8) and only
+/// the data side loses precision. Build them with `sq8_encode` and `sq8_query`.
+pub const SQ8_DATA: u8 = 1;
+pub const SQ8_QUERY: u8 = 2;
+
+/// Quantizes a data vector, 1 byte per dimension plus a 5 bytes header.
+pub fn sq8_encode(va: &[f32]) -> Vec<u8> {
+    let max = va.iter().fold(0f32, |acc, x| acc.max(x.abs()));
+    let scale = if max > 0. { max / 127. } else { 1. };
+    let mut encoded = Vec::with_capacity(5 + va.len());
+    encoded.push(SQ8_DATA);
+    encoded.extend_from_slice(&scale.to_le_bytes());
+    encoded.extend(va.iter().map(|x| ((x / scale).round().max(-127.).min(127.) as i8) as u8));
+    encoded
+}
+
+/// Wraps a full precision query vector for search against sq8 data.
+pub fn sq8_query(va: &[f32]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(1 + 4 * va.len());
+    encoded.push(SQ8_QUERY);
+    for x in va {
+        encoded.extend_from_slice(&x.to_le_bytes());
+    }
+    encoded
+}
+
+/// Gives back the f32 values of a data or query vector.
+pub fn sq8_decode(va: &[u8]) -> Vec<f32> {
+    match Sq8View::new(va) {
+        Sq8View::Data(scale, codes) => codes.iter().map(|c| scale * (*c as i8) as f32).collect(),
+        Sq8View::Query(values) => values.chunks_exact(4).map(read_f32).collect(),
+    }
+}
+
+fn read_f32(bytes: &[u8]) -> f32 {
+    f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+enum Sq8View<'a> {
+    /// scale and codes
+    Data(f32, &'a [u8]),
+    /// f32 values as bytes
+    Query(&'a [u8]),
+}
+
+impl<'a> Sq8View<'a> {
+    fn new(va: &'a [u8]) -> Sq8View<'a> {
+        match va.first() {
+            Some(&SQ8_DATA) => Sq8View::Data(read_f32(&va[1..5]), &va[5..]),
+            Some(&SQ8_QUERY) => Sq8View::Query(&va[1..]),
+            _ => panic!("not a sq8 vector, build it with sq8_encode or sq8_query"),
+        }
+    }
+}
+
+/// Sum over dimensions of `f(a_i, b_i)`, decoding each side on the fly.
+fn sq8_fold<F: Fn(f32, f32) -> f32>(va: &[u8], vb: &[u8], f: F) -> f32 {
+    match (Sq8View::new(va), Sq8View::new(vb)) {
+        (Sq8View::Data(sa, ca), Sq8View::Data(sb, cb)) => ca
+            .iter()
+            .zip(cb.iter())
+            .map(|(a, b)| f(sa * (*a as i8) as f32, sb * (*b as i8) as f32))
+            .sum(),
+        (Sq8View::Query(qa), Sq8View::Data(sb, cb)) => qa
+            .chunks_exact(4)
+            .zip(cb.iter())
+            .map(|(a, b)| f(read_f32(a), sb * (*b as i8) as f32))
+            .sum(),
+        (Sq8View::Data(sa, ca), Sq8View::Query(qb)) => ca
+            .iter()
+            .zip(qb.chunks_exact(4))
+            .map(|(a, b)| f(sa * (*a as i8) as f32, read_f32(b)))
+            .sum(),
+        (Sq8View::Query(qa), Sq8View::Query(qb)) => qa
+            .chunks_exact(4)
+            .zip(qb.chunks_exact(4))
+            .map(|(a, b)| f(read_f32(a), read_f32(b)))
+            .sum(),
+    }
+}
+
+/// L2 distance between sq8 vectors, see `SQ8_DATA`.
+#[derive(Default)]
+pub struct DistL2Sq8;
+
+impl Distance<u8> for DistL2Sq8 {
+    fn eval(&self, va: &[u8], vb: &[u8]) -> f32 {
+        sq8_fold(va, vb, |a, b| (a - b) * (a - b)).sqrt()
+    }
+}
+
+/// `DistDot` between sq8 vectors: 1 - dot product, the vectors must be L2 normalized
+/// before `sq8_encode` / `sq8_query`. Quantization can push the dot product of close
+/// vectors a little above 1, the distance is clamped at 0.
+#[derive(Default)]
+pub struct DistDotSq8;
+
+impl Distance<u8> for DistDotSq8 {
+    fn eval(&self, va: &[u8], vb: &[u8]) -> f32 {
+        (1. - sq8_fold(va, vb, |a, b| a * b)).max(0.)
+    }
+}
+
+
 //=======================================================================================
 //   Case of function pointers (cover Trait Fn , FnOnce ...)
 // The book (Function item types):  " There is a coercion from function items to function pointers with the same signature  "
@@ -1089,6 +1200,33 @@ fn test_jensenshannon() {
 }
 
 
+#[test]
+fn test_sq8_distances() {
+    let mut va: Vec<f32> = vec![0.3, -1.2, 0.05, 2.4, -0.7, 0.0, 1.1, -0.25];
+    let mut vb: Vec<f32> = vec![0.1, -0.9, 0.4, 2.0, -0.2, 0.3, 0.8, -0.5];
+    let data_a = sq8_encode(&va);
+    assert_eq!(5 + va.len(), data_a.len());
+    let decoded = sq8_decode(&data_a);
+    let max_error = 2.4 / 127. / 2. + 1e-6;
+    for (x, y) in va.iter().zip(decoded.iter()) {
+        assert!((x - y).abs() <= max_error);
+    }
+    // asymmetric and symmetric L2 stay close to the f32 distance, in both argument orders
+    let exact = DistL2.eval(&va, &vb);
+    let asymmetric = DistL2Sq8.eval(&sq8_query(&vb), &data_a);
+    assert_eq!(asymmetric, DistL2Sq8.eval(&data_a, &sq8_query(&vb)));
+    assert!((exact - asymmetric).abs() < 0.05);
+    assert!((exact - DistL2Sq8.eval(&data_a, &sq8_encode(&vb))).abs() < 0.1);
+    assert!((exact - DistL2Sq8.eval(&sq8_query(&va), &sq8_query(&vb))).abs() < 1e-5);
+    //
+    l2_normalize(&mut va);
+    l2_normalize(&mut vb);
+    let exact = DistDot.eval(&va, &vb);
+    let asymmetric = DistDotSq8.eval(&sq8_query(&va), &sq8_encode(&vb));
+    assert!((exact - asymmetric).abs() < 0.02);
+    assert!(DistDotSq8.eval(&sq8_query(&va), &sq8_encode(&va)) < 0.02);
+}
+
 use rand::distributions::{Distribution, Uniform};
 
 #[test]
@@ -11,6 +11,7 @@ use std::cmp::Ordering;
 
 use parking_lot::{RwLock, Mutex};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use rayon::prelude::*;
 use std::sync::mpsc::channel;
 
@@ -23,6 +24,7 @@ use log::debug;
 use log::trace;
 
 pub use crate::hnswlib::*;
+use crate::hnswlib::stats::CompactionReport;
 
 
 // TODO
@@ -40,6 +42,9 @@ pub struct NoData;
 /// maximum number of layers
 pub(crate) const NB_LAYER_MAX:u8 = 16;   // so max layer is 15!!
 
+/// a search whose predicate rejects most points widens ef by doubling, up to this factor
+pub(crate) const MAX_EF_WIDENING:usize = 64;
+
 #[derive(Debug, Default, Clone,Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 /// The 2-uple represent layer as u8  and rank in layer as a i32 as stored in our structure
 pub struct PointId(pub u8, pub i32);
@@ -123,6 +128,104 @@ impl Neighbor {
 
 //=======================================================================================
 
+/// Types whose vectors can be stored as raw rows in a container and memory mapped,
+/// see container::load_hnsw_container.
+///
+/// # Safety
+/// Every bit pattern of `size_of::<Self>()` bytes must be a valid value and the type must not have padding.
+pub unsafe trait MappableValue : Copy+Send+Sync+'static {}
+
+unsafe impl MappableValue for f32 {}
+unsafe impl MappableValue for f64 {}
+unsafe impl MappableValue for u8 {}
+unsafe impl MappableValue for u16 {}
+unsafe impl MappableValue for u32 {}
+unsafe impl MappableValue for u64 {}
+unsafe impl MappableValue for i8 {}
+unsafe impl MappableValue for i16 {}
+unsafe impl MappableValue for i32 {}
+unsafe impl MappableValue for i64 {}
+
+
+/// The vector of a point. It is owned by the point, or lies in a memory mapped container.
+/// Dereferences to a slice.
+#[derive(Clone)]
+pub struct PointData<T> {
+    storage : PointStorage<T>,
+}
+
+#[derive(Clone)]
+enum PointStorage<T> {
+    Owned(Vec<T>),
+    // len values of type T at offset bytes in map
+    Mapped { map : Arc<memmap2::Mmap>, offset : usize, len : usize },
+}
+
+impl <T> PointData<T> {
+    pub fn owned(v : Vec<T>) -> Self {
+        PointData{storage : PointStorage::Owned(v)}
+    }
+
+    /// # Safety
+    /// offset must be aligned for T, the len values must lie in map and be valid values of T
+    /// (T: MappableValue) in the byte order of the target.
+    pub(crate) unsafe fn mapped(map : Arc<memmap2::Mmap>, offset : usize, len : usize) -> Self {
+        debug_assert!(offset % std::mem::align_of::<T>() == 0);
+        debug_assert!(offset + len * std::mem::size_of::<T>() <= map.len());
+        PointData{storage : PointStorage::Mapped{map, offset, len}}
+    }
+
+    /// true if the vector lies in a memory mapped file
+    pub fn is_mapped(&self) -> bool {
+        match self.storage {
+            PointStorage::Mapped{..} => true,
+            PointStorage::Owned(_) => false,
+        }
+    }
+}
+
+impl <T> std::ops::Deref for PointData<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        match &self.storage {
+            PointStorage::Owned(v) => v.as_slice(),
+            // checked by the constructor
+            PointStorage::Mapped{map, offset, len} => unsafe {
+                std::slice::from_raw_parts(map.as_ptr().add(*offset) as *const T, *len)
+            },
+        }
+    }
+}
+
+impl <T:std::fmt::Debug> std::fmt::Debug for PointData<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+
+/// Decodes the neighbours of a point the first time they are accessed,
+/// see container::AdjacencyMode::Lazy.
+pub(crate) trait NeighbourSource<T:Clone+Send+Sync> : Send+Sync {
+    /// neighbours, one vector by layer, of the point of layer p_id.0 whose record is at offset
+    fn load_neighbours(&self, p_id : PointId, offset : usize) -> Vec<Vec<Arc<PointWithOrder<T>>>>;
+}
+
+// neighbours of a point not decoded yet
+#[derive(Clone)]
+pub(crate) struct PendingNeighbours<T:Clone+Send+Sync> {
+    pub(crate) source : Arc<dyn NeighbourSource<T>>,
+    pub(crate) offset : usize,
+}
+
+impl <T:Clone+Send+Sync> std::fmt::Debug for PendingNeighbours<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PendingNeighbours at {}", self.offset)
+    }
+}
+
+//=======================================================================================
+
 /// The basestructure representing a data point.  
 /// Its constains data as coming from the client, its client id,  
 /// and position in layer representation and neighbours.
@@ -132,29 +235,85 @@ impl Neighbor {
 #[derive(Debug, Clone)]
 pub struct Point<T:Clone+Send+Sync> {
     /// The data of this point, coming from hnsw client and associated to origin_id,
-    v: Vec<T>,
+    v: PointData<T>,
     /// an id coming from client using hnsw, should identify point uniquely
     origin_id : DataId,
     /// a point id identifying point as stored in our structure
     p_id: PointId, 
-    /// neighbours info
-    pub(crate) neighbours:Arc<RwLock<Vec<Vec<Arc<PointWithOrder<T>> >> >>,
+    /// neighbours info. Access goes through neighbours_read and neighbours_write which decode pending neighbours.
+    neighbours:Arc<RwLock<Vec<Vec<Arc<PointWithOrder<T>> >> >>,
+    /// neighbours still to be decoded, with has_pending to avoid locking once they are
+    pending : Arc<Mutex<Option<PendingNeighbours<T>>>>,
+    has_pending : Arc<AtomicBool>,
+    /// set by Hnsw::delete, the point stays in the graph but is not returned by searches
+    pub(crate) deleted: Arc<AtomicBool>,
 }
 
 impl<T:Clone+Send+Sync> Point<T> {
     pub fn new(v: &Vec<T>, origin_id: usize, p_id:PointId) -> Self {
+        Point::from_data(PointData::owned(v.clone()), origin_id, p_id)
+    }
+
+    pub(crate) fn from_data(v: PointData<T>, origin_id: usize, p_id:PointId) -> Self {
         let mut neighbours = Vec::with_capacity(NB_LAYER_MAX as usize);
         // CAVEAT, perhaps pass nb layer as arg ?
         for _ in 0..NB_LAYER_MAX {
             neighbours.push(Vec::<Arc<PointWithOrder<T>> >::new());
         }
-        Point{v:v.clone(), origin_id, p_id, neighbours: Arc::new(RwLock::new(neighbours))}
+        Point{v, origin_id, p_id, neighbours: Arc::new(RwLock::new(neighbours)),
+                pending: Arc::new(Mutex::new(None)), has_pending: Arc::new(AtomicBool::new(false)),
+                deleted: Arc::new(AtomicBool::new(false))}
+    }
+
+    // the neighbours of the point will be decoded from pending on first access
+    pub(crate) fn set_pending_neighbours(&self, pending : PendingNeighbours<T>) {
+        *self.pending.lock() = Some(pending);
+        self.has_pending.store(true, AtomicOrdering::Release);
+    }
+
+    // decodes pending neighbours. No other lock is held while decoding, 
+    // the source resolves neighbours without the locks of PointIndexation.
+    fn load_pending(&self) {
+        if !self.has_pending.load(AtomicOrdering::Acquire) {
+            return;
+        }
+        let mut pending = self.pending.lock();
+        if let Some(p) = pending.take() {
+            let loaded = p.source.load_neighbours(self.p_id, p.offset);
+            let mut neighbours = self.neighbours.write();
+            for (l, neighbours_l) in loaded.into_iter().enumerate() {
+                neighbours[l] = neighbours_l;
+            }
+            self.has_pending.store(false, AtomicOrdering::Release);
+        }
+    }
+
+    /// read access to neighbours, one vector by layer
+    pub(crate) fn neighbours_read(&self) -> parking_lot::RwLockReadGuard<'_, Vec<Vec<Arc<PointWithOrder<T>>>>> {
+        self.load_pending();
+        self.neighbours.read()
+    }
+
+    /// write access to neighbours, one vector by layer
+    pub(crate) fn neighbours_write(&self) -> parking_lot::RwLockWriteGuard<'_, Vec<Vec<Arc<PointWithOrder<T>>>>> {
+        self.load_pending();
+        self.neighbours.write()
+    }
+
+    /// true if the point has been deleted
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.load(AtomicOrdering::Acquire)
     }
 
 
     /// get a reference to vector data
     pub fn get_v(&self) -> &[T] {
-        & self.v.as_slice()
+        &self.v
+    }
+
+    /// true if the vector lies in a memory mapped container
+    pub fn is_mapped(&self) -> bool {
+        self.v.is_mapped()
     }
 
     /// return coordinates in indexation
@@ -170,7 +329,7 @@ impl<T:Clone+Send+Sync> Point<T> {
     // returns for each layer, a vector Neighbor of a point, one vector by layer
     //  
     pub fn get_neighborhood_id(&self) -> Vec<Vec<Neighbor>> {
-        let ref_neighbours = self.neighbours.read();
+        let ref_neighbours = self.neighbours_read();
         let nb_layer = ref_neighbours.len();
         let mut neighborhood = Vec::<Vec<Neighbor>>::with_capacity(nb_layer);
         for i in 0..nb_layer {
@@ -193,7 +352,7 @@ impl<T:Clone+Send+Sync> Point<T> {
         println!(" \n dump of point id : {:?}", self.p_id);
         println!("\n origin id : {:?} ", self.origin_id);
         println!(" neighbours : ...");
-        let ref_neighbours = self.neighbours.read();
+        let ref_neighbours = self.neighbours_read();
         for i in 0..ref_neighbours.len() {
             if ref_neighbours[i].len() > 0usize {
                 println!("neighbours at layer {:?}", i);
@@ -214,9 +373,9 @@ impl<T:Clone+Send+Sync> Point<T> {
 pub(crate) struct  PointWithOrder<T:Clone+Send+Sync> {
     /// the identificateur of the point for which we store a distance to a point for which 
     ///  we made a request.
-    point_ref: Arc<Point<T>>,
+    pub(crate) point_ref: Arc<Point<T>>,
     /// The distance to a point_ref to the request point (not represented in the structure)
-    dist_to_ref : f32,
+    pub(crate) dist_to_ref : f32,
 }
 
 
@@ -288,6 +447,14 @@ impl LayerGenerator {
         LayerGenerator{rng: Arc::new(Mutex::new(StdRng::from_entropy())), 
             unif: Uniform::<f64>::new(0.,1.), scale:scale, maxlevel:maxlevel}
     }
+
+    /// same as new, but the levels are drawn from a generator seeded with seed:
+    /// the same sequence of levels is generated at each run.
+    pub fn new_with_seed(max_nb_connection: usize, maxlevel:usize, seed: u64) -> Self {
+        let layer_g = LayerGenerator::new(max_nb_connection, maxlevel);
+        *layer_g.rng.lock() = StdRng::seed_from_u64(seed);
+        layer_g
+    }
     //
     // l=0 most densely packed layer
     // if S is scale we sample so that P(l=n) = exp(-n/S) - exp(- (n+1)/S)
@@ -321,6 +488,10 @@ impl LayerGenerator {
 // ====================================================================
 
 /// a structure for indexation of points in layer
+// Locks are always taken in the order of the fields: entry_point, points_by_layer, origin_index,
+// then nb_point and nb_deleted. The neighbours lock of a point is never held while taking another
+// lock, so a thread holds at most one of them: this is what allows insertions and searches to run
+// concurrently.
 pub struct PointIndexation<T:Clone+Send+Sync> {
     /// max number of connection for a point at a layer
     pub(crate) max_nb_connection: usize, 
@@ -334,6 +505,10 @@ pub struct PointIndexation<T:Clone+Send+Sync> {
     pub(crate) nb_point: Arc<RwLock<usize>>,
     /// curent enter_point: an Arc RwLock on a possible Arc Point
     pub(crate) entry_point : Arc<RwLock<Option<Arc<Point<T>>  >> >,
+    /// point id of the last point inserted with a given origin id
+    pub(crate) origin_index: Arc<RwLock<HashMap<DataId, PointId>>>,
+    /// number of points marked as deleted
+    pub(crate) nb_deleted: Arc<RwLock<usize>>,
 }
 
 
@@ -354,6 +529,8 @@ impl<T:Clone+Send+Sync> PointIndexation<T> {
             layer_g,
             nb_point:Arc::new(RwLock::new(0)),
             entry_point: Arc::new(RwLock::new(None)),
+            origin_index: Arc::new(RwLock::new(HashMap::new())),
+            nb_deleted: Arc::new(RwLock::new(0)),
         }
     }  // end of new
 
@@ -379,7 +556,9 @@ impl<T:Clone+Send+Sync> PointIndexation<T> {
     /// real insertion of point in point indexation
     // generate a new Point/ArcPoint (with neigbourhood info empty) and store it in global table
     // The function is called by Hnsw insert method
-    fn generate_new_point(& self, data : &Vec<T>, origin_id : usize) -> (Arc<Point<T>> , usize) {
+    // With replace, the point previously inserted with origin_id is deleted and the returned flag tells
+    // whether there was a live one.
+    fn generate_new_point(& self, data : &Vec<T>, origin_id : usize, replace : bool) -> (Arc<Point<T>> , usize, bool) {
         // get a write lock at the beginning of the function
         let level = self.layer_g.generate();
         let new_point;
@@ -393,6 +572,15 @@ impl<T:Clone+Send+Sync> PointIndexation<T> {
             log::trace!("definitive pushing of point {:?}", p_id);
             points_by_layer_ref[p_id.0 as usize].push(Arc::clone(&new_point));
         } // close write lock on points_by_layer
+        let previous = self.origin_index.write().insert(origin_id, new_point.p_id);
+        let mut replaced = false;
+        if let (true, Some(p_id)) = (replace, previous) {
+            let previous_point = Arc::clone(&self.points_by_layer.read()[p_id.0 as usize][p_id.1 as usize]);
+            if !previous_point.deleted.swap(true, AtomicOrdering::AcqRel) {
+                *self.nb_deleted.write() += 1;
+                replaced = true;
+            }
+        }
         //
         let nb_point;
         {
@@ -405,10 +593,29 @@ impl<T:Clone+Send+Sync> PointIndexation<T> {
         }
         log::trace!(" setting number of points {:?} ", *self.nb_point);
         // Now possibly this is a point on a new layer that will have no neighbours in its layer
-        return (Arc::clone(&new_point), nb_point);
+        return (Arc::clone(&new_point), nb_point, replaced);
     } // end of insert
 
 
+    /// returns the entry point, or makes new_point the entry point and returns None if there is none yet.  
+    /// The check and the initialization are done under the same write lock, so that two concurrent
+    /// first insertions cannot both become an entry point and stay unlinked.
+    fn get_or_init_entry_point(&self, new_point : &Arc<Point<T>>) -> Option<Arc<Point<T>>> {
+        if let Some(arc_point) = self.entry_point.read().as_ref() {
+            return Some(Arc::clone(arc_point));
+        }
+        let mut entry_point_ref = self.entry_point.write();
+        match entry_point_ref.as_ref() {
+            Some(arc_point) => Some(Arc::clone(arc_point)),
+            None => {
+                log::debug!("Hnsw  , inserting  entry point {:?} ", new_point.p_id);
+                *entry_point_ref = Some(Arc::clone(new_point));
+                None
+            }
+        }
+    } // end of get_or_init_entry_point
+
+
     /// check if entry_point is modified
     fn check_entry_point(&self, new_point : &Arc<Point<T>>) {
         //
@@ -432,11 +639,24 @@ impl<T:Clone+Send+Sync> PointIndexation<T> {
         }
     }  // end of check_entry_point
 
-    /// returns the number of points in layered structure
+    /// returns the number of points in layered structure, deleted points included
     pub fn get_nb_point(&self) ->usize {
         *self.nb_point.read()
     }
 
+    /// returns the number of points marked as deleted
+    pub fn get_nb_deleted(&self) -> usize {
+        *self.nb_deleted.read()
+    }
+
+    /// returns the last point inserted with origin id data_id, if any
+    pub fn get_point(&self, data_id: DataId) -> Option<Arc<Point<T>>> {
+        // the origin_index lock is released before taking the points_by_layer one
+        let p_id = self.origin_index.read().get(&data_id).copied()?;
+        let points_by_layer = self.points_by_layer.read();
+        Some(Arc::clone(&points_by_layer[p_id.0 as usize][p_id.1 as usize]))
+    }
+
     /// returns the size of data vector in graph if any, else return 0
     pub fn get_data_dimension(&self) -> usize {
         let ep = self.entry_point.read();
@@ -526,6 +746,7 @@ impl<'a,T:Clone+Send+Sync> IntoIterator for &'a PointIndexation<T> {
 /// The Base structure for hnsw implementation.  
 /// The main useful functions are : new, insert, insert_parallel, search, parallel_search and file_dump
 /// as described in trait AnnT.  
+/// All of them take a shared reference: insertions and searches can run at the same time from different threads.
 /// 
 /// Other functions are mainly for others crate to get access to some fields. 
 #[allow(dead_code)]
@@ -548,8 +769,12 @@ pub struct Hnsw<T:Clone+Send+Sync, D: Distance<T>> {
     pub(crate) data_dimension : usize,
     /// distance between points. initialized at first insertion
     pub(crate) dist_f : D,
-    /// insertion mode or searching mode. This flag prevents a internal thread to do a write when searching with other threads.
-    pub(crate) searching : bool,
+    /// insertion mode or searching mode, kept as a hint for clients. Insertions and searches can run concurrently.
+    pub(crate) searching : AtomicBool,
+    /// if true parallel_insert inserts in the order of its argument, in the calling thread
+    pub(crate) deterministic : bool,
+    /// taken shared by insertions and deletions, and exclusively by compact and repair_deleted so that no write is lost
+    pub(crate) write_gate : RwLock<()>,
 }  // end of Hnsw
 
 
@@ -579,10 +804,21 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
                 layer_indexed_points: layer_indexed_points,
                 data_dimension : 0,
                 dist_f: f,
-                searching : false,
+                searching : AtomicBool::new(false),
+                deterministic : false,
+                write_gate : RwLock::new(()),
             }
     }   // end of new
 
+    /// same as new, with the levels of the points drawn from a generator seeded with seed.
+    /// With set_deterministic(true), two constructions from the same data give the same graph.
+    pub fn new_with_seed(max_nb_connection : usize , max_elements:usize, max_layer:usize, ef_construction: usize, f:D, seed: u64) -> Self {
+        let mut hnsw = Hnsw::new(max_nb_connection, max_elements, max_layer, ef_construction, f);
+        hnsw.layer_indexed_points.layer_g = LayerGenerator::new_with_seed(max_nb_connection, hnsw.max_layer, seed);
+        log::info!("Hnsw seed {:?}", seed);
+        hnsw
+    }   // end of new_with_seed
+
     /// get ef_construction used in graph creation
     pub fn get_ef_construction(&self) -> usize {
         self.ef_construction
@@ -603,12 +839,14 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         self.layer_indexed_points.get_nb_point()
     }
     /// set searching mode.  
-    /// It is not possible to do parallel insertion and parallel searching simultaneously in different threads
-    /// so to enable searching after parallel insertion the flag must be set to true.  
-    /// To resume parallel insertion reset the flag to false and so on. 
-    pub fn set_searching_mode(&mut self, flag: bool) {
-        // must use an atomic!
-        self.searching = flag;
+    /// Insertions (insert, parallel_insert) and searches can be run simultaneously from different threads
+    /// through a shared reference, so the flag is not required anymore and is only kept as a hint.
+    pub fn set_searching_mode(&self, flag: bool) {
+        self.searching.store(flag, AtomicOrdering::Release);
+    }
+    /// get searching mode as set by set_searching_mode
+    pub fn get_searching_mode(&self) -> bool {
+        self.searching.load(AtomicOrdering::Acquire)
     }
     /// get name if distance
     pub fn get_distance_name(&self) -> String {
@@ -627,6 +865,12 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         self.extend_candidates = flag;
     }
 
+    /// deterministic insertion mode : parallel_insert inserts the points one after the other
+    /// in the order given. With a seed (see new_with_seed) the graph built is then the same at each run.
+    pub fn set_deterministic(&mut self, flag:bool) {
+        self.deterministic = flag;
+    }
+
     // multiplicative factor applied to default scale. Must between 0.5 and 1.
     // more  than 1. gives more occupied layers. This is just to experiment
     // parameters variations on the algorithm but not general use.
@@ -650,7 +894,11 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
     ///
     /// Greedy algorithm n° 2 in Malkov paper.
     /// search in a layer (layer) for the ef points nearest a point to be inserted in hnsw.
-    fn search_layer(& self, point: &Vec<T>, entry_point: Arc<Point<T>> , ef:usize, layer: u8) -> BinaryHeap<Arc<PointWithOrder<T>> > {
+    /// With an admit predicate, points it rejects (e.g. deleted ones) are explored but not returned.
+    /// The exploration is bounded as without predicate, by the ef nearest points visited, so fewer than
+    /// ef points can be returned: see search_layer_widening.
+    fn search_layer(& self, point: &[T], entry_point: Arc<Point<T>> , ef:usize, layer: u8,
+                    admit: Option<&dyn Fn(&Point<T>) -> bool>) -> BinaryHeap<Arc<PointWithOrder<T>> > {
         //
         trace!("entering search_layer with entry_point_id {:?} layer : {:?} ef {:?} ", entry_point.p_id, layer, ef);
         //
@@ -659,6 +907,9 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         let skiplist_size = ef.max(2);
         // we will store positive distances in this one
         let mut return_points = BinaryHeap::<Arc<PointWithOrder<T>> >::with_capacity(skiplist_size);
+        // with a predicate, the ef nearest points visited, admitted or not, bound the exploration.
+        // Without one they are return_points.
+        let mut nearest_visited = BinaryHeap::<Arc<PointWithOrder<T>> >::new();
         //
         if self.layer_indexed_points.points_by_layer.read()[layer as usize].len() == 0 {
             // at the beginning we can have nothing in layer
@@ -679,27 +930,35 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         let mut candidate_points = BinaryHeap::<Arc<PointWithOrder<T>> >::with_capacity(skiplist_size);
 
         candidate_points.push(Arc::new(PointWithOrder::new(&entry_point, -dist_to_entry_point)));
-        return_points.push(Arc::new(PointWithOrder::new(&entry_point, dist_to_entry_point)));
+        let admitted = |p: &Point<T>| admit.map_or(true, |f| f(p));
+        if admit.is_some() {
+            nearest_visited.push(Arc::new(PointWithOrder::new(&entry_point, dist_to_entry_point)));
+        }
+        if admitted(&entry_point) {
+            return_points.push(Arc::new(PointWithOrder::new(&entry_point, dist_to_entry_point)));
+        }
         // at the beginning candidate_points contains point passed as arg in layer entry_point_id.0
         while candidate_points.len() > 0 {
             // get nearest point in candidate_points
             let c = candidate_points.pop().unwrap();
-           // f farthest point to 
-            let f = return_points.peek().unwrap();
-            assert!(f.dist_to_ref >= 0.);
             assert!(c.dist_to_ref <= 0.);
-            log::trace!("comparaing c : {:?} f : {:?}", -(c.dist_to_ref), f.dist_to_ref);
-            if -(c.dist_to_ref) > f.dist_to_ref {
+            // f farthest of the ef nearest points visited
+            let (nb_nearest, f_dist) = {
+                let nearest = if admit.is_some() { &nearest_visited } else { &return_points };
+                (nearest.len(), nearest.peek().map_or(f32::MAX, |f| f.dist_to_ref))
+            };
+            log::trace!("comparaing c : {:?} f : {:?}", -(c.dist_to_ref), f_dist);
+            if nb_nearest > 0 && -(c.dist_to_ref) > f_dist {
                 // this comparison requires that we are sure that distances compared are distances to the same point : 
                 // This is the case we compare distance to point passed as arg.
-                log::trace!("fast return from search_layer, nb points : {:?} \n \t c {:?} \n \t f {:?} dists: {:?}  {:?}", 
-                                return_points.len(), c.point_ref.p_id, f.point_ref.p_id, -(c.dist_to_ref), f.dist_to_ref);
+                log::trace!("fast return from search_layer, nb points : {:?} \n \t c {:?} dists: {:?}  {:?}", 
+                                return_points.len(), c.point_ref.p_id, -(c.dist_to_ref), f_dist);
                 return return_points;
             }
             // now we scan neighborhood of c in layer and increment visited_point, candidate_points 
             // and optimize candidate_points so that it contains points with lowest distances to point arg
             // 
-            let neighbours_c_l = &c.point_ref.neighbours.read()[layer as usize];
+            let neighbours_c_l = &c.point_ref.neighbours_read()[layer as usize];
             let c_pid = c.point_ref.p_id;
             log::trace!("       search_layer, {:?} has  nb neighbours  : {:?} ", c_pid, neighbours_c_l.len());
             for e in neighbours_c_l {
@@ -708,23 +967,33 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
                 if visited_point_id.contains_key(&e.point_ref.p_id) != true {
                     visited_point_id.insert(e.point_ref.p_id, Arc::clone(&e.point_ref));
                     log::trace!("             visited insertion {:?}", e.point_ref.p_id);
-                    let f_opt = return_points.peek();
-                    if f_opt.is_none() {
+                    let (nb_nearest, f_dist_to_p) = {
+                        let nearest = if admit.is_some() { &nearest_visited } else { &return_points };
+                        (nearest.len(), nearest.peek().map(|f| f.dist_to_ref))
+                    };
+                    if f_dist_to_p.is_none() && admit.is_none() {
                         // do some debug info, dumped distance is from e to c! as e is in c neighbours
                         debug!("return points empty when inserting {:?}", e.point_ref.p_id);
                         return return_points;
                     }
-                    let f = f_opt.unwrap();
                     let e_dist_to_p = self.dist_f.eval(point, & e.point_ref.v);
-                    let f_dist_to_p = f.dist_to_ref;
-                    if e_dist_to_p < f_dist_to_p || return_points.len() < ef {
+                    if e_dist_to_p < f_dist_to_p.unwrap_or(f32::MAX) || nb_nearest < ef {
                         let e_prime = Arc::new(PointWithOrder::new(&e.point_ref, e_dist_to_p));
                         // a neighbour of neighbour is better, we insert it into candidate with the distance to point
                         log::trace!("                inserting new candidate {:?}", e_prime.point_ref.p_id);
                         candidate_points.push(Arc::new(PointWithOrder::new(&e.point_ref, -e_dist_to_p)));
-                        return_points.push(Arc::clone(&e_prime));
-                        if return_points.len() > ef {
-                            return_points.pop();
+                        if admit.is_some() {
+                            nearest_visited.push(Arc::clone(&e_prime));
+                            if nearest_visited.len() > ef {
+                                nearest_visited.pop();
+                            }
+                        }
+                        // a rejected point is a path to its neighbours, not a result
+                        if admitted(&e.point_ref) {
+                            return_points.push(Arc::clone(&e_prime));
+                            if return_points.len() > ef {
+                                return_points.pop();
+                            }
                         }
                     } // end if e.dist_to_ref < f.dist_to_ref
                 }
@@ -740,37 +1009,43 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
     ///  Insert a data vector with its external id as given by the client.   
     ///  The insertion method gives the point an internal id.
    pub fn insert(&self, data_with_id: (&Vec<T>,usize))  {
+        let _writing = self.write_gate.read();
+        self.insert_point(data_with_id, false);
+    } // end of insert
+
+
+    /// Insert a data vector with its external id, replacing the point previously inserted with the same id:
+    /// the previous point is marked as deleted, as with delete. Returns true if a live point was replaced.
+    pub fn upsert(&self, data_with_id: (&Vec<T>,usize)) -> bool {
+        let _writing = self.write_gate.read();
+        self.insert_point(data_with_id, true)
+    } // end of upsert
+
+
+    // insertion proper, called with the write gate taken
+    fn insert_point(&self, data_with_id: (&Vec<T>,usize), replace : bool) -> bool {
         //
         let (data , origin_id) = data_with_id;
         let keep_pruned = self.keep_pruned;
         // insert in indexation and get point_id adn generate a new entry_point if necessary
-        let (new_point, point_rank) = self.layer_indexed_points.generate_new_point(data, origin_id);
+        let (new_point, _point_rank, replaced) = self.layer_indexed_points.generate_new_point(data, origin_id, replace);
         log::trace!("\n\n Hnsw insert generated new point {:?} ", new_point.p_id);
         // now real work begins
         // allocate a binary heap
         let level = new_point.p_id.0;
-        let mut enter_point_copy = None;
-        let mut max_level_observed = 0;
-        // entry point has been set in 
-        { // I open a read lock on an option
-            if let Some(arc_point) = self.layer_indexed_points.entry_point.read().as_ref() {
-                enter_point_copy = Some(Arc::clone(&arc_point));
-                if point_rank == 1 {
-                    log::debug!("Hnsw  stored first point , direct return  {:?} ", new_point.p_id);
-                    return;
-                }
-                max_level_observed = enter_point_copy.as_ref().unwrap().p_id.0;
+        let mut enter_point_copy = match self.layer_indexed_points.get_or_init_entry_point(&new_point) {
+            Some(arc_point) => Some(arc_point),
+            None => {
+                log::debug!("Hnsw  stored first point , direct return  {:?} ", new_point.p_id);
+                return replaced;
             }
-        }
-        if enter_point_copy.is_none() {
-                self.layer_indexed_points.check_entry_point(&new_point);
-                return;
-        }
+        };
+        let max_level_observed = enter_point_copy.as_ref().unwrap().p_id.0;
         let mut dist_to_entry = self.dist_f.eval(data , & enter_point_copy.as_ref().unwrap().v);
         // we go from self.max_level_observed to level+1 included
         for l in ((level+1)..(max_level_observed+1)).rev() {
             // CAVEAT could bypass when layer empty, avoid  allocation..
-            let mut sorted_points = self.search_layer(&data, Arc::clone(enter_point_copy.as_ref().unwrap()), 1, l);
+            let mut sorted_points = self.search_layer(&data, Arc::clone(enter_point_copy.as_ref().unwrap()), 1, l, None);
             log::trace!("in insert :search_layer layer {:?}, returned {:?} points ", l, sorted_points.len());
             if sorted_points.len() > 1 {
                 panic!("in insert : search_layer layer {:?}, returned {:?} points ", l, sorted_points.len());
@@ -796,7 +1071,7 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         for l in (0..level+1).rev() {
             let ef = self.ef_construction;
             // when l == level, we cannot get new_point in sorted_points as it is seen only from declared neighbours
-            let mut sorted_points = self.search_layer(&data, Arc::clone(enter_point_copy.as_ref().unwrap()), ef, l);
+            let mut sorted_points = self.search_layer(&data, Arc::clone(enter_point_copy.as_ref().unwrap()), ef, l, None);
             log::trace!("in insert :search_layer layer {:?}, returned {:?} points ", l, sorted_points.len());
             sorted_points = from_positive_binaryheap_to_negative_binary_heap(&mut sorted_points);
             if sorted_points.len() > 0 {
@@ -815,7 +1090,7 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
                 // sort neighbours 
                 neighbours.sort_unstable();
                 // we must add bidirecti*onal from data i.e new_point_id to neighbours
-                new_point.neighbours.write()[l as usize] = neighbours.clone(); 
+                new_point.neighbours_write()[l as usize] = neighbours.clone(); 
                 // this reverse neighbour update could be done here but we put it at end to gather all code
                 // requiring a mutex guard for multi threading.
                 // update ep for loop iteration. As we sorted neighbours the nearest 
@@ -833,17 +1108,247 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         self.layer_indexed_points.check_entry_point(&new_point);
         //
         log::trace!("Hnsw exiting insert new point {:?} ", new_point.p_id);
-    } // end of insert
+        replaced
+    } // end of insert_point
 
 
     /// Insert in parallel a slice of Vec<T> each associated to its id.    
     /// It uses Rayon for threading so the number of insertions asked for must be large enough to be efficient.  
     /// Typically 1000 * the number of threads.
+    /// If set_deterministic(true) was called, insertions are done sequentially in the order of datas.
     pub fn parallel_insert(&self, datas: &Vec<(&Vec<T>, usize)> ) {
-        datas.par_iter().for_each( |&item| self.insert(item));
+        if self.deterministic {
+            datas.iter().for_each( |&item| self.insert(item));
+        }
+        else {
+            datas.par_iter().for_each( |&item| self.insert(item));
+        }
     }  // end of parallel_insert
 
 
+    /// Marks the point inserted with data_id as deleted.  
+    /// The point stays in the graph: searches still go through it to reach its neighbours
+    /// but do not return it. Returns false if there is no such point or if it is already deleted.  
+    /// After many deletions, repair_deleted reconnects the graph around deleted points.
+    pub fn delete(&self, data_id: DataId) -> bool {
+        let _writing = self.write_gate.read();
+        let point = match self.layer_indexed_points.get_point(data_id) {
+            Some(point) => point,
+            None => return false,
+        };
+        if point.deleted.swap(true, AtomicOrdering::AcqRel) {
+            return false;
+        }
+        *self.layer_indexed_points.nb_deleted.write() += 1;
+        log::debug!("Hnsw deleted point {:?} origin id {:?}", point.p_id, data_id);
+        true
+    } // end of delete
+
+    /// returns true if the point inserted with data_id has been deleted
+    pub fn is_deleted(&self, data_id: DataId) -> bool {
+        match self.layer_indexed_points.get_point(data_id) {
+            Some(point) => point.is_deleted(),
+            None => false,
+        }
+    }
+
+    /// returns number of points marked as deleted, they are counted in get_nb_point
+    pub fn get_nb_deleted(&self) -> usize {
+        self.layer_indexed_points.get_nb_deleted()
+    }
+
+    /// Reconnects the graph around deleted points.  
+    /// In each layer of each live point, deleted neighbours are replaced by their own live
+    /// neighbours, and the neighbourhood is selected again with the insertion heuristic.
+    /// If the entry point is deleted, the live point of highest layer replaces it.  
+    /// Returns the number of neighbourhoods (point, layer) modified.  
+    /// Insertions and deletions called meanwhile wait for the end of the repair, as with compact.
+    pub fn repair_deleted(&self) -> Result<usize, rayon::ThreadPoolBuildError> {
+        if self.get_nb_deleted() == 0 || self.get_nb_point() == 0 {
+            return Ok(0);
+        }
+        self.run_exclusive(|| self.repair_deleted_locked())
+    } // end of repair_deleted
+
+    fn repair_deleted_locked(&self) -> usize {
+        let live_points : Vec<Arc<Point<T>>> = self.layer_indexed_points.into_iter().filter(|p| !p.is_deleted()).collect();
+        let nb_repaired : usize = live_points.par_iter().map(|point| self.repair_point(point)).sum();
+        self.elect_entry_point();
+        log::info!("Hnsw repair_deleted modified {:?} neighbourhoods", nb_repaired);
+        nb_repaired
+    } // end of repair_deleted_locked
+
+    // replaces deleted neighbours of point. No two locks are held at the same time
+    fn repair_point(&self, point: &Arc<Point<T>>) -> usize {
+        let mut nb_repaired = 0;
+        for l in 0..=point.p_id.0 {
+            let current = point.neighbours_read()[l as usize].clone();
+            if !current.iter().any(|n| n.point_ref.is_deleted()) {
+                continue;
+            }
+            let mut candidates = self.live_neighbour_candidates(point, l);
+            let nb_conn = if l == 0 { 2 * self.max_nb_connection } else { self.max_nb_connection };
+            let mut neighbours = Vec::<Arc<PointWithOrder<T>> >::with_capacity(nb_conn);
+            self.select_neighbours(&point.v, &mut candidates, nb_conn, false, l, self.keep_pruned, &mut neighbours);
+            neighbours.sort_unstable();
+            point.neighbours_write()[l as usize] = neighbours;
+            nb_repaired += 1;
+        }
+        nb_repaired
+    } // end of repair_point
+
+    // candidates to replace the neighbours of point in layer l: its live neighbours and the live
+    // neighbours of its deleted neighbours. Returned with negative distances as select_neighbours expects.
+    fn live_neighbour_candidates(&self, point: &Arc<Point<T>>, l: u8) -> BinaryHeap<Arc<PointWithOrder<T>>> {
+        let current = point.neighbours_read()[l as usize].clone();
+        let mut candidate_points = HashMap::<PointId, Arc<Point<T>>>::new();
+        for n in &current {
+            if n.point_ref.is_deleted() {
+                let second_neighbours = n.point_ref.neighbours_read()[l as usize].clone();
+                for q in second_neighbours {
+                    if !q.point_ref.is_deleted() && q.point_ref.p_id != point.p_id {
+                        candidate_points.insert(q.point_ref.p_id, Arc::clone(&q.point_ref));
+                    }
+                }
+            }
+            else {
+                candidate_points.insert(n.point_ref.p_id, Arc::clone(&n.point_ref));
+            }
+        }
+        candidate_points.values()
+                .map(|q| Arc::new(PointWithOrder::new(q, -self.dist_f.eval(&point.v, &q.v))))
+                .collect()
+    } // end of live_neighbour_candidates
+
+    // candidates for the neighbours of point in layer l, searched in the graph with ef_construction
+    // as at insertion, deleted points skipped. Returned with negative distances.
+    fn search_live_candidates(&self, point: &Arc<Point<T>>, l: u8) -> BinaryHeap<Arc<PointWithOrder<T>>> {
+        let mut entry_point = match self.layer_indexed_points.entry_point.read().as_ref() {
+            Some(entry_point) => Arc::clone(entry_point),
+            None => return BinaryHeap::new(),
+        };
+        let mut dist_to_entry = self.dist_f.eval(&point.v, &entry_point.v);
+        for layer in ((l+1)..=entry_point.p_id.0).rev() {
+            let mut neighbours = self.search_layer(&point.v, Arc::clone(&entry_point), 1, layer, None);
+            if let Some(nearest) = neighbours.pop() {
+                if nearest.dist_to_ref < dist_to_entry {
+                    dist_to_entry = nearest.dist_to_ref;
+                    entry_point = Arc::clone(&nearest.point_ref);
+                }
+            }
+        }
+        let candidates = self.search_layer_widening(&point.v, entry_point, self.ef_construction, self.ef_construction, l,
+                    &|p: &Point<T>| !p.is_deleted());
+        candidates.into_iter()
+                .filter(|c| c.point_ref.p_id != point.p_id)
+                .map(|c| Arc::new(PointWithOrder::new(&c.point_ref, -c.dist_to_ref)))
+                .collect()
+    } // end of search_live_candidates
+
+    /// Rebuilds the graph with the live points only: deleted points, and points replaced by a later
+    /// insertion with the same id, are dropped and the memory they used is released.  
+    /// With reuse_neighbours the neighbourhoods start from the old ones, deleted neighbours being
+    /// replaced by their live neighbours as in repair_deleted. Otherwise each neighbourhood is searched
+    /// again in the old graph with ef_construction: slower, but it restores recall after heavy churn.  
+    /// The new graph is built aside while the old one stays searchable, then swapped in.
+    /// Insertions and deletions called meanwhile wait for the swap and then go to the new graph.  
+    /// Fails only if the threads of the rebuild cannot be created.
+    pub fn compact(&self, reuse_neighbours: bool) -> Result<CompactionReport, rayon::ThreadPoolBuildError> {
+        self.run_exclusive(|| self.compact_locked(reuse_neighbours))
+    } // end of compact
+
+    // runs f with the write gate held exclusively, on threads of its own: workers of the global
+    // rayon pool can be blocked on the write gate by insertions waiting for f to end
+    fn run_exclusive<R: Send>(&self, f: impl FnOnce() -> R + Send) -> Result<R, rayon::ThreadPoolBuildError> {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(rayon::current_num_threads()).build()?;
+        let _writing = self.write_gate.write();
+        Ok(pool.install(f))
+    } // end of run_exclusive
+
+    fn compact_locked(&self, reuse_neighbours: bool) -> CompactionReport {
+        let before = self.get_graph_stats();
+        let live_points : Vec<Arc<Point<T>>> = match self.get_nb_point() {
+            0 => Vec::new(),
+            _ => self.layer_indexed_points.into_iter().filter(|p| !p.is_deleted()).collect(),
+        };
+        // new neighbourhoods of each live point, as (old point id, distance) for each layer
+        let neighbourhoods : Vec<Vec<Vec<(PointId, f32)>>> = live_points.par_iter().map(|point| {
+            (0..=point.p_id.0).map(|l| {
+                let mut candidates = if reuse_neighbours {
+                    self.live_neighbour_candidates(point, l)
+                } else {
+                    self.search_live_candidates(point, l)
+                };
+                let nb_conn = if l == 0 { 2 * self.max_nb_connection } else { self.max_nb_connection };
+                let mut neighbours = Vec::<Arc<PointWithOrder<T>> >::with_capacity(nb_conn);
+                self.select_neighbours(&point.v, &mut candidates, nb_conn, false, l, self.keep_pruned, &mut neighbours);
+                neighbours.sort_unstable();
+                neighbours.iter().map(|n| (n.point_ref.p_id, n.dist_to_ref)).collect()
+            }).collect()
+        }).collect();
+        // new points keep their layer, their rank in the layer is reassigned
+        let nb_layer = self.layer_indexed_points.points_by_layer.read().len();
+        let mut points_by_layer : Vec<Vec<Arc<Point<T>>>> = (0..nb_layer).map(|_| Vec::new()).collect();
+        let mut new_points = HashMap::<PointId, Arc<Point<T>>>::with_capacity(live_points.len());
+        let mut origin_index = HashMap::<DataId, PointId>::with_capacity(live_points.len());
+        for point in &live_points {
+            let layer = point.p_id.0 as usize;
+            let p_id = PointId(point.p_id.0, points_by_layer[layer].len() as i32);
+            let new_point = Arc::new(Point::from_data(point.v.clone(), point.origin_id, p_id));
+            points_by_layer[layer].push(Arc::clone(&new_point));
+            origin_index.insert(point.origin_id, p_id);
+            new_points.insert(point.p_id, new_point);
+        }
+        for (point, neighbourhood) in live_points.iter().zip(neighbourhoods.into_iter()) {
+            let mut neighbours = new_points[&point.p_id].neighbours_write();
+            for (l, neighbours_l) in neighbourhood.into_iter().enumerate() {
+                neighbours[l] = neighbours_l.into_iter()
+                        .map(|(p_id, dist)| Arc::new(PointWithOrder::new(&new_points[&p_id], dist)))
+                        .collect();
+            }
+        }
+        // the old entry point if it is live, else a point of the highest layer
+        let old_entry_id = self.layer_indexed_points.entry_point.read().as_ref().map(|p| p.p_id);
+        let entry_point = match old_entry_id.and_then(|p_id| new_points.get(&p_id)) {
+            Some(point) => Some(Arc::clone(point)),
+            None => points_by_layer.iter().rev().find(|layer| layer.len() > 0).map(|layer| Arc::clone(&layer[0])),
+        };
+        // swap, taking the locks in the same order as the rest of the structure
+        {
+            let mut entry_point_ref = self.layer_indexed_points.entry_point.write();
+            let mut points_by_layer_ref = self.layer_indexed_points.points_by_layer.write();
+            *points_by_layer_ref = points_by_layer;
+            *entry_point_ref = entry_point;
+            *self.layer_indexed_points.origin_index.write() = origin_index;
+            *self.layer_indexed_points.nb_point.write() = live_points.len();
+            *self.layer_indexed_points.nb_deleted.write() = 0;
+        }
+        let report = CompactionReport{before, after: self.get_graph_stats()};
+        log::info!("Hnsw compaction \n{}", report);
+        report
+    } // end of compact_locked
+
+    // if the entry point is deleted, replace it by a live point of the highest possible layer
+    fn elect_entry_point(&self) {
+        let mut entry_point_ref = self.layer_indexed_points.entry_point.write();
+        let deleted = match entry_point_ref.as_ref() {
+            Some(point) => point.is_deleted(),
+            None => false,
+        };
+        if !deleted {
+            return;
+        }
+        let points_by_layer = self.layer_indexed_points.points_by_layer.read();
+        for layer in points_by_layer.iter().rev() {
+            if let Some(point) = layer.iter().find(|p| !p.is_deleted()) {
+                log::debug!("Hnsw new entry point {:?} replacing deleted one", point.p_id);
+                *entry_point_ref = Some(Arc::clone(point));
+                return;
+            }
+        }
+    } // end of elect_entry_point
+
+
 
 
     /// insert new_point in neighbourhood info of point
@@ -852,11 +1357,14 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         log::trace!("reverse update neighbourhood for  new point {:?} ", new_point.p_id);
         let level = new_point.p_id.0;
         for l in (0..level+1).rev() {
-            for q in &new_point.neighbours.read()[l as usize] {
+            // copy the neighbours so that no lock on new_point is held while locking theirs: a concurrent
+            // insertion of one of them could be waiting for new_point the other way round.
+            let neighbours_l = new_point.neighbours_read()[l as usize].clone();
+            for q in &neighbours_l {
                 if new_point.p_id != q.point_ref.p_id {
                     // as new point is in global table, do not loop and deadlock!!
                     let q_point = &q.point_ref;
-                    let mut q_point_neighbours = q_point.neighbours.write();
+                    let mut q_point_neighbours = q_point.neighbours_write();
                     let n_to_add = PointWithOrder::<T>::new(&Arc::clone(&new_point), q.dist_to_ref);
                     // must be sure that we add a point at the correct level
                     let l_n = n_to_add.point_ref.p_id.0 as usize;
@@ -944,17 +1452,20 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
                     candidates_set.insert(c.point_ref.p_id, Arc::clone(&c.point_ref));
             }
             let mut new_candidates_set = HashMap::<PointId, Arc<Point<T>> >::new();
+            // keep new candidates in discovery order, hash map iteration order varies between runs
+            let mut new_candidates = Vec::<Arc<Point<T>>>::new();
             // get a list of all neighbours of candidates
-            for (_p_id, p_point) in candidates_set.iter() {
-                let n_p_layer = &p_point.neighbours.read()[layer as usize];
+            for c in candidates.iter() {
+                let n_p_layer = &c.point_ref.neighbours_read()[layer as usize];
                 for q in n_p_layer {
                     if !candidates_set.contains_key(& q.point_ref.p_id) && !new_candidates_set.contains_key(& q.point_ref.p_id) { 
                         new_candidates_set.insert(q.point_ref.p_id, Arc::clone(&q.point_ref));
+                        new_candidates.push(Arc::clone(&q.point_ref));
                     }
                 }
             } // end of for p
-            log::trace!("select neighbours extend candidates from  : {:?} adding : {:?}", candidates.len(), new_candidates_set.len());
-            for (_p_id, p_point) in new_candidates_set.iter() {
+            log::trace!("select neighbours extend candidates from  : {:?} adding : {:?}", candidates.len(), new_candidates.len());
+            for p_point in new_candidates.iter() {
                 let dist_topoint = self.dist_f.eval(data, &p_point.v);
                 candidates.push(Arc::new(PointWithOrder::new(p_point, -dist_topoint)));
             }
@@ -1033,7 +1544,7 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         //
         let mut dist_to_entry = self.dist_f.eval(data , & entry_point.as_ref().v);
         for layer in (1..=entry_point.p_id.0).rev() {
-            let mut neighbours = self.search_layer(data, Arc::clone(&entry_point), 1, layer);
+            let mut neighbours = self.search_layer(data, Arc::clone(&entry_point), 1, layer, None);
             neighbours = from_positive_binaryheap_to_negative_binary_heap(&mut neighbours);
             if let Some(entry_point_tmp) = neighbours.pop() {
                 // get the lowest  distance point.
@@ -1047,7 +1558,7 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         // ef must be greater than knbn. Possibly it should be between knbn and self.max_nb_connection
         let ef = ef_arg.max(knbn);
         // now search with asked ef in layer 0
-        let neighbours_heap = self.search_layer(data, entry_point, ef, 0);
+        let neighbours_heap = self.search_layer(data, entry_point, ef, 0, Some(&|p: &Point<T>| !p.is_deleted()));
         // go from heap of points with negative dist to a sorted vec of increasing points with > 0 distances.
         let neighbours = neighbours_heap.into_sorted_vec();
         // get the min of K and ef points into a vector.
@@ -1065,12 +1576,29 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
     /// than number of neighbours asked.  
     /// A rule of thumb could be between knbn and max_nb_connection.
     pub fn search(&self, data :&Vec<T> , knbn:usize, ef_arg:usize) -> Vec<Neighbor> {
+        self.search_admitted(data, knbn, ef_arg, &|p: &Point<T>| !p.is_deleted())
+    }
+
+
+    /// search the first knbn nearest neigbours of a data among the points whose data id satisfies filter.  
+    /// Points rejected by the filter are still traversed, as paths to their neighbours, but never returned.
+    /// If fewer than knbn matching points are found, the search is run again with ef doubled, up to
+    /// MAX_EF_WIDENING times ef: a very selective filter can return fewer than knbn points.
+    pub fn search_filter(&self, data :&Vec<T> , knbn:usize, ef_arg:usize, filter: &dyn Fn(DataId) -> bool) -> Vec<Neighbor> {
+        self.search_admitted(data, knbn, ef_arg, &|p: &Point<T>| !p.is_deleted() && filter(p.origin_id))
+    }
+
+
+    // search in layer 0 only returns points accepted by admit, upper layers are searched as usual.
+    // greedy descent from the entry point through the upper layers, returns the point of layer 0
+    // the search in layer 0 starts from. None if the graph is empty.
+    fn descend_to_layer0(&self, data :&Vec<T>) -> Option<Arc<Point<T>>> {
         //
         let entry_point;
         {  // a lock on an option an a Arc<Point>
             let entry_point_opt_ref = self.layer_indexed_points.entry_point.read();
             if entry_point_opt_ref.is_none() {
-                return Vec::<Neighbor>::new();
+                return None;
             }
             else {
               entry_point =  Arc::clone((*entry_point_opt_ref).as_ref().unwrap()); 
@@ -1085,7 +1613,7 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
             let mut has_changed = false;
             // search in stored neighbours
             {
-                let neighbours = &pivot.neighbours.read()[layer as usize];
+                let neighbours = &pivot.neighbours_read()[layer as usize];
                 for n in neighbours {
                     // get the lowest  distance point.
                     let tmp_dist = self.dist_f.eval(data , & n.point_ref.v);
@@ -1100,15 +1628,43 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
                 pivot =  Arc::clone(new_pivot.as_ref().unwrap());
             }
         } // end on for on layers
+        Some(pivot)
+    } // end of descend_to_layer0
+
+
+    // search_layer with the predicate admit, run again with ef doubled while fewer than nb_wanted points
+    // are admitted, up to MAX_EF_WIDENING times ef (and the number of points). The cost of a search that
+    // admits almost nothing is so bounded, at the price of returning fewer than nb_wanted points.
+    fn search_layer_widening(&self, data: &[T], entry_point: Arc<Point<T>>, ef: usize, nb_wanted: usize, layer: u8,
+                    admit: &dyn Fn(&Point<T>) -> bool) -> BinaryHeap<Arc<PointWithOrder<T>>> {
+        let max_ef = (ef * MAX_EF_WIDENING).min(self.get_nb_point()).max(ef);
+        let mut ef_search = ef;
+        loop {
+            let neighbours = self.search_layer(data, Arc::clone(&entry_point), ef_search, layer, Some(admit));
+            if neighbours.len() >= nb_wanted || ef_search >= max_ef {
+                log::trace!("search_layer_widening stops at ef {:?} with {:?} points", ef_search, neighbours.len());
+                return neighbours;
+            }
+            ef_search = (2 * ef_search).min(max_ef);
+        }
+    } // end of search_layer_widening
+
+
+    fn search_admitted(&self, data :&Vec<T> , knbn:usize, ef_arg:usize, admit: &dyn Fn(&Point<T>) -> bool) -> Vec<Neighbor> {
+        //
+        let pivot = match self.descend_to_layer0(data) {
+            Some(pivot) => pivot,
+            None => return Vec::<Neighbor>::new(),
+        };
         // ef must be greater than knbn. Possibly it should be between knbn and self.max_nb_connection
         let ef = ef_arg.max(knbn);
-        // now search with asked ef in layer 0
-        let neighbours_heap = self.search_layer(data, pivot, ef, 0);
+        // now search in layer 0, widening ef if admit rejects too many points
+        let neighbours_heap = self.search_layer_widening(data, pivot, ef, knbn, 0, admit);
         // go from heap of points with negative dist to a sorted vec of increasing points with > 0 distances.
         let neighbours = neighbours_heap.into_sorted_vec();
         // get the min of K and ef points into a vector.
         //
-        let last = knbn.min(ef).min(neighbours.len());
+        let last = knbn.min(neighbours.len());
         let knn_neighbours : Vec<Neighbor> = 
                 neighbours[0..last].iter().map(|p| Neighbor::new(p.as_ref().point_ref.origin_id, p.as_ref().dist_to_ref, p.as_ref().point_ref.p_id)).collect();
 
@@ -1116,6 +1672,55 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
     } // end of knn_search
 
 
+    /// search all the points at distance at most radius of data, sorted by increasing distance.  
+    /// The greedy descent and a search with width ef in layer 0 give the starting points, then every
+    /// point found within radius has its neighbours examined, until no candidate left can be within radius.
+    /// ef mainly matters when few points are within radius, a rule of thumb is max_nb_connection.
+    /// The result is not bounded in size: a radius too large returns a large part of the graph.
+    pub fn search_range(&self, data :&Vec<T> , radius: f32, ef:usize) -> Vec<Neighbor> {
+        //
+        let pivot = match self.descend_to_layer0(data) {
+            Some(pivot) => pivot,
+            None => return Vec::<Neighbor>::new(),
+        };
+        let seeds = self.search_layer(data, pivot, ef.max(1), 0, Some(&|p: &Point<T>| !p.is_deleted()));
+        //
+        let mut visited_point_id = HashMap::<PointId, ()>::new();
+        // negative distances, so that the nearest candidate is on top
+        let mut candidate_points = BinaryHeap::<Arc<PointWithOrder<T>> >::with_capacity(seeds.len());
+        let mut in_range = Vec::<Neighbor>::new();
+        for s in seeds.into_iter() {
+            visited_point_id.insert(s.point_ref.p_id, ());
+            if s.dist_to_ref <= radius {
+                in_range.push(Neighbor::new(s.point_ref.origin_id, s.dist_to_ref, s.point_ref.p_id));
+            }
+            candidate_points.push(Arc::new(PointWithOrder::new(&s.point_ref, -s.dist_to_ref)));
+        }
+        while let Some(c) = candidate_points.pop() {
+            if -c.dist_to_ref > radius {
+                // the remaining candidates are all farther
+                break;
+            }
+            let neighbours_c_l = &c.point_ref.neighbours_read()[0];
+            for e in neighbours_c_l {
+                if visited_point_id.insert(e.point_ref.p_id, ()).is_some() {
+                    continue;
+                }
+                let e_dist_to_p = self.dist_f.eval(data, & e.point_ref.v);
+                if e_dist_to_p <= radius {
+                    // a deleted point is a path to its neighbours, not a result
+                    if !e.point_ref.is_deleted() {
+                        in_range.push(Neighbor::new(e.point_ref.origin_id, e_dist_to_p, e.point_ref.p_id));
+                    }
+                    candidate_points.push(Arc::new(PointWithOrder::new(&e.point_ref, -e_dist_to_p)));
+                }
+            }
+        }
+        in_range.sort_unstable_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Ordering::Equal));
+        in_range
+    } // end of search_range
+
+
 
     fn search_with_id(&self, request : (usize, &Vec<T>) , knbn:usize, ef:usize) -> (usize, Vec<Neighbor>) {
         (request.0, self.search(request.1, knbn, ef))
@@ -1146,6 +1751,15 @@ impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync > Hnsw<T,D>  {
         answers
     }  // end of insert_parallel
 
+
+    /// parallel version of search_filter. Returns for each data vector a Vector of Neighbor
+    /// whose data ids satisfy filter, in the order of datas.
+    pub fn parallel_search_filter(&self, datas: &Vec<Vec<T>>, knbn:usize, ef:usize,
+                                  filter: &(dyn Fn(DataId) -> bool + Sync)) -> Vec<Vec<Neighbor >> {
+        // par_iter().map().collect() keeps the order of the requests
+        datas.par_iter().map(|data| self.search_filter(data, knbn, ef, filter)).collect()
+    }  // end of parallel_search_filter
+
 } // end of Hnsw
 
 
@@ -1219,10 +1833,11 @@ pub(crate) fn check_graph_equality<T1, D1, T2, D2>(hnsw1:&Hnsw<T1,D1>, hnsw2: &H
             let p2 = &layers_2[i][j];
             assert_eq!(p1.origin_id, p2.origin_id);
             assert_eq!(p1.p_id, p2.p_id, "\n checking origin_id point {:?} ", p1.origin_id);
+            assert_eq!(p1.is_deleted(), p2.is_deleted(), "deleted state of point {:?}", p1.origin_id);
             nb_point_checked += 1;
             // check neighborhood
-            let nbgh1 = p1.neighbours.read();
-            let nbgh2 = p2.neighbours.read();
+            let nbgh1 = p1.neighbours_read();
+            let nbgh2 = p2.neighbours_read();
             assert_eq!(nbgh1.len(), nbgh2.len());
             for k in 0..nbgh1.len() {
                 assert_eq!(nbgh1[k].len(), nbgh2[k].len());
@@ -1304,4 +1919,335 @@ fn test_iter_point() {
     assert_eq!(nb_dumped, nbcolumn);
 } // end of test_insert_iter_point
 
+
+#[test]
+fn test_delete_and_repair() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 2000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let hns = Hnsw::<f32, DistL2>::new(10, nbcolumn, 16, 50, DistL2{});
+    for i in 0..data.len() {
+        hns.insert((&data[i], i));
+    }
+    // a point is its own nearest neighbour until it is deleted
+    assert_eq!(hns.search(&data[7], 1, 30)[0].d_id, 7);
+    assert!(hns.delete(7));
+    assert!(!hns.delete(7));
+    assert!(!hns.delete(nbcolumn + 1));
+    assert!(hns.is_deleted(7));
+    assert_eq!(hns.get_nb_deleted(), 1);
+    assert_eq!(hns.get_nb_point(), nbcolumn);
+    // delete one point out of 4, entry point included
+    let entry_id = hns.layer_indexed_points.entry_point.read().as_ref().unwrap().get_origin_id();
+    hns.delete(entry_id);
+    for i in (0..nbcolumn).step_by(4) {
+        hns.delete(i);
+    }
+    let is_deleted = |id : usize| id == 7 || id == entry_id || id % 4 == 0;
+    for i in 0..100 {
+        let neighbours = hns.search(&data[i], 10, 30);
+        assert_eq!(neighbours.len(), 10);
+        assert!(neighbours.iter().all(|n| !is_deleted(n.d_id)));
+    }
+    //
+    assert!(hns.repair_deleted().unwrap() > 0);
+    assert!(!hns.layer_indexed_points.entry_point.read().as_ref().unwrap().is_deleted());
+    // no live point is linked to a deleted one anymore
+    for point in hns.get_point_indexation() {
+        if !point.is_deleted() {
+            for layer in point.neighbours_read().iter() {
+                assert!(layer.iter().all(|n| !n.point_ref.is_deleted()));
+            }
+        }
+    }
+    let mut nb_found = 0;
+    for i in (1..nbcolumn).filter(|i| !is_deleted(*i)).take(200) {
+        let neighbours = hns.search(&data[i], 10, 30);
+        assert!(neighbours.iter().all(|n| !is_deleted(n.d_id)));
+        if neighbours[0].d_id == i {
+            nb_found += 1;
+        }
+    }
+    assert!(nb_found >= 190, "nb_found {}", nb_found);
+} // end of test_delete_and_repair
+
+
+#[test]
+fn test_compact() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 2000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    for reuse_neighbours in [true, false].iter() {
+        let hns = Hnsw::<f32, DistL2>::new(10, nbcolumn, 16, 50, DistL2{});
+        for i in 0..data.len() {
+            hns.insert((&data[i], i));
+        }
+        // half the points deleted, 100 updated: inserted again with the same id
+        for i in (0..nbcolumn).step_by(2) {
+            hns.delete(i);
+        }
+        for i in (1..200).step_by(2) {
+            assert!(hns.upsert((&data[i - 1], i)));
+        }
+        assert!(!hns.is_deleted(1));
+        assert_eq!(hns.get_nb_deleted(), nbcolumn / 2 + 100);
+        //
+        let report = hns.compact(*reuse_neighbours).unwrap();
+        assert_eq!(report.before.nb_point, nbcolumn + 100);
+        assert_eq!(report.after.nb_point, nbcolumn / 2);
+        assert_eq!(report.nb_removed(), nbcolumn / 2 + 100);
+        assert_eq!(report.after.nb_deleted, 0);
+        assert_eq!(report.after.nb_links_to_deleted, 0);
+        assert_eq!(hns.get_point_indexation().into_iter().count(), nbcolumn / 2);
+        // updated ids now hold their new vector
+        assert_eq!(hns.search(&data[0], 1, 30)[0].d_id, 1);
+        let mut nb_found = 0;
+        for i in (201..nbcolumn).step_by(2).take(200) {
+            if hns.search(&data[i], 1, 30)[0].d_id == i {
+                nb_found += 1;
+            }
+        }
+        assert!(nb_found >= 190, "nb_found {} reuse_neighbours {}", nb_found, reuse_neighbours);
+        // the compacted graph takes deletions and insertions
+        assert!(hns.delete(201));
+        hns.insert((&data[0], nbcolumn));
+        assert_eq!(hns.get_nb_point(), nbcolumn / 2 + 1);
+    }
+} // end of test_compact
+
+
+#[test]
+fn test_insert_keeps_previous_point() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 500;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let hns = Hnsw::<f32, DistL2>::new(10, nbcolumn, 16, 50, DistL2{});
+    for i in 0..data.len() {
+        hns.insert((&data[i], i));
+    }
+    // insert with an existing id adds a point, the previous one stays live
+    hns.insert((&data[1], 0));
+    assert_eq!(hns.get_nb_deleted(), 0);
+    assert_eq!(hns.get_nb_point(), nbcolumn + 1);
+    let ids : Vec<DataId> = hns.search(&data[0], 2, 30).iter().map(|n| n.d_id).collect();
+    assert!(ids.contains(&0));
+    // upsert replaces it
+    assert!(hns.upsert((&data[2], 3)));
+    assert_eq!(hns.get_nb_deleted(), 1);
+    // the previous point 3, at distance 0, is not returned anymore
+    assert!(hns.search(&data[3], 5, 30).iter().all(|n| !(n.d_id == 3 && n.distance == 0.)));
+    assert!(hns.delete(3));
+    assert!(!hns.upsert((&data[3], 3)));
+    assert!(!hns.upsert((&data[3], nbcolumn)));
+} // end of test_insert_keeps_previous_point
+
+
+#[test]
+fn test_compact_with_concurrent_writes() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 4000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let data_with_id : Vec<(&Vec<f32>, usize)> = data.iter().zip(0..nbcolumn).collect();
+    let hns = Hnsw::<f32, DistL2>::new(10, nbcolumn, 16, 50, DistL2{});
+    hns.parallel_insert(&data_with_id[0..2000].to_vec());
+    for i in (0..2000).step_by(2) {
+        hns.delete(i);
+    }
+    // insertions, deletions and searches while compacting: none of the writes is lost
+    let (report, _) = rayon::join(
+        || hns.compact(false).unwrap(),
+        || {
+            for chunk in data_with_id[2000..].chunks(200) {
+                hns.parallel_insert(&chunk.to_vec());
+                hns.search(&data[1], 5, 30);
+            }
+            for i in (1..1000).step_by(2) {
+                hns.delete(i);
+            }
+        },
+    );
+    assert!(report.after.nb_point <= 1000 + 2000);
+    assert_eq!(hns.get_nb_point() - hns.get_nb_deleted(), 500 + 2000);
+    for i in 0..nbcolumn {
+        let live = (i >= 1000 && i % 2 == 1) || i >= 2000;
+        assert_eq!(hns.get_point_indexation().get_point(i).map_or(false, |p| !p.is_deleted()), live, "point {}", i);
+    }
+    let nb_found = (2000..nbcolumn).step_by(10).filter(|i| hns.search(&data[*i], 1, 30)[0].d_id == *i).count();
+    assert!(nb_found >= 190, "nb_found {}", nb_found);
+} // end of test_compact_with_concurrent_writes
+
+
+#[test]
+fn test_search_filter() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 2000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let hns = Hnsw::<f32, DistL2>::new(10, nbcolumn, 16, 50, DistL2{});
+    for i in 0..data.len() {
+        hns.insert((&data[i], i));
+    }
+    hns.delete(70);
+    // one point out of 50 matches: the search must go through many rejected points
+    let filter = |id: DataId| id % 50 == 20;
+    let queries : Vec<Vec<f32>> = data[0..50].to_vec();
+    let answers = hns.parallel_search_filter(&queries, 10, 30, &filter);
+    let mut nb_found = 0;
+    for (query, neighbours) in queries.iter().zip(answers.iter()) {
+        assert_eq!(neighbours.len(), 10);
+        assert!(neighbours.iter().all(|n| filter(n.d_id) && n.d_id != 70));
+        let ids : Vec<DataId> = hns.search_filter(query, 10, 30, &filter).iter().map(|n| n.d_id).collect();
+        assert_eq!(neighbours.iter().map(|n| n.d_id).collect::<Vec<DataId>>(), ids);
+        // exact nearest matching point
+        let nearest = (0..nbcolumn).filter(|i| filter(*i) && *i != 70)
+                    .min_by(|a, b| DistL2{}.eval(query, &data[*a]).partial_cmp(&DistL2{}.eval(query, &data[*b])).unwrap())
+                    .unwrap();
+        if neighbours[0].d_id == nearest {
+            nb_found += 1;
+        }
+    }
+    assert!(nb_found >= 45, "nb_found {}", nb_found);
+    // no point matches
+    assert!(hns.search_filter(&data[0], 10, 30, &|_| false).is_empty());
+} // end of test_search_filter
+
+
+#[test]
+fn test_search_filter_bounded() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 10000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let data_with_id : Vec<(&Vec<f32>, usize)> = data.iter().zip(0..nbcolumn).collect();
+    let hns = Hnsw::<f32, DistL2>::new(10, nbcolumn, 16, 50, DistL2{});
+    hns.parallel_insert(&data_with_id);
+    // a filter matching nothing stops widening at MAX_EF_WIDENING * ef instead of visiting the whole graph
+    let nb_calls = std::sync::atomic::AtomicUsize::new(0);
+    let filter = |_id: DataId| {
+        nb_calls.fetch_add(1, AtomicOrdering::Relaxed);
+        false
+    };
+    assert!(hns.search_filter(&data[0], 5, 10, &filter).is_empty());
+    let nb_calls = nb_calls.load(AtomicOrdering::Relaxed);
+    assert!(nb_calls > 10 * MAX_EF_WIDENING / 2, "nb_calls {}", nb_calls);
+    assert!(nb_calls < nbcolumn, "nb_calls {}", nb_calls);
+    // a filter matching one point out of 100 is widened until it finds some
+    let found = hns.search_filter(&data[0], 5, 10, &|id: DataId| id % 100 == 0);
+    assert!(found.len() > 0 && found.iter().all(|n| n.d_id % 100 == 0));
+} // end of test_search_filter_bounded
+
+
+#[test]
+fn test_search_range() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 2000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let hns = Hnsw::<f32, DistL2>::new(10, nbcolumn, 16, 50, DistL2{});
+    for i in 0..data.len() {
+        hns.insert((&data[i], i));
+    }
+    hns.delete(3);
+    let radius = 0.6;
+    let mut nb_expected = 0;
+    let mut nb_found = 0;
+    for i in 0..50 {
+        let in_range = hns.search_range(&data[i], radius, 16);
+        assert!(in_range.windows(2).all(|w| w[0].distance <= w[1].distance));
+        assert!(in_range.iter().all(|n| n.distance <= radius && n.d_id != 3));
+        let expected : Vec<usize> = (0..nbcolumn).filter(|j| *j != 3 && DistL2{}.eval(&data[i], &data[*j]) <= radius).collect();
+        nb_expected += expected.len();
+        nb_found += in_range.iter().filter(|n| expected.contains(&n.d_id)).count();
+        assert_eq!(in_range.len(), in_range.iter().filter(|n| expected.contains(&n.d_id)).count());
+    }
+    assert!(nb_found as f32 >= 0.95 * nb_expected as f32, "found {} out of {}", nb_found, nb_expected);
+    // a null radius only returns the point itself
+    let in_range = hns.search_range(&data[10], 0., 16);
+    assert_eq!(in_range.len(), 1);
+    assert_eq!(in_range[0].d_id, 10);
+} // end of test_search_range
+
+
+#[test]
+fn test_concurrent_insert_search() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 8000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let data_with_id : Vec<(&Vec<f32>, usize)> = data.iter().zip(0..nbcolumn).collect();
+    let hns = Hnsw::<f32, DistL2>::new(16, nbcolumn, 16, 100, DistL2{});
+    // the first points race for the entry point
+    hns.parallel_insert(&data_with_id[0..100].to_vec());
+    let queries : Vec<Vec<f32>> = data[0..200].to_vec();
+    // insert the rest in parallel while searching in parallel
+    let (_, answers) = rayon::join(
+        || {
+            for chunk in data_with_id[100..].chunks(1000) {
+                hns.parallel_insert(&chunk.to_vec());
+            }
+        },
+        || {
+            (0..20).map(|_| hns.parallel_search(&queries, 5, 32)).last().unwrap()
+        },
+    );
+    assert_eq!(answers.len(), queries.len());
+    assert_eq!(hns.get_nb_point(), nbcolumn);
+    // every point is linked to the graph
+    for point in hns.get_point_indexation() {
+        assert!(point.neighbours_read()[0].len() > 0, "point {:?} has no neighbour", point.p_id);
+    }
+    // the graph built concurrently is as good as usual
+    let queries : Vec<Vec<f32>> = data.iter().step_by(20).cloned().collect();
+    let answers = hns.parallel_search(&queries, 1, 32);
+    let nb_found = answers.iter().enumerate().filter(|(i, a)| a[0].d_id == i * 20).count();
+    assert!(nb_found as f32 >= 0.98 * queries.len() as f32, "nb_found {} out of {}", nb_found, queries.len());
+} // end of test_concurrent_insert_search
+
+
+#[test]
+fn test_seeded_deterministic_construction() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 3000;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect()).collect();
+    let data_with_id : Vec<(&Vec<f32>, usize)> = data.iter().zip(0..nbcolumn).collect();
+    let build = |seed : u64| {
+        let mut hns = Hnsw::<f32, DistL2>::new_with_seed(16, nbcolumn, 16, 100, DistL2{}, seed);
+        hns.set_extend_candidates(true);
+        hns.set_deterministic(true);
+        hns.parallel_insert(&data_with_id);
+        hns
+    };
+    let hns1 = build(4517);
+    let hns2 = build(4517);
+    check_graph_equality(&hns1, &hns2);
+    let queries : Vec<Vec<f32>> = data.iter().step_by(30).cloned().collect();
+    let answers1 = hns1.parallel_search(&queries, 10, 32);
+    let answers2 = hns2.parallel_search(&queries, 10, 32);
+    for (a1, a2) in answers1.iter().zip(answers2.iter()) {
+        let ids1 : Vec<usize> = a1.iter().map(|n| n.d_id).collect();
+        let ids2 : Vec<usize> = a2.iter().map(|n| n.d_id).collect();
+        assert_eq!(ids1, ids2);
+    }
+    // same seed gives same levels
+    let g1 = LayerGenerator::new_with_seed(16, 16, 11);
+    let g2 = LayerGenerator::new_with_seed(16, 16, 11);
+    let levels1 : Vec<usize> = (0..1000).map(|_| g1.generate()).collect();
+    let levels2 : Vec<usize> = (0..1000).map(|_| g2.generate()).collect();
+    assert_eq!(levels1, levels2);
+} // end of test_seeded_deterministic_construction
+
 }  // end of module test
\ No newline at end of file
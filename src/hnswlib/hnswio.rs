@@ -1,28 +1,41 @@
 //! This file provides io dump/ reload of computed graph.
 //!
-//! A dump is constituted of 2 files. 
-//! One file stores just the graph (or topology) with id of points.  
+//! A dump is constituted of 2 files.
+//! One file stores just the graph (or topology) with id of points.
 //! The other file stores the ids and vector in point.
 //! The graph file is suffixed by "hnsw.graph" the other is suffixed by "hnsw.data"
-//! 
+//!
 //! An example of dump and reload of structure Hnsw is given in the tests (see test_dump_reload)
-/// 
-/// 
-// datafile
-// MAGICDATAP : u32
-// dimension : u32
-// The for each point the triplet: (MAGICDATAP, origin_id , array of values.) ( u32, u64, ....)
+//! The module container dumps the graph and the vectors in a single file.
+//!
+//! Since format version 3 all integers are written little endian with a fixed width, so that a dump
+//! can be reloaded on any machine, and each section ends with the crc32 checksum of its bytes.
+//! Dumps in format version 2 (native endianness and word size) can still be reloaded.
+///
+///
+// graph file, format version 3
+// header   : MAGICDESCR_3 : u32, then  FORMAT_VERSION : u32, dumpmode : u8, max_nb_connection : u8, nb_layer : u8,
+//            ef : u64, nb_point : u64, dimension : u64, distance name and T name (u32 length then bytes), crc32
+// layers   : for each layer, MAGICLAYER : u32, number of points : u64, the points, crc32
+// a point  : MAGICPOINT : u32, origin_id : u64, layer : u8, rank_in_layer : i32, then for each layer up to its own
+//            the number of neighbours : u32 and for each neighbour origin_id : u64, layer : u8, rank_in_layer : i32, distance : f32
+// entry    : MAGICENTRY : u32, origin_id : u64, layer : u8, rank_in_layer : i32, crc32
+// deleted  : MAGICDELETED : u32, number of deleted points : u64, for each layer : u8, rank_in_layer : i32, crc32
 //
-// A point is dumped in graph file as given by its external id (type DataId i.e : a usize, possibly a hash value) 
+// data file, format version 3
+// header   : MAGICDATAP : u32, FORMAT_VERSION : u32, dimension : u64, crc32
+// layers   : for each layer the points in the order of the graph file, then crc32
+// a point  : MAGICDATAP : u32, origin_id : u64, length : u64, the vector serialized by bincode.
+//
+// A point is dumped in graph file as given by its external id (type DataId i.e : a usize, possibly a hash value)
 // and layer (u8) and rank_in_layer:i32.
-// In the data file the point dump consist in the triplet: (MAGICDATAP, origin_id , array of values.)
 //
 
 use serde::{Serialize, de::DeserializeOwned};
+use thiserror::Error as ThisError;
 
 
 use std::io;
-use std::mem;
 
 use parking_lot::{RwLock};
 use std::sync::Arc;
@@ -35,7 +48,13 @@ use std::any::{type_name};
 use std::io::prelude::*;
 use crate::hnswlib::hnsw;
 use self::hnsw::*;
-use crate::hnswlib::dist::Distance;
+use crate::hnswlib::dist::*;
+use crate::hnswlib::api::AnnT;
+use crate::hnswlib::container::MappedNeighbours;
+use crate::hnswlib::stats::GraphStats;
+
+/// version of the dump format written by file_dump
+pub const FORMAT_VERSION : u32 = 3;
 
 // magic before each graph point data for each point
 const MAGICPOINT : u32 = 0x000a678f;
@@ -43,10 +62,56 @@ const MAGICPOINT : u32 = 0x000a678f;
 const MAGICDESCR_1 : u32 = 0x001a677f;
 // magic at beginning of description format v& of dump
 const MAGICDESCR_2 : u32 = 0x002a677f;
+// magic at beginning of description, format version 3 and later, followed by the version
+const MAGICDESCR_3 : u32 = 0x003a677f;
 // magic at beginning of a layer dump
 const MAGICLAYER : u32 = 0x000a676f;
 // magic head of data file and before each data vector
 const MAGICDATAP : u32 = 0xa67f0000;
+// magic before the list of deleted points, after the entry point. Absent in older dumps.
+const MAGICDELETED : u32 = 0x000a675f;
+// magic before the entry point, format version 3
+const MAGICENTRY : u32 = 0x000a674f;
+// bound on the length of the distance and type names
+const MAX_NAME_LEN : usize = 256;
+
+
+/// Errors of dump and reload.
+#[derive(Debug, ThisError)]
+pub enum HnswIoError {
+    #[error("bad magic {found:#x} in {section}, expected {expected:#x}")]
+    BadMagic {
+        section: &'static str,
+        found: u32,
+        expected: u32,
+    },
+    #[error("unsupported dump format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("dump truncated in {0}")]
+    Truncated(&'static str),
+    #[error("checksum mismatch in {section}: stored {stored:#010x}, computed {computed:#010x}")]
+    ChecksumMismatch {
+        section: &'static str,
+        stored: u32,
+        computed: u32,
+    },
+    #[error("dump is for distance {dumped}, the reload asks for {asked}")]
+    DistanceMismatch { dumped: String, asked: String },
+    #[error("dump is for data type {dumped}, the reload asks for {asked}")]
+    TypeMismatch { dumped: String, asked: String },
+    #[error("corrupted dump: {0}")]
+    Corrupted(String),
+    #[error("an empty graph cannot be dumped")]
+    EmptyGraph,
+    #[error("containers are only supported on little endian targets")]
+    BigEndianTarget,
+    #[error("no loader for distance {distname} over {t_name}")]
+    UnknownDescription { distname: String, t_name: String },
+    #[error(transparent)]
+    Serialization(#[from] bincode::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DumpMode {
@@ -57,17 +122,183 @@ pub enum DumpMode {
 
 /// The main interface for dumping struct Hnsw.
 pub trait HnswIO {
-    fn dump<W:Write>(&self, mode : DumpMode, outgraph : &mut io::BufWriter<W>, outdata: &mut io::BufWriter<W>) -> Result<i32, String>;
+    fn dump<W:Write>(&self, mode : DumpMode, outgraph : &mut io::BufWriter<W>, outdata: &mut io::BufWriter<W>) -> Result<i32, HnswIoError>;
 }
 
 
+//
+// checksummed sections
+// ====================
+//
+
+// writes little endian values and keeps the crc32 of the bytes written since the last end_section
+pub(crate) struct SectionWriter<'a, W:Write> {
+    out : &'a mut io::BufWriter<W>,
+    hasher : crc32fast::Hasher,
+}
+
+impl <'a, W:Write> SectionWriter<'a, W> {
+    pub(crate) fn new(out : &'a mut io::BufWriter<W>) -> Self {
+        SectionWriter{out, hasher : crc32fast::Hasher::new()}
+    }
+
+    pub(crate) fn write_bytes(&mut self, bytes : &[u8]) -> Result<(), HnswIoError> {
+        self.hasher.update(bytes);
+        self.out.write_all(bytes)?;
+        Ok(())
+    }
+
+    pub(crate) fn write_u8(&mut self, v : u8) -> Result<(), HnswIoError> {
+        self.write_bytes(&[v])
+    }
+
+    pub(crate) fn write_u32(&mut self, v : u32) -> Result<(), HnswIoError> {
+        self.write_bytes(&v.to_le_bytes())
+    }
+
+    pub(crate) fn write_u64(&mut self, v : u64) -> Result<(), HnswIoError> {
+        self.write_bytes(&v.to_le_bytes())
+    }
+
+    pub(crate) fn write_i32(&mut self, v : i32) -> Result<(), HnswIoError> {
+        self.write_bytes(&v.to_le_bytes())
+    }
+
+    pub(crate) fn write_f32(&mut self, v : f32) -> Result<(), HnswIoError> {
+        self.write_bytes(&v.to_le_bytes())
+    }
+
+    pub(crate) fn write_name(&mut self, name : &str) -> Result<(), HnswIoError> {
+        self.write_u32(name.len() as u32)?;
+        self.write_bytes(name.as_bytes())
+    }
+
+    pub(crate) fn write_point_id(&mut self, origin_id : DataId, p_id : PointId) -> Result<(), HnswIoError> {
+        self.write_u64(origin_id as u64)?;
+        self.write_u8(p_id.0)?;
+        self.write_i32(p_id.1)
+    }
+
+    // writes the checksum of the section, which is not part of the next one
+    pub(crate) fn end_section(&mut self) -> Result<(), HnswIoError> {
+        let checksum = std::mem::replace(&mut self.hasher, crc32fast::Hasher::new()).finalize();
+        self.out.write_all(&checksum.to_le_bytes())?;
+        Ok(())
+    }
+} // end of impl SectionWriter
+
+
+// reads little endian values, keeping the crc32 of the bytes read in the current section.
+// A premature end of file is reported as the truncation of the current section.
+pub(crate) struct SectionReader<'a> {
+    input : &'a mut dyn Read,
+    hasher : crc32fast::Hasher,
+    section : &'static str,
+    // number of bytes read, checksums included
+    pub(crate) position : usize,
+}
+
+impl <'a> SectionReader<'a> {
+    pub(crate) fn new(input : &'a mut dyn Read, section : &'static str) -> Self {
+        SectionReader{input, hasher : crc32fast::Hasher::new(), section, position : 0}
+    }
+
+    fn read_raw(&mut self, buf : &mut [u8]) -> Result<(), HnswIoError> {
+        match self.input.read_exact(buf) {
+            Ok(()) => {
+                self.position += buf.len();
+                Ok(())
+            },
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(HnswIoError::Truncated(self.section)),
+            Err(e) => Err(HnswIoError::Io(e)),
+        }
+    }
+
+    pub(crate) fn read_bytes(&mut self, buf : &mut [u8]) -> Result<(), HnswIoError> {
+        self.read_raw(buf)?;
+        self.hasher.update(buf);
+        Ok(())
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, HnswIoError> {
+        let mut buf = [0u8; 1];
+        self.read_bytes(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, HnswIoError> {
+        let mut buf = [0u8; 4];
+        self.read_bytes(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, HnswIoError> {
+        let mut buf = [0u8; 8];
+        self.read_bytes(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_i32(&mut self) -> Result<i32, HnswIoError> {
+        let mut buf = [0u8; 4];
+        self.read_bytes(&mut buf)?;
+        Ok(i32::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_f32(&mut self) -> Result<f32, HnswIoError> {
+        let mut buf = [0u8; 4];
+        self.read_bytes(&mut buf)?;
+        Ok(f32::from_le_bytes(buf))
+    }
+
+    pub(crate) fn read_name(&mut self) -> Result<String, HnswIoError> {
+        let len = self.read_u32()? as usize;
+        if len > MAX_NAME_LEN {
+            return Err(HnswIoError::Corrupted(format!("name length {} in {}", len, self.section)));
+        }
+        let mut buf = vec![0u8; len];
+        self.read_bytes(&mut buf)?;
+        String::from_utf8(buf).map_err(|_| HnswIoError::Corrupted(format!("name not in utf8 in {}", self.section)))
+    }
+
+    pub(crate) fn read_point_id(&mut self) -> Result<(DataId, PointId), HnswIoError> {
+        let origin_id = self.read_u64()? as DataId;
+        let layer = self.read_u8()?;
+        let rank_in_l = self.read_i32()?;
+        Ok((origin_id, PointId{0: layer, 1: rank_in_l}))
+    }
+
+    pub(crate) fn check_magic(&mut self, expected : u32) -> Result<(), HnswIoError> {
+        let found = self.read_u32()?;
+        if found != expected {
+            return Err(HnswIoError::BadMagic{section: self.section, found, expected});
+        }
+        Ok(())
+    }
+
+    // reads and checks the checksum of the current section, then starts section next
+    pub(crate) fn end_section(&mut self, next : &'static str) -> Result<(), HnswIoError> {
+        let mut buf = [0u8; 4];
+        self.read_raw(&mut buf)?;
+        let stored = u32::from_le_bytes(buf);
+        let computed = std::mem::replace(&mut self.hasher, crc32fast::Hasher::new()).finalize();
+        if stored != computed {
+            return Err(HnswIoError::ChecksumMismatch{section: self.section, stored, computed});
+        }
+        self.section = next;
+        Ok(())
+    }
+} // end of impl SectionReader
+
+
 
 
 /// structure describing main parameters for hnsnw data and written at the beginning of a dump file.
-/// 
+///
 /// Name of distance and type of data must be encoded in the dump file for a coherent reload.
 #[repr(C)]
 pub struct Description {
+    /// version of the format of the dump, FORMAT_VERSION for dumps written by this version
+    pub format_version : u32,
     ///  value is 1 for Full 0 for Light
     pub dumpmode : u8,
     /// max number of connections in layers != 0
@@ -88,45 +319,37 @@ pub struct Description {
 
 impl Description {
     /// The dump of Description consists in :
-    /// . The value MAGICDESCR_1 as a u32 (4 u8)
+    /// . The value MAGICDESCR_3 as a u32 and the format version as a u32
     /// . The type of dump as u8
     /// . max_nb_connection as u8
-    /// . ef (search parameter used in construction) as usize
-    /// . nb_point (the number points dumped) as a usize
-    /// . the name of distance used. (nb byes as a usize then list of bytes)
-    /// 
-    fn dump<W:Write>(&self, argmode : DumpMode, out : &mut io::BufWriter<W>) -> Result<i32, String> {
+    /// . nb_layer as u8
+    /// . ef (search parameter used in construction) as u64
+    /// . nb_point (the number points dumped) as a u64
+    /// . dimension as a u64
+    /// . the name of distance and the name of T. (nb bytes as a u32 then list of bytes)
+    /// . the checksum of all the above
+    pub(crate) fn dump<W:Write>(&self, argmode : DumpMode, out : &mut io::BufWriter<W>) -> Result<i32, HnswIoError> {
         log::info!("in dump of description");
-        out.write(unsafe { &mem::transmute::<u32, [u8; std::mem::size_of::<u32>()]>(MAGICDESCR_2) } ).unwrap();
+        let mut out = SectionWriter::new(out);
+        out.write_u32(MAGICDESCR_3)?;
+        out.write_u32(FORMAT_VERSION)?;
         let mode : u8 = match argmode {
             DumpMode::Full => 1,
             _              => 0,
         };
-        // CAVEAT should check mode == self.mode
-        out.write(unsafe { &mem::transmute::<u8, [u8;1]>(mode) } ).unwrap();
-        out.write(unsafe { &mem::transmute::<u8, [u8;1]>(self.max_nb_connection) } ).unwrap();
-        out.write(unsafe { &mem::transmute::<u8, [u8;1]>(self.nb_layer) } ).unwrap();
-        if self.nb_layer != NB_LAYER_MAX {
-            println!("dump of Description, nb_layer != NB_MAX_LAYER");
-            return Err(String::from("dump of Description, nb_layer != NB_MAX_LAYER"));
-        }
-        out.write(unsafe { &mem::transmute::<usize, [u8;std::mem::size_of::<usize>()]>(self.ef) } ).unwrap();
+        out.write_u8(mode)?;
+        out.write_u8(self.max_nb_connection)?;
+        out.write_u8(self.nb_layer)?;
+        out.write_u64(self.ef as u64)?;
         log::info!("dumping nb point {:?}", self.nb_point);
-        // 
-        out.write(unsafe { &mem::transmute::<usize, [u8;std::mem::size_of::<usize>()]>(self.nb_point) } ).unwrap();
-        //
+        out.write_u64(self.nb_point as u64)?;
         log::info!("dumping dimension of data {:?}", self.dimension);
-        out.write(unsafe { &mem::transmute::<usize, [u8;std::mem::size_of::<usize>()]>(self.dimension) } ).unwrap();
-        // dump of distance name
-        let namelen : usize = self.distname.len();
+        out.write_u64(self.dimension as u64)?;
         log::info!("distance name {:?} ", self.distname);
-        out.write(unsafe { &mem::transmute::<usize, [u8;std::mem::size_of::<usize>()]>(namelen) } ).unwrap();
-        out.write(self.distname.as_bytes()).unwrap();
-        // dump of T value typename
-        let namelen : usize = self.t_name.len();
+        out.write_name(&self.distname)?;
         log::info!("T name {:?} ", self.t_name);
-        out.write(unsafe { &mem::transmute::<usize, [u8;std::mem::size_of::<usize>()]>(namelen) } ).unwrap();
-        out.write(self.t_name.as_bytes()).unwrap();
+        out.write_name(&self.t_name)?;
+        out.end_section()?;
         //
         return Ok(1);
     } // end fo dump
@@ -137,24 +360,397 @@ impl Description {
 /// This method is a preliminary to do a full reload from a dump.
 /// The method load_hnsw needs to know the typename , distance used, and construction parameters.
 /// So the reload is made in two steps.
-pub fn load_description(io_in: &mut dyn Read)  -> io::Result<Description> {
+/// Descriptions of format version 2 and 3 are recognized.
+pub fn load_description(io_in: &mut dyn Read)  -> Result<Description, HnswIoError> {
     //
-    let mut descr = Description{ dumpmode: 0, max_nb_connection: 0, nb_layer: 0, 
-                                ef: 0, nb_point: 0, dimension : 0, 
-                                distname: String::from(""), t_name : String::from("")};
-    let magic : u32 = 0;
-    let it_slice = unsafe {::std::slice::from_raw_parts_mut((&magic as *const u32) as *mut u8, ::std::mem::size_of::<u32>() )};
-    io_in.read_exact(it_slice)?;
+    let mut header = SectionReader::new(io_in, "description");
+    let magic = header.read_u32()?;
     log::debug!(" magic {:X} ", magic);
-    if magic !=  MAGICDESCR_1 && magic !=  MAGICDESCR_2 {
-        log::info!("bad magic");
-        return Err(io::Error::new(io::ErrorKind::Other, "bad magic at descr beginning"));
+    match magic {
+        MAGICDESCR_3 => (),
+        MAGICDESCR_2 => {
+            log::info!("dump in format version 2");
+            return load_description_v2(header.input);
+        },
+        MAGICDESCR_1 => {
+            log::info!("old version of dump..., exiting");
+            return Err(HnswIoError::UnsupportedVersion(1));
+        },
+        _ => return Err(HnswIoError::BadMagic{section: "description", found: magic, expected: MAGICDESCR_3}),
+    }
+    let format_version = header.read_u32()?;
+    if format_version != FORMAT_VERSION {
+        return Err(HnswIoError::UnsupportedVersion(format_version));
+    }
+    let dumpmode = header.read_u8()?;
+    let max_nb_connection = header.read_u8()?;
+    let nb_layer = header.read_u8()?;
+    let ef = header.read_u64()? as usize;
+    let nb_point = header.read_u64()? as usize;
+    let dimension = header.read_u64()? as usize;
+    let distname = header.read_name()?;
+    let t_name = header.read_name()?;
+    header.end_section("graph")?;
+    if nb_layer == 0 || nb_layer > NB_LAYER_MAX {
+        return Err(HnswIoError::Corrupted(format!("number of layers {}", nb_layer)));
     }
-    else if magic ==  MAGICDESCR_1 {
-        log::info!("old version of dump..., exiting");
-        println!("old version of dump");
-        return Err(io::Error::new(io::ErrorKind::Other, "old format of dump"));
+    log::info!("nb_point {:?} dimension {:?} distance {:?} T {:?}", nb_point, dimension, distname, t_name);
+    //
+    Ok(Description{format_version, dumpmode, max_nb_connection, nb_layer, ef, nb_point, dimension, distname, t_name})
+}
+//
+// dump and load of Point<T>
+// ==========================
+//
+
+    ///  Graph part of point dump
+    /// dump of a point consist in
+    ///  1. The value MAGICPOINT
+    ///  2. its identity : origin_id (: u64, rank in original data , hash value or else), layer (: u8) rank_in_layer (: i32)
+    ///  3. for each layer up to its own dump of the number of neighbours (: u32) followed by :
+    ///      for each neighbour dump of its identity and then distance (: f32) to point dumped.
+    ///
+    ///  For data dump, if there is a dataout (a container stores the vectors by itself)
+    ///  1. The value MAGICDATAP (u32)
+    ///  2. origin_id as a u64
+    ///  3. The length of the serialized vector as a u64, then the vector serialized by bincode
+
+fn dump_point<'a, T:Serialize+Clone+Sized+Send+Sync, W:Write>(point : &Point<T> ,
+                    graphout : &mut SectionWriter<W>, dataout : Option<&mut SectionWriter<W>>) -> Result<i32, HnswIoError> {
+    //
+    graphout.write_u32(MAGICPOINT)?;
+    let p_id = point.get_point_id();
+    graphout.write_point_id(point.get_origin_id(), p_id)?;
+    // then dump neighborhood info of the layers the point belongs to
+    let neighborhood = point.get_neighborhood_id();
+    for l in 0..=p_id.0 as usize {
+        let neighbours_at_l = &neighborhood[l];
+        graphout.write_u32(neighbours_at_l.len() as u32)?;
+        for n in neighbours_at_l {
+            graphout.write_point_id(n.d_id, n.p_id)?;
+            graphout.write_f32(n.distance)?;
+        }
+    }
+    // now we dump data vector!
+    if let Some(dataout) = dataout {
+        dataout.write_u32(MAGICDATAP)?;
+        dataout.write_u64(point.get_origin_id() as u64)?;
+        //
+        let serialized : Vec<u8> = bincode::serialize(point.get_v())?;
+        dataout.write_u64(serialized.len() as u64)?;
+        dataout.write_bytes(&serialized)?;
+    }
+    //
+    return Ok(1);
+} // end of dump for Point<T>
+
+
+// where load_point_indexation takes the vectors of the points from:
+// the data file, or the vector section of a container.
+pub(crate) trait VectorIn<T> {
+    // vector of the next point of the graph, which has id origin_id
+    fn next_vector(&mut self, origin_id : DataId) -> Result<PointData<T>, HnswIoError>;
+    // called at the end of each layer
+    fn end_layer(&mut self) -> Result<(), HnswIoError>;
+}
+
+// the data file
+impl <'a, T:'static+DeserializeOwned> VectorIn<T> for SectionReader<'a> {
+    fn next_vector(&mut self, origin_id : DataId) -> Result<PointData<T>, HnswIoError> {
+        self.check_magic(MAGICDATAP)?;
+        let origin_id_data = self.read_u64()? as DataId;
+        if origin_id != origin_id_data {
+            return Err(HnswIoError::Corrupted(format!("origin_id incoherent between graph {:?} and data {:?}", origin_id, origin_id_data)));
+        }
+        let serialized_len = self.read_u64()? as usize;
+        let mut v_serialized = vec![0u8; serialized_len];
+        self.read_bytes(&mut v_serialized)?;
+        let v : Vec<T>;
+        if std::any::TypeId::of::<T>() != std::any::TypeId::of::<NoData>() {
+            v = bincode::deserialize(&v_serialized)?;
+        }
+        else {
+            v = Vec::<T>::new();
+        }
+        Ok(PointData::owned(v))
     }
+
+    fn end_layer(&mut self) -> Result<(), HnswIoError> {
+        self.end_section("data")
+    }
+} // end of impl VectorIn for SectionReader
+
+
+//
+//  Reload a point from a dump.
+//
+//  The graph part is loaded from graph_in file
+// the data vector itself is obtained from vectors.
+// With lazy, the neighbours are skipped and will be decoded from the source on first access,
+// their ids are only checked against the layers, max_rank_by_layer keeps the highest rank referenced in each layer.
+//
+fn load_point<T:'static+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut SectionReader,
+                                                vectors: &mut dyn VectorIn<T>,
+                                                lazy: Option<&Arc<MappedNeighbours<T>>>,
+                                                max_rank_by_layer: &mut Vec<i32>) -> Result<(Arc<Point<T>>, Option<Vec<Vec<Neighbor>>>), HnswIoError> {
+    //
+    graph_in.check_magic(MAGICPOINT)?;
+    let (origin_id, p_id) = graph_in.read_point_id()?;
+    if p_id.0 >= NB_LAYER_MAX {
+        return Err(HnswIoError::Corrupted(format!("layer {} of point {:?}", p_id.0, origin_id)));
+    }
+    let neighbours_offset = graph_in.position;
+    // Now  for each layer , read neighbours
+    let mut neighborhood = Vec::<Vec<Neighbor> >::with_capacity(NB_LAYER_MAX as usize);
+    for _l in 0..=p_id.0 {
+        let nb_neighbours = graph_in.read_u32()? as usize;
+        // the count is not trusted for the allocation before the checksum is verified
+        let mut neighborhood_l : Vec<Neighbor> = Vec::with_capacity(if lazy.is_some() { 0 } else { nb_neighbours.min(256) });
+        for _j in 0..nb_neighbours {
+            let (d_id, n_p_id) = graph_in.read_point_id()?;
+            let distance = graph_in.read_f32()?;
+            if lazy.is_some() {
+                if n_p_id.1 < 0 || n_p_id.0 as usize >= max_rank_by_layer.len() {
+                    return Err(HnswIoError::Corrupted(format!("neighbourhood refers to point {:?} not in graph", n_p_id)));
+                }
+                let max_rank = &mut max_rank_by_layer[n_p_id.0 as usize];
+                *max_rank = (*max_rank).max(n_p_id.1);
+            }
+            else {
+                neighborhood_l.push(Neighbor::new(d_id, distance, n_p_id));
+            }
+        }
+        neighborhood.push(neighborhood_l);
+    }
+    for _l in (p_id.0 + 1)..NB_LAYER_MAX {
+        neighborhood.push(Vec::<Neighbor>::new());
+    }
+    //
+    // construct a point from vectors
+    //
+    let v = vectors.next_vector(origin_id)?;
+    let point = Point::<T>::from_data(v, origin_id, p_id);
+    log::trace!("load_point  origin {:?} allocated size {:?}", origin_id, point.get_v().len());
+    //
+    match lazy {
+        Some(source) => {
+            let source : Arc<dyn NeighbourSource<T>> = Arc::clone(source) as Arc<dyn NeighbourSource<T>>;
+            point.set_pending_neighbours(PendingNeighbours{source, offset : neighbours_offset});
+            Ok((Arc::new(point), None))
+        },
+        None => Ok((Arc::new(point), Some(neighborhood))),
+    }
+}  // end of load_point
+
+
+
+//
+// dump and load of PointIndexation<T>
+// ===================================
+//
+//
+// for each layer a magic : u32, number of points in layer (u64), list of point of layer, checksum
+// then entry point and deleted points, each with its magic and checksum
+//
+impl <T:Serialize+DeserializeOwned+Clone+Send+Sync> HnswIO for PointIndexation<T> {
+    fn dump<W:Write>(&self, _mode : DumpMode, graphout : &mut io::BufWriter<W>, dataout : &mut io::BufWriter<W>) -> Result<i32, HnswIoError> {
+        dump_point_indexation(self, graphout, Some(dataout))?;
+        Ok(1)
+    } // end of dump for PointIndexation<T>
+} // end of impl HnswIO
+
+
+// dumps the layers, the entry point and the deleted points, and the vectors in dataout if any.
+// Returns the points in the order they were dumped.
+pub(crate) fn dump_point_indexation<T:Serialize+Clone+Send+Sync, W:Write>(indexation : &PointIndexation<T>,
+                graphout : &mut io::BufWriter<W>, dataout : Option<&mut io::BufWriter<W>>) -> Result<Vec<Arc<Point<T>>>, HnswIoError> {
+    let mut graphout = SectionWriter::new(graphout);
+    let mut dataout = dataout.map(SectionWriter::new);
+    // entry point first, so that no insertion can change it while we dump
+    let ep_read = indexation.entry_point.read();
+    let ep = match ep_read.as_ref() {
+        Some(ep) => ep,
+        None => return Err(HnswIoError::EmptyGraph),
+    };
+    let layers = indexation.points_by_layer.read();
+    let mut dumped = Vec::<Arc<Point<T>>>::with_capacity(layers.iter().map(|layer| layer.len()).sum());
+    // dump layers from lower (most populatated to higher level)
+    for i in 0..layers.len() {
+        let nb_point = layers[i].len();
+        log::debug!("dumping layer {:?}, nb_point {:?}", i, nb_point);
+        graphout.write_u32(MAGICLAYER)?;
+        graphout.write_u64(nb_point as u64)?;
+        for j in 0..layers[i].len() {
+            assert_eq!(layers[i][j].get_point_id() , PointId{0: i as u8,1:j as i32 });
+            dump_point(&layers[i][j], &mut graphout, dataout.as_mut())?;
+            dumped.push(Arc::clone(&layers[i][j]));
+        }
+        graphout.end_section()?;
+        if let Some(dataout) = dataout.as_mut() {
+            dataout.end_section()?;
+        }
+    }
+    // dump id of entry point
+    graphout.write_u32(MAGICENTRY)?;
+    graphout.write_point_id(ep.get_origin_id(), ep.get_point_id())?;
+    graphout.end_section()?;
+    log::info!("dumped entry_point origin_d {:?}, p_id {:?} ", ep.get_origin_id(), ep.get_point_id());
+    // dump point id of deleted points
+    let deleted : Vec<PointId> = layers.iter().flat_map(|layer| layer.iter())
+            .filter(|point| point.is_deleted())
+            .map(|point| point.get_point_id())
+            .collect();
+    graphout.write_u32(MAGICDELETED)?;
+    graphout.write_u64(deleted.len() as u64)?;
+    for p_id in &deleted {
+        graphout.write_u8(p_id.0)?;
+        graphout.write_i32(p_id.1)?;
+    }
+    graphout.end_section()?;
+    log::info!("dumped {:?} deleted points", deleted.len());
+    //
+    Ok(dumped)
+} // end of dump_point_indexation
+
+
+// returns the point stored at p_id, or an error if the dump refers to a point it does not contain
+fn get_loaded_point<'a, T:Clone+Send+Sync>(points_by_layer : &'a Vec<Vec<Arc<Point<T>>>>, p_id : PointId, section : &str) -> Result<&'a Arc<Point<T>>, HnswIoError> {
+    if p_id.1 < 0 {
+        return Err(HnswIoError::Corrupted(format!("{} refers to point {:?}", section, p_id)));
+    }
+    points_by_layer.get(p_id.0 as usize).and_then(|layer| layer.get(p_id.1 as usize))
+            .ok_or_else(|| HnswIoError::Corrupted(format!("{} refers to point {:?} not in graph", section, p_id)))
+}
+
+
+// With lazy the neighbours are not decoded, the points are registered in the source
+// which decodes them on first access.
+pub(crate) fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut SectionReader,
+                descr : &Description,
+                vectors: &mut dyn VectorIn<T>,
+                lazy: Option<&Arc<MappedNeighbours<T>>>) -> Result<PointIndexation<T>, HnswIoError> {
+    //
+    log::debug!(" in load_point_indexation");
+    //
+    let nb_layer = descr.nb_layer as usize;
+    let mut points_by_layer : Vec<Vec<Arc<Point<T>> > >= Vec::with_capacity(nb_layer);
+    let mut neighbourhood_map : HashMap<PointId, Vec<Vec<Neighbor>> > =  HashMap::new();
+    let mut max_rank_by_layer = vec![-1i32; nb_layer];
+    let mut nb_points_loaded : usize = 0;
+    //
+    for l in 0..nb_layer {
+        log::debug!("loading layer {:?}", l);
+        graph_in.check_magic(MAGICLAYER)?;
+        let nbpoints = graph_in.read_u64()? as usize;
+        if nb_points_loaded + nbpoints > descr.nb_point {
+            return Err(HnswIoError::Corrupted(format!("more points than the {} of the description", descr.nb_point)));
+        }
+        log::debug!(" layer {:?} , nb points {:?}", l ,  nbpoints);
+        let mut vlayer : Vec<Arc<Point<T>>> = Vec::with_capacity(nbpoints);
+        for r in 0..nbpoints {
+            // load graph and data part of point. Points are dumped in the same order.
+            let (point, neighbourhood) = load_point(graph_in, vectors, lazy, &mut max_rank_by_layer)?;
+            let p_id = point.get_point_id();
+            if p_id != (PointId{0: l as u8, 1: r as i32}) {
+                return Err(HnswIoError::Corrupted(format!("point {:?} stored at layer {} rank {}", p_id, l, r)));
+            }
+            if let Some(neighbourhood) = neighbourhood {
+                neighbourhood_map.insert(p_id, neighbourhood);
+            }
+            vlayer.push(point);
+        }
+        graph_in.end_section("graph")?;
+        vectors.end_layer()?;
+        points_by_layer.push(vlayer);
+        nb_points_loaded += nbpoints;
+    }
+    if nb_points_loaded != descr.nb_point {
+        return Err(HnswIoError::Corrupted(format!("{} points loaded, the description has {}", nb_points_loaded, descr.nb_point)));
+    }
+    // at this step all points are loaded , but without their neighbours fileds are not yet initialized
+    for (p_id , neighbours) in &neighbourhood_map {
+        let point = &points_by_layer[p_id.0 as usize][p_id.1 as usize];
+        let mut point_neighbours = point.neighbours_write();
+        for l in 0..neighbours.len() {
+            for n in &neighbours[l] {
+                let n_point = get_loaded_point(&points_by_layer, n.p_id, "neighbourhood")?;
+                point_neighbours[l].push(Arc::new(PointWithOrder::<T>::new(n_point, n.distance)));
+            }
+            point_neighbours[l].sort_unstable();
+        }
+    }
+    if let Some(source) = lazy {
+        for (l, max_rank) in max_rank_by_layer.iter().enumerate() {
+            if *max_rank >= 0 {
+                get_loaded_point(&points_by_layer, PointId{0: l as u8, 1: *max_rank}, "neighbourhood")?;
+            }
+        }
+        source.set_points(&points_by_layer);
+    }
+    log::info!("\n end of layer loading, allocating PointIndexation, nb points loaded {:?}", nb_points_loaded);
+    //
+    graph_in.check_magic(MAGICENTRY)?;
+    let (origin_id, p_id) = graph_in.read_point_id()?;
+    graph_in.end_section("deleted points")?;
+    let entry_point = Arc::clone(get_loaded_point(&points_by_layer, p_id, "entry point")?);
+    if entry_point.get_origin_id() != origin_id {
+        return Err(HnswIoError::Corrupted(format!("entry point {:?} has origin id {:?}, not {:?}", p_id, entry_point.get_origin_id(), origin_id)));
+    }
+    log::info!(" loaded entry point, origin_id {:} p_id {:?}", entry_point.get_origin_id(),entry_point.get_point_id());
+    //
+    graph_in.check_magic(MAGICDELETED)?;
+    let nb_deleted = graph_in.read_u64()? as usize;
+    if nb_deleted > nb_points_loaded {
+        return Err(HnswIoError::Corrupted(format!("{} deleted points out of {}", nb_deleted, nb_points_loaded)));
+    }
+    for _ in 0..nb_deleted {
+        let layer = graph_in.read_u8()?;
+        let rank_in_l = graph_in.read_i32()?;
+        let point = get_loaded_point(&points_by_layer, PointId{0: layer, 1: rank_in_l}, "deleted points")?;
+        point.deleted.store(true, std::sync::atomic::Ordering::Release);
+    }
+    graph_in.end_section("end of graph")?;
+    log::info!("loaded {:?} deleted points", nb_deleted);
+    //
+    let mut origin_index = hashbrown::HashMap::with_capacity(nb_points_loaded);
+    for layer in &points_by_layer {
+        for point in layer {
+            origin_index.insert(point.get_origin_id(), point.get_point_id());
+        }
+    }
+    //
+    let point_indexation = PointIndexation {
+        max_nb_connection : descr.max_nb_connection as usize,
+        max_layer : nb_layer,
+        points_by_layer : Arc::new(RwLock::new(points_by_layer)),
+        layer_g : LayerGenerator::new(descr.max_nb_connection as usize , nb_layer),
+        nb_point : Arc::new(RwLock::new(nb_points_loaded)),
+        entry_point : Arc::new(RwLock::new(Some(entry_point))),
+        origin_index : Arc::new(RwLock::new(origin_index)),
+        nb_deleted : Arc::new(RwLock::new(nb_deleted)),
+    };
+    //
+    log::debug!("\n exiting load_pointIndexation");
+    Ok(point_indexation)
+} // end of load_point_indexation
+
+
+
+//
+// reload of dumps in format version 2
+// ===================================
+//
+// The description, the graph and the data file are written in native endianness, usize on 8 bytes.
+// These functions are those of the version that wrote the format.
+//
+
+
+// reads a version 2 description, written in native endianness and word size, after its magic.
+fn load_description_v2(io_in: &mut dyn Read)  -> Result<Description, HnswIoError> {
+    //
+    let mut descr = Description{ format_version: 2, dumpmode: 0, max_nb_connection: 0, nb_layer: 0, 
+                                ef: 0, nb_point: 0, dimension : 0, 
+                                distname: String::from(""), t_name : String::from("")};
     let it_slice = unsafe {::std::slice::from_raw_parts_mut((&descr.dumpmode as *const u8) as *mut u8, ::std::mem::size_of::<u8>() )};
     io_in.read_exact(it_slice)?;
     log::info!(" dumpmode {:?} ", descr.dumpmode);
@@ -180,15 +776,14 @@ pub fn load_description(io_in: &mut dyn Read)  -> io::Result<Description> {
     let it_slice = unsafe {::std::slice::from_raw_parts_mut((&len as *const usize) as *mut u8, ::std::mem::size_of::<usize>() )};
     io_in.read_exact(it_slice)?;
     log::debug!("length of distance name {:?} ", len);
-    if len > 256 {
-        log::info!(" length of distance name > 256");
-        println!(" length of distance name should not exceed 256");
-        return Err(io::Error::new(io::ErrorKind::Other, "bad lenght for distance name"));
+    if len > MAX_NAME_LEN {
+        return Err(HnswIoError::Corrupted(format!("distance name length {} in description", len)));
     }
     let mut distv = Vec::<u8>::new();
     distv.resize(len , 0);
     io_in.read_exact(distv.as_mut_slice())?;
-    let distname = String::from_utf8(distv).unwrap();
+    let distname = String::from_utf8(distv)
+            .map_err(|_| HnswIoError::Corrupted(String::from("distance name not in utf8 in description")))?;
     log::debug!("distance name {:?} ", distname);
     descr.distname = distname;
     // reload of type name
@@ -196,97 +791,31 @@ pub fn load_description(io_in: &mut dyn Read)  -> io::Result<Description> {
     let it_slice = unsafe {::std::slice::from_raw_parts_mut((&len as *const usize) as *mut u8, ::std::mem::size_of::<usize>() )};
     io_in.read_exact(it_slice)?;
     log::debug!("length of T  name {:?} ", len);
-    if len > 256 {
-        println!(" length of T name should not exceed 256");
-        return Err(io::Error::new(io::ErrorKind::Other, "bad lenght for T name"));
+    if len > MAX_NAME_LEN {
+        return Err(HnswIoError::Corrupted(format!("T name length {} in description", len)));
     }
     let mut tnamev = Vec::<u8>::new();
     tnamev.resize(len , 0);
     io_in.read_exact(tnamev.as_mut_slice())?;
-    let t_name = String::from_utf8(tnamev).unwrap();
+    let t_name = String::from_utf8(tnamev)
+            .map_err(|_| HnswIoError::Corrupted(String::from("T name not in utf8 in description")))?;
     log::debug!("T type name {:?} ", t_name);
     descr.t_name = t_name;   
     log::debug!(" end of description load \n");
     //
     Ok(descr)
 }
-//
-// dump and load of Point<T>
-// ==========================
-//
 
-    ///  Graph part of point dump
-    /// dump of a point consist in  
-    ///  1. The value MAGICPOINT
-    ///  2. its identity ( a usize  rank in original data , hash value or else , and PointId)
-    ///  3. for each layer dump of the number of neighbours followed by :
-    ///      for each neighbour dump of its identity (: usize) and then distance (): u32) to point dumped.
-    ///
-    /// identity of a point is in full mode the triplet origin_id (: usize), layer (: u8) rank_in_layer (: u32)
-    ///                           light mode only origin_id (: usize)
-    ///  For data dump
-    ///  1. The value MAGICDATAP (u32)
-    ///  2. origin_id as a u64
-    ///  3. The vector of data (the length is known from Description)
-    
-fn dump_point<'a, T:Serialize+Clone+Sized+Send+Sync, W:Write>(point : &Point<T> , mode : DumpMode, 
-                    graphout : &mut io::BufWriter<W>, dataout : &mut io::BufWriter<W>) -> Result<i32, String> {
-    //
-    graphout.write(unsafe { &mem::transmute::<u32, [u8;4]>(MAGICPOINT) } ).unwrap();
-    // dump ext_id: usize , layer : u8 , rank in layer : i32
-    graphout.write(unsafe { &mem::transmute::<usize, [u8;8]>(point.get_origin_id()) } ).unwrap();
-    let p_id = point.get_point_id();
-    if mode == DumpMode::Full {
-        graphout.write(unsafe { &mem::transmute::<u8, [u8;1]>(p_id.0) } ).unwrap();
-        graphout.write(unsafe { &mem::transmute::<i32, [u8;4]>(p_id.1) } ).unwrap();
-    }
-//        log::debug!(" point dump {:?} {:?}  ", p_id, self.get_origin_id());
-    // then dump neighborhood info : nb neighbours : u32 , then list of origin_id, layer, rank_in_layer
-    let neighborhood = point.get_neighborhood_id();
-    // in any case nb_layers are dumped with possibly 0 neighbours at a layer, but this does not occur by construction
-    for l in 0..neighborhood.len() {
-        let neighbours_at_l = &neighborhood[l];
-        graphout.write(unsafe { &mem::transmute::<u8, [u8;1]>(neighbours_at_l.len() as u8) } ).unwrap();
-        for n in neighbours_at_l { // dump d_id : uszie , distance : f32, layer : u8, rank in layer : i32
-            graphout.write(unsafe { &mem::transmute::<usize, [u8;8]>(n.d_id) } ).unwrap();
-            if mode == DumpMode::Full {
-                graphout.write(unsafe { &mem::transmute::<u8, [u8;1]>(n.p_id.0) } ).unwrap();
-                graphout.write(unsafe { &mem::transmute::<i32, [u8;std::mem::size_of::<i32>()]>(n.p_id.1) } ).unwrap();
-            }
-            graphout.write(unsafe { &mem::transmute::<f32, [u8;std::mem::size_of::<f32>()]>(n.distance) } ).unwrap();
-//                log::debug!("        voisins  {:?}  {:?}  {:?}", n.p_id,  n.d_id , n.distance);
-        }
-    }
-    // now we dump data vector!
-    dataout.write(unsafe { &mem::transmute::<u32, [u8;4]>(MAGICDATAP) } ).unwrap();
-    dataout.write(unsafe { &mem::transmute::<u64, [u8;8]>(point.get_origin_id() as u64) } ).unwrap();
-    //
-    let serialized : Vec<u8> = bincode::serialize(point.get_v()).unwrap();
-//    log::debug!("serializing len {:?}", serialized.len());
-    dataout.write(unsafe { &mem::transmute::<u64, [u8;8]>(serialized.len() as u64) } ).unwrap();
-    dataout.write_all(&serialized).unwrap();
-    //
-    return Ok(1);
-} // end of dump for Point<T>
 
-
-
-//
-//  Reload a point from a dump.
-// 
-//  The graph part is loaded from graph_in file
-// the data vector itself is loaded from data_in
-// 
-fn load_point<T:'static+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut dyn Read, descr: &Description, 
-                                                data_in: &mut dyn Read) -> io::Result<(Arc<Point<T>>, Vec<Vec<Neighbor> >) > {
+fn load_point_v2<T:'static+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut dyn Read, descr: &Description, 
+                                                data_in: &mut dyn Read) -> Result<(Arc<Point<T>>, Vec<Vec<Neighbor> >), HnswIoError> {
     //
     // read and check magic
     let magic : u32 = 0;
     let it_slice = unsafe {::std::slice::from_raw_parts_mut((&magic as *const u32) as *mut u8, ::std::mem::size_of::<u32>() )};
     graph_in.read_exact(it_slice)?;
     if magic != MAGICPOINT {
-        log::debug!("got instead of MAGICPOINT {:x}", magic);
-        return Err(io::Error::new(io::ErrorKind::Other, "bad magic at point beginning"));
+        return Err(HnswIoError::BadMagic{section: "graph", found: magic, expected: MAGICPOINT});
     }
     let origin_id : DataId = 0;
     let it_slice = unsafe {::std::slice::from_raw_parts_mut((&origin_id as *const DataId) as *mut u8, 
@@ -346,12 +875,16 @@ fn load_point<T:'static+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut d
     let it_slice = unsafe {::std::slice::from_raw_parts_mut( (&magic as *const u32) as *mut u8, 
                                         ::std::mem::size_of::<u32>() )}; 
     data_in.read_exact(it_slice)?;
-    assert_eq!(magic, MAGICDATAP, "magic not equal to MAGICDATAP in load_point, point_id : {:?} ", origin_id);
+    if magic != MAGICDATAP {
+        return Err(HnswIoError::BadMagic{section: "data", found: magic, expected: MAGICDATAP});
+    }
     // read origin id
     let origin_id_data : usize = 0;
     let it_slice = unsafe {::std::slice::from_raw_parts_mut( (&origin_id_data as *const usize) as *mut u8, ::std::mem::size_of::<usize>() )};
     data_in.read_exact(it_slice)?;
-    assert_eq!(origin_id, origin_id_data, "origin_id incoherent between graph and data");
+    if origin_id != origin_id_data {
+        return Err(HnswIoError::Corrupted(format!("origin_id incoherent between graph {:?} and data {:?}", origin_id, origin_id_data)));
+    }
     // now read data. we use size_t that is in description, to take care of the casewhere we reload
     let serialized_len : u64 = 0;
     let it_slice = unsafe {::std::slice::from_raw_parts_mut( (&serialized_len as *const u64) as *mut u8, ::std::mem::size_of::<u64>() )};
@@ -363,7 +896,7 @@ fn load_point<T:'static+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut d
     data_in.read_exact(&mut v_serialized)?;
     let v : Vec<T>;
     if std::any::TypeId::of::<T>() != std::any::TypeId::of::<NoData>() {
-        v = bincode::deserialize(&v_serialized).unwrap();
+        v = bincode::deserialize(&v_serialized)?;
     }
     else {
         v = Vec::<T>::new();
@@ -372,67 +905,14 @@ fn load_point<T:'static+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut d
     log::trace!("load_point  origin {:?} allocated size {:?}, dim {:?}", origin_id, point.get_v().len(), descr.dimension);
     //
     return Ok((Arc::new(point), neighborhood));
-}  // end of load_point
-
-
-
-//
-// dump and load of PointIndexation<T>
-// ===================================
-//
-//
-// nb_layer : 8
-// a magick at each Layer : u32
-// . number of points in layer (usize), 
-// . list of point of layer
-// dump entry point
-// 
-impl <T:Serialize+DeserializeOwned+Clone+Send+Sync> HnswIO for PointIndexation<T> {
-    fn dump<W:Write>(&self, mode : DumpMode, graphout : &mut io::BufWriter<W>, dataout : &mut io::BufWriter<W>) -> Result<i32, String> {
-        // dump max_layer
-        let layers = self.points_by_layer.read();
-        let nb_layer = layers.len() as u8;
-        graphout.write(unsafe { &mem::transmute::<u8, [u8;1]>(nb_layer) } ).unwrap();
-        // dump layers from lower (most populatated to higher level)
-        for i in 0..layers.len() {
-            let nb_point = layers[i].len();
-            log::debug!("dumping layer {:?}, nb_point {:?}", i, nb_point);
-            graphout.write(unsafe { &mem::transmute::<u32, [u8;4]>(MAGICLAYER) } ).unwrap();
-            graphout.write(unsafe { &mem::transmute::<usize, [u8;8]>(nb_point) } ).unwrap();
-            for j in 0..layers[i].len() {
-                assert_eq!(layers[i][j].get_point_id() , PointId{0: i as u8,1:j as i32 });
-                dump_point(&layers[i][j], mode, graphout, dataout)?;
-            }
-        }
-        // dump id of entry point
-        let ep_read = self.entry_point.read();
-        assert!(ep_read.is_some());
-        let ep = ep_read.as_ref().unwrap();
-        graphout.write(unsafe { &mem::transmute::<DataId,[u8; ::std::mem::size_of::<DataId>()] >(ep.get_origin_id()) } ).unwrap();
-        let p_id = ep.get_point_id();
-        if mode == DumpMode::Full {
-            graphout.write(unsafe { &mem::transmute::<u8, [u8;1]>(p_id.0) } ).unwrap();
-            graphout.write(unsafe { &mem::transmute::<i32, [u8;4]>(p_id.1) } ).unwrap();
-        }
-        log::info!("dumped entry_point origin_d {:?}, p_id {:?} ", ep.get_origin_id(), p_id);
-        //
-        Ok(1)
-    } // end of dump for PointIndexation<T>
-} // end of impl HnswIO
+}  // end of load_point_v2
 
 
-fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut dyn Read, 
+fn load_point_indexation_v2<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut dyn Read, 
                 descr : &Description, 
-                data_in:  &mut dyn Read) -> io::Result<PointIndexation<T> > {
+                data_in:  &mut dyn Read) -> Result<PointIndexation<T>, HnswIoError> {
     //
-    log::debug!(" in load_point_indexation");
-    //
-    // now we check that except for the case NoData, the typename are the sames.
-    if std::any::TypeId::of::<T>() != std::any::TypeId::of::<NoData>() && std::any::type_name::<T>() != descr.t_name {
-        log::error!("typename loaded in  description {:?} do not correspond to instanciation type {:?}", 
-                            descr.t_name,std::any::type_name::<T>() );
-        panic!("incohrent size of T in description");
-    }
+    log::debug!(" in load_point_indexation_v2");
     //
     let mut points_by_layer : Vec<Vec<Arc<Point<T>> > >= Vec::with_capacity(NB_LAYER_MAX as usize);
     let mut neighbourhood_map : HashMap<PointId, Vec<Vec<Neighbor>> > =  HashMap::new();
@@ -442,7 +922,7 @@ fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+S
     graph_in.read_exact(it_slice)?;
     log::debug!("nb layer {:?}", nb_layer);
     if nb_layer > NB_LAYER_MAX {
-        return Err(io::Error::new(io::ErrorKind::Other, "inconsistent number of layErrers"));
+        return Err(HnswIoError::Corrupted(format!("number of layers {}", nb_layer)));
     }
     //
     let mut nb_points_loaded : usize = 0;
@@ -454,25 +934,25 @@ fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+S
         let it_slice = unsafe {::std::slice::from_raw_parts_mut((&magic as *const u32) as *mut u8, ::std::mem::size_of::<u32>() )};
         graph_in.read_exact(it_slice)?;
         if magic != MAGICLAYER {
-            return Err(io::Error::new(io::ErrorKind::Other, "bad magic at layer beginning"));
+            return Err(HnswIoError::BadMagic{section: "graph", found: magic, expected: MAGICLAYER});
         }
         let nbpoints : usize = 0;
         let it_slice = unsafe {::std::slice::from_raw_parts_mut((&nbpoints as *const usize) as *mut u8, ::std::mem::size_of::<usize>() )};
         graph_in.read_exact(it_slice)?;
+        if nb_points_loaded + nbpoints > descr.nb_point {
+            return Err(HnswIoError::Corrupted(format!("more points than the {} of the description", descr.nb_point)));
+        }
         log::debug!(" layer {:?} , nb points {:?}", l ,  nbpoints);
         let mut vlayer : Vec<Arc<Point<T>>> = Vec::with_capacity(nbpoints);
         for r in 0..nbpoints {
             // load graph and data part of point. Points are dumped in the same order.
-            let load_point_res = load_point(graph_in, descr, data_in)?;
+            let load_point_res = load_point_v2(graph_in, descr, data_in)?;
             let point = load_point_res.0;
             let p_id = point.get_point_id();
             // some checks
-            assert_eq!(l, p_id.0 as usize);
-            if r != p_id.1 as usize {
-                log::debug!("\n\n origin= {:?},  p_id = {:?}", point.get_origin_id(), p_id);
-                log::debug!("storing at l {:?}, r {:?}",  l, r);
+            if l != p_id.0 as usize || p_id.1 < 0 || r != p_id.1 as usize {
+                return Err(HnswIoError::Corrupted(format!("point {:?} stored at layer {} rank {}", p_id, l, r)));
             }
-            assert_eq!(r , p_id.1 as usize);
             // store neoghbour info of this point
             neighbourhood_map.insert(p_id, load_point_res.1);
             vlayer.push(Arc::clone(&point));
@@ -482,17 +962,17 @@ fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+S
     }
     // at this step all points are loaded , but without their neighbours fileds are not yet initialized
     for (p_id , neighbours) in &neighbourhood_map {
-        let point = &points_by_layer[p_id.0 as usize][p_id.1 as usize];
+        let point = get_loaded_point(&points_by_layer, *p_id, "graph")?;
         for l in 0..neighbours.len() {
             for n in &neighbours[l] {
-                let n_point = &points_by_layer[n.p_id.0 as usize][n.p_id.1 as usize];
+                let n_point = get_loaded_point(&points_by_layer, n.p_id, "neighbourhood")?;
                 // now n_point is the Arc<Point> corresponding to neighbour n of point, 
                 // construct a corresponding PointWithOrder
-                let n_pwo = PointWithOrder::<T>::new(&Arc::clone(&n_point), n.distance);
-                point.neighbours.write()[l].push(Arc::new(n_pwo));
+                let n_pwo = PointWithOrder::<T>::new(n_point, n.distance);
+                point.neighbours_write()[l].push(Arc::new(n_pwo));
             } // end of for n
             //  must sort
-            point.neighbours.write()[l].sort_unstable();
+            point.neighbours_write()[l].sort_unstable();
         } // end of for l
     } // end loop in neighbourhood_map
     // 
@@ -513,9 +993,19 @@ fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+S
     graph_in.read_exact(it_slice)?;
     //
     log::info!("found entry point, origin_id {:?} , layer {:?}, rank in layer {:?} ", origin_id, layer, rank_in_l);
-    let entry_point = Arc::clone(&points_by_layer[layer as usize][rank_in_l as usize]);
+    let entry_point = Arc::clone(get_loaded_point(&points_by_layer, PointId(layer, rank_in_l), "entry point")?);
+    if entry_point.get_origin_id() != origin_id {
+        return Err(HnswIoError::Corrupted(format!("entry point has origin id {:?}, not {:?}", entry_point.get_origin_id(), origin_id)));
+    }
     log::info!(" loaded entry point, origin_id {:} p_id {:?}", entry_point.get_origin_id(),entry_point.get_point_id());
-
+    //
+    let nb_deleted = load_deleted(graph_in, &points_by_layer)?;
+    let mut origin_index = hashbrown::HashMap::with_capacity(nb_points_loaded);
+    for layer in &points_by_layer {
+        for point in layer {
+            origin_index.insert(point.get_origin_id(), point.get_point_id());
+        }
+    }
     //
     let point_indexation = PointIndexation {
         max_nb_connection : descr.max_nb_connection as usize,
@@ -524,14 +1014,50 @@ fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+S
         layer_g : LayerGenerator::new(descr.max_nb_connection as usize , NB_LAYER_MAX as usize),
         nb_point : Arc::new(RwLock::new(nb_points_loaded)),   // CAVEAT , we should increase , the whole thing is to be able to increment graph ?
         entry_point : Arc::new(RwLock::new(Some(entry_point))),
+        origin_index : Arc::new(RwLock::new(origin_index)),
+        nb_deleted : Arc::new(RwLock::new(nb_deleted)),
     };
     //  
     log::debug!("\n exiting load_pointIndexation");
     Ok(point_indexation)
-} // end of load_pointIndexation
+} // end of load_point_indexation_v2
+
 
 
 
+// marks the points listed after MAGICDELETED as deleted and returns their number.
+// Dumps written before deletion existed end after the entry point: no deleted point.
+fn load_deleted<T:Clone+Send+Sync>(graph_in: &mut dyn Read, points_by_layer: &Vec<Vec<Arc<Point<T>>>>) -> Result<usize, HnswIoError> {
+    let magic : u32 = 0;
+    let it_slice = unsafe {::std::slice::from_raw_parts_mut((&magic as *const u32) as *mut u8, ::std::mem::size_of::<u32>() )};
+    match graph_in.read_exact(it_slice) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+        Err(e) => return Err(HnswIoError::Io(e)),
+    }
+    if magic != MAGICDELETED {
+        return Err(HnswIoError::BadMagic{section: "deleted points", found: magic, expected: MAGICDELETED});
+    }
+    let nb_deleted : usize = 0;
+    let it_slice = unsafe {::std::slice::from_raw_parts_mut((&nb_deleted as *const usize) as *mut u8, ::std::mem::size_of::<usize>() )};
+    graph_in.read_exact(it_slice)?;
+    for _ in 0..nb_deleted {
+        let layer : u8 = 0;
+        let it_slice = unsafe {::std::slice::from_raw_parts_mut((&layer as *const u8) as *mut u8, ::std::mem::size_of::<u8>() )};
+        graph_in.read_exact(it_slice)?;
+        let rank_in_l : i32 = 0;
+        let it_slice = unsafe {::std::slice::from_raw_parts_mut((&rank_in_l as *const i32) as *mut u8, ::std::mem::size_of::<i32>() )};
+        graph_in.read_exact(it_slice)?;
+        match points_by_layer.get(layer as usize).and_then(|l| l.get(rank_in_l as usize)) {
+            Some(point) => point.deleted.store(true, std::sync::atomic::Ordering::Release),
+            None => return Err(HnswIoError::Corrupted(format!("deleted point {:?} not in graph", PointId(layer, rank_in_l)))),
+        }
+    }
+    log::info!("loaded {:?} deleted points", nb_deleted);
+    Ok(nb_deleted)
+} // end of load_deleted
+
+
 //
 // dump and load of Hnsw<T>
 // =========================
@@ -539,33 +1065,22 @@ fn load_point_indexation<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+S
 //
 
 impl <T:Serialize+DeserializeOwned+Clone+Sized+Send+Sync, D: Distance<T>+Send+Sync> HnswIO for Hnsw<T, D> {
-    /// The dump method for hnsw.  
+    /// The dump method for hnsw.
     /// - graphout is a BufWriter dedicated to the dump of the graph part of Hnsw
     /// - dataout is a bufWriter dedicated to the dump of the data stored in the Hnsw structure.
-    fn dump<W:Write>(&self, mode : DumpMode, graphout : &mut io::BufWriter<W>, dataout : &mut io::BufWriter<W>) -> Result<i32, String> {
+    fn dump<W:Write>(&self, mode : DumpMode, graphout : &mut io::BufWriter<W>, dataout : &mut io::BufWriter<W>) -> Result<i32, HnswIoError> {
         // dump description , then PointIndexation
-        let dumpmode : u8 = match mode {
-                DumpMode::Full => 1,
-                            _  => 0,
-        };  
-        let datadim : usize = self.layer_indexed_points.get_data_dimension();
-
-        let description = Description {
-               ///  value is 1 for Full 0 for Light
-            dumpmode : dumpmode,
-            max_nb_connection : self.get_max_nb_connection(),
-            nb_layer : self.get_max_level() as u8,
-            ef: self.get_ef_construction(),
-            nb_point: self.get_nb_point(),
-            dimension : datadim,
-            distname : self.get_distance_name(),
-            t_name: type_name::<T>().to_string(),
-        };
-        log::debug!("dump  obtained typename {:?}", type_name::<T>());
+        let description = make_description(self, mode);
+        let datadim = description.dimension;
         description.dump(mode, graphout)?;
         // We must dump a header for dataout.
-        dataout.write(unsafe { &mem::transmute::<u32, [u8;4]>(MAGICDATAP) } ).unwrap();
-        dataout.write(unsafe { &mem::transmute::<usize, [u8;::std::mem::size_of::<usize>()]>(datadim) } ).unwrap();
+        {
+            let mut dataout = SectionWriter::new(dataout);
+            dataout.write_u32(MAGICDATAP)?;
+            dataout.write_u32(FORMAT_VERSION)?;
+            dataout.write_u64(datadim as u64)?;
+            dataout.end_section()?;
+        }
         //
         self.layer_indexed_points.dump(mode, graphout, dataout)?;
         Ok(1)
@@ -573,69 +1088,270 @@ impl <T:Serialize+DeserializeOwned+Clone+Sized+Send+Sync, D: Distance<T>+Send+Sy
 }   // end impl block for Hnsw
 
 
+// the description of hnsw as it is dumped
+pub(crate) fn make_description<T:Serialize+DeserializeOwned+Clone+Sized+Send+Sync, D: Distance<T>+Send+Sync>(hnsw : &Hnsw<T, D>, mode : DumpMode) -> Description {
+    let dumpmode : u8 = match mode {
+            DumpMode::Full => 1,
+                        _  => 0,
+    };
+    log::debug!("dump  obtained typename {:?}", type_name::<T>());
+    Description {
+        format_version : FORMAT_VERSION,
+           ///  value is 1 for Full 0 for Light
+        dumpmode : dumpmode,
+        max_nb_connection : hnsw.get_max_nb_connection(),
+        nb_layer : hnsw.layer_indexed_points.points_by_layer.read().len() as u8,
+        ef: hnsw.get_ef_construction(),
+        nb_point: hnsw.get_nb_point(),
+        dimension : hnsw.layer_indexed_points.get_data_dimension(),
+        distname : hnsw.get_distance_name(),
+        t_name: type_name::<T>().to_string(),
+    }
+} // end of make_description
 
-/// The reload is made in two steps.
-/// First a call to load_description must be used to get basic information
-/// about structure to reload (Typename, distance type, construction parameters).  
-/// Cf fn load_description(io_in: &mut dyn Read) -> io::Result<Description>
-///
-pub fn load_hnsw<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync, D:Distance<T>+Default+Send+Sync>(graph_in: &mut dyn Read, 
-                                            description: &Description, 
-                                            data_in : &mut dyn Read) -> io::Result<Hnsw<T,D> > {
-    //  In datafile , we must read MAGICDATAP and dimension and check
-    let magic : u32 = 0;
-    let it_slice = unsafe {::std::slice::from_raw_parts_mut( (&magic as *const u32) as *mut u8, 
-                                        ::std::mem::size_of::<u32>() )}; 
-    data_in.read_exact(it_slice)?;
-    assert_eq!(magic, MAGICDATAP, "magic not equal to MAGICDATAP in load_point");
-    let dimension : usize = 0;
-    let it_slice = unsafe {::std::slice::from_raw_parts_mut( (&dimension as *const usize) as *mut u8, 
-        ::std::mem::size_of::<usize>() )};
-    data_in.read_exact(it_slice)?;
-    assert_eq!(dimension, description.dimension, "data dimension incoherent {:?} {:?} ", 
-            dimension, description.dimension);
-    //
-    let _mode = description.dumpmode;
+
+// We must ensure that the distance and type stored match the ones asked for in loading hnsw
+pub(crate) fn check_description<T:'static, D:Distance<T>>(description : &Description) -> Result<(), HnswIoError> {
     let distname = description.distname.clone();
-    // We must ensure that the distance stored matches the one asked for in loading hnsw
-    // for that we check for short names equality stripping 
     log::debug!("distance in description = {:?}", distname);
     let d_type_name = type_name::<D>().to_string();
-    let v: Vec<&str> = d_type_name.rsplit_terminator("::").collect();
-    for s in v {
-        log::info!(" distname in generic type argument {:?}", s);
-    }
     if (std::any::TypeId::of::<T>() != std::any::TypeId::of::<NoData>())  &&  (d_type_name != distname) {
         // for all types except NoData , distance asked in reload declaration and distance in dump must be equal!
-        let mut errmsg = String::from("error in distances : dumped distance is : ");
-        errmsg.push_str(&distname);
-        errmsg.push_str(" asked distance in loading is : ");
-        errmsg.push_str(&d_type_name);
-        log::error!(" distance in type argument : {:?}", d_type_name);
-        log::error!("error , dump is for distance = {:?}", distname);
-        return Err(io::Error::new(io::ErrorKind::Other, errmsg));
-    }
-    let t_type = description.t_name.clone();
-    log::debug!("T type name in dump = {:?}", t_type);
-    let layer_point_indexation = load_point_indexation(graph_in, &description, data_in)?;
+        log::error!("error , dump is for distance = {:?}, asked {:?}", distname, d_type_name);
+        return Err(HnswIoError::DistanceMismatch{dumped: distname, asked: d_type_name});
+    }
+    // now we check that except for the case NoData, the typename are the sames.
+    if std::any::TypeId::of::<T>() != std::any::TypeId::of::<NoData>() && type_name::<T>() != description.t_name {
+        log::error!("typename loaded in  description {:?} do not correspond to instanciation type {:?}",
+                            description.t_name, type_name::<T>() );
+        return Err(HnswIoError::TypeMismatch{dumped: description.t_name.clone(), asked: type_name::<T>().to_string()});
+    }
+    log::debug!("T type name in dump = {:?}", description.t_name);
+    Ok(())
+} // end of check_description
+
+
+// the Hnsw around a reloaded point indexation
+pub(crate) fn hnsw_from_indexation<T:Serialize+DeserializeOwned+Clone+Sized+Send+Sync, D:Distance<T>+Default+Send+Sync>(description : &Description,
+                layer_point_indexation : PointIndexation<T>) -> Hnsw<T,D> {
     let data_dim = layer_point_indexation.get_data_dimension();
     //
-    let hnsw : Hnsw::<T,D> =  Hnsw{  max_nb_connection : description.max_nb_connection as usize,
-                        ef_construction : description.ef, 
-                        extend_candidates : true, 
-                        keep_pruned: false,
-                        max_layer: description.nb_layer as usize, 
-                        layer_indexed_points: layer_point_indexation,
-                        data_dimension : data_dim,
-                        dist_f: D::default(),
-                        searching : false,
-                    } ;
-    //
-    Ok(hnsw)
+    Hnsw{  max_nb_connection : description.max_nb_connection as usize,
+            ef_construction : description.ef,
+            extend_candidates : true,
+            keep_pruned: false,
+            max_layer: description.nb_layer as usize,
+            layer_indexed_points: layer_point_indexation,
+            data_dimension : data_dim,
+            dist_f: D::default(),
+            searching : std::sync::atomic::AtomicBool::new(false),
+            deterministic : false,
+            write_gate : RwLock::new(()),
+        }
+} // end of hnsw_from_indexation
+
+
+
+/// The reload is made in two steps.
+/// First a call to load_description must be used to get basic information
+/// about structure to reload (Typename, distance type, construction parameters).
+/// Cf fn load_description(io_in: &mut dyn Read) -> Result<Description, HnswIoError>
+///
+/// Every section of a dump in format version 3 is checked against its checksum,
+/// a truncated or corrupted dump is reported as an error.
+pub fn load_hnsw<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync, D:Distance<T>+Default+Send+Sync>(graph_in: &mut dyn Read,
+                                            description: &Description,
+                                            data_in : &mut dyn Read) -> Result<Hnsw<T,D>, HnswIoError> {
+    //
+    check_description::<T, D>(description)?;
+    let layer_point_indexation = match description.format_version {
+        2 => load_point_indexation_v2_with_header(graph_in, description, data_in)?,
+        FORMAT_VERSION => {
+            // In datafile , we must read MAGICDATAP, the version and dimension and check
+            let mut data_in = SectionReader::new(data_in, "data header");
+            data_in.check_magic(MAGICDATAP)?;
+            let version = data_in.read_u32()?;
+            if version != description.format_version {
+                return Err(HnswIoError::Corrupted(format!("data file in version {}, graph file in version {}", version, description.format_version)));
+            }
+            let dimension = data_in.read_u64()? as usize;
+            data_in.end_section("data")?;
+            if dimension != description.dimension {
+                return Err(HnswIoError::Corrupted(format!("data dimension incoherent {:?} {:?}", dimension, description.dimension)));
+            }
+            let mut graph_in = SectionReader::new(graph_in, "graph");
+            load_point_indexation(&mut graph_in, description, &mut data_in, None)?
+        },
+        version => return Err(HnswIoError::UnsupportedVersion(version)),
+    };
+    //
+    Ok(hnsw_from_indexation(description, layer_point_indexation))
 }  // end of load_hnsw
 
 
 
+/// Reloads the dump written by file_dump(basename), i.e the files basename.hnsw.graph and basename.hnsw.data.
+///
+/// The description is read from the dump, the distance and data type it names must be D and T
+/// else a DistanceMismatch or TypeMismatch error gives both names.
+pub fn load_hnsw_checked<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync, D:Distance<T>+Default+Send+Sync>(basename : &str)
+                        -> Result<Hnsw<T,D>, HnswIoError> {
+    let (mut graph_in, mut data_in) = open_dump(basename)?;
+    let description = load_description(&mut graph_in)?;
+    check_description::<T, D>(&description)?;
+    load_hnsw(&mut graph_in, &description, &mut data_in)
+} // end of load_hnsw_checked
+
+
+// the graph and data files of a dump
+fn open_dump(basename : &str) -> Result<(io::BufReader<std::fs::File>, io::BufReader<std::fs::File>), HnswIoError> {
+    let graph_in = io::BufReader::new(std::fs::File::open(format!("{}.hnsw.graph", basename))?);
+    let data_in = io::BufReader::new(std::fs::File::open(format!("{}.hnsw.data", basename))?);
+    Ok((graph_in, data_in))
+}
+
+
+/// A reloaded Hnsw whose data type and distance are only known from its dump, see load_hnsw_any.
+pub enum AnyHnsw {
+    F32(Box<dyn AnnT<Val=f32>>),
+    F64(Box<dyn AnnT<Val=f64>>),
+    U8(Box<dyn AnnT<Val=u8>>),
+    U16(Box<dyn AnnT<Val=u16>>),
+    U32(Box<dyn AnnT<Val=u32>>),
+    I32(Box<dyn AnnT<Val=i32>>),
+}
+
+impl AnyHnsw {
+    /// name of the data type, as in the description of the dump
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            AnyHnsw::F32(_) => type_name::<f32>(),
+            AnyHnsw::F64(_) => type_name::<f64>(),
+            AnyHnsw::U8(_) => type_name::<u8>(),
+            AnyHnsw::U16(_) => type_name::<u16>(),
+            AnyHnsw::U32(_) => type_name::<u32>(),
+            AnyHnsw::I32(_) => type_name::<i32>(),
+        }
+    }
+
+    /// statistics on the graph, see Hnsw::get_graph_stats
+    pub fn graph_stats(&self) -> GraphStats {
+        match self {
+            AnyHnsw::F32(hnsw) => hnsw.graph_stats(),
+            AnyHnsw::F64(hnsw) => hnsw.graph_stats(),
+            AnyHnsw::U8(hnsw) => hnsw.graph_stats(),
+            AnyHnsw::U16(hnsw) => hnsw.graph_stats(),
+            AnyHnsw::U32(hnsw) => hnsw.graph_stats(),
+            AnyHnsw::I32(hnsw) => hnsw.graph_stats(),
+        }
+    }
+}
+
+
+// loads the dump as Hnsw<T, D> for the first distance D of the list whose name is the one of the description
+macro_rules! load_with_distance (
+    ($t:ty, $description:expr, $graph_in:expr, $data_in:expr, $($d:ty),+) => ({
+        let distname = &$description.distname;
+        $(
+        if *distname == type_name::<$d>() {
+            let hnsw : Hnsw<$t, $d> = load_hnsw($graph_in, $description, $data_in)?;
+            return Ok(Box::new(hnsw));
+        }
+        )+
+    })
+);
+
+
+/// Reloads the dump written by file_dump(basename) with the distance named in its description.
+/// The data type of the dump must be T.
+pub fn load_hnsw_boxed<T:LoadBoxed>(basename : &str) -> Result<Box<dyn AnnT<Val=T>>, HnswIoError> {
+    let (mut graph_in, mut data_in) = open_dump(basename)?;
+    let description = load_description(&mut graph_in)?;
+    if description.t_name != type_name::<T>() {
+        return Err(HnswIoError::TypeMismatch{dumped: description.t_name.clone(), asked: type_name::<T>().to_string()});
+    }
+    T::load_boxed(&mut graph_in, &description, &mut data_in)
+} // end of load_hnsw_boxed
+
+
+/// Reloads the dump written by file_dump(basename) with the data type and distance named in its description,
+/// so that a tool can open any dump.
+///
+/// The supported data types are those of AnyHnsw, with the distances of dist implemented for them.
+pub fn load_hnsw_any(basename : &str) -> Result<AnyHnsw, HnswIoError> {
+    let (mut graph_in, mut data_in) = open_dump(basename)?;
+    let description = load_description(&mut graph_in)?;
+    let t_name = description.t_name.as_str();
+    let any = if t_name == type_name::<f32>() {
+        AnyHnsw::F32(f32::load_boxed(&mut graph_in, &description, &mut data_in)?)
+    } else if t_name == type_name::<f64>() {
+        AnyHnsw::F64(f64::load_boxed(&mut graph_in, &description, &mut data_in)?)
+    } else if t_name == type_name::<u8>() {
+        AnyHnsw::U8(u8::load_boxed(&mut graph_in, &description, &mut data_in)?)
+    } else if t_name == type_name::<u16>() {
+        AnyHnsw::U16(u16::load_boxed(&mut graph_in, &description, &mut data_in)?)
+    } else if t_name == type_name::<u32>() {
+        AnyHnsw::U32(u32::load_boxed(&mut graph_in, &description, &mut data_in)?)
+    } else if t_name == type_name::<i32>() {
+        AnyHnsw::I32(i32::load_boxed(&mut graph_in, &description, &mut data_in)?)
+    } else {
+        return Err(unknown_description(&description));
+    };
+    Ok(any)
+} // end of load_hnsw_any
+
+
+fn unknown_description(description : &Description) -> HnswIoError {
+    HnswIoError::UnknownDescription{distname: description.distname.clone(), t_name: description.t_name.clone()}
+}
+
+
+/// The data types of AnyHnsw, reloaded with the distances of dist implemented for them.
+pub trait LoadBoxed : 'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync {
+    fn load_boxed(graph_in : &mut dyn Read, description : &Description, data_in : &mut dyn Read) -> Result<Box<dyn AnnT<Val=Self>>, HnswIoError>;
+}
+
+macro_rules! implementLoadBoxed (
+    ($t:ty, $($d:ty),+) => (
+    impl LoadBoxed for $t {
+        fn load_boxed(graph_in : &mut dyn Read, description : &Description, data_in : &mut dyn Read) -> Result<Box<dyn AnnT<Val=$t>>, HnswIoError> {
+            load_with_distance!($t, description, graph_in, data_in, $($d),+);
+            Err(unknown_description(description))
+        }
+    }
+    )
+);
+
+implementLoadBoxed!(f32, DistL1, DistL2, DistCosine, DistDot, DistHellinger, DistJeffreys, DistJensenShannon);
+implementLoadBoxed!(f64, DistL1, DistL2, DistCosine, DistHellinger, DistJeffreys, DistJensenShannon);
+implementLoadBoxed!(u8, DistL1, DistL2, DistHamming, DistJaccard, DistL2Sq8, DistDotSq8);
+implementLoadBoxed!(u16, DistL1, DistL2, DistCosine, DistHamming, DistJaccard, DistLevenshtein);
+implementLoadBoxed!(u32, DistL1, DistL2, DistHamming, DistJaccard);
+implementLoadBoxed!(i32, DistL1, DistL2, DistCosine, DistHamming);
+
+
+// reload of the point indexation of a version 2 dump, starting with the header of the data file
+fn load_point_indexation_v2_with_header<T:'static+Serialize+DeserializeOwned+Clone+Sized+Send+Sync>(graph_in: &mut dyn Read,
+                description : &Description,
+                data_in:  &mut dyn Read) -> Result<PointIndexation<T>, HnswIoError> {
+    //  In datafile , we must read MAGICDATAP and dimension and check
+    let mut magic = [0u8; 4];
+    data_in.read_exact(&mut magic)?;
+    let magic = u32::from_ne_bytes(magic);
+    if magic != MAGICDATAP {
+        return Err(HnswIoError::BadMagic{section: "data header", found: magic, expected: MAGICDATAP});
+    }
+    let mut dimension = [0u8; 8];
+    data_in.read_exact(&mut dimension)?;
+    let dimension = u64::from_ne_bytes(dimension) as usize;
+    if dimension != description.dimension {
+        return Err(HnswIoError::Corrupted(format!("data dimension incoherent {:?} {:?}", dimension, description.dimension)));
+    }
+    load_point_indexation_v2(graph_in, description, data_in)
+} // end of load_point_indexation_v2_with_header
+
+
+
 
 //===============================================================================================================
 
@@ -797,6 +1513,270 @@ fn test_dump_reload_graph_only() {
 
 
 
+#[test]
+fn test_dump_reload_deleted() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 500;
+    let nbrow = 10;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..nbrow).map(|_| unif.sample(&mut rng)).collect()).collect();
+    let hnsw = Hnsw::<f32, dist::DistL1>::new(10, nbcolumn, 16, 25, dist::DistL1{});
+    for i in 0..data.len() {
+        hnsw.insert((&data[i], i));
+    }
+    for i in (0..nbcolumn).step_by(3) {
+        hnsw.delete(i);
+    }
+    // Must take care of name as tests runs in // !!!
+    let fname = String::from("dumpreloadtestdeleted");
+    hnsw.file_dump(&fname).unwrap();
+    let mut graph_in = BufReader::new(OpenOptions::new().read(true).open("dumpreloadtestdeleted.hnsw.graph").unwrap());
+    let mut data_in = BufReader::new(OpenOptions::new().read(true).open("dumpreloadtestdeleted.hnsw.data").unwrap());
+    let hnsw_description = load_description(&mut graph_in).unwrap();
+    let hnsw_loaded : Hnsw<f32,DistL1>= load_hnsw(&mut graph_in, &hnsw_description, &mut data_in).unwrap();
+    check_graph_equality(&hnsw_loaded, &hnsw);
+    assert_eq!(hnsw_loaded.get_nb_deleted(), hnsw.get_nb_deleted());
+    assert!(hnsw_loaded.is_deleted(3));
+    assert!(!hnsw_loaded.is_deleted(4));
+    // the origin index is rebuilt on reload
+    assert!(hnsw_loaded.delete(4));
+    let neighbours = hnsw_loaded.search(&data[4], 5, 30);
+    assert!(neighbours.iter().all(|n| n.d_id % 3 != 0 && n.d_id != 4));
+}  // end of test_dump_reload_deleted
+
+
+
+// dumps hnsw in memory, returns the graph and data bytes
+fn dump_in_memory<D:Distance<f32>+Send+Sync>(hnsw : &Hnsw<f32, D>) -> (Vec<u8>, Vec<u8>) {
+    let mut graphout = io::BufWriter::new(Vec::<u8>::new());
+    let mut dataout = io::BufWriter::new(Vec::<u8>::new());
+    hnsw.dump(DumpMode::Full, &mut graphout, &mut dataout).unwrap();
+    (graphout.into_inner().unwrap(), dataout.into_inner().unwrap())
+}
+
+fn load_in_memory<D:Distance<f32>+Default+Send+Sync>(graph : &[u8], data : &[u8]) -> Result<Hnsw<f32, D>, HnswIoError> {
+    let mut graph_in = io::Cursor::new(graph);
+    let mut data_in = io::Cursor::new(data);
+    let description = load_description(&mut graph_in)?;
+    load_hnsw(&mut graph_in, &description, &mut data_in)
+}
+
+
+#[test]
+fn test_dump_checksums() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 300;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..10).map(|_| unif.sample(&mut rng)).collect()).collect();
+    let hnsw = Hnsw::<f32, dist::DistL1>::new(10, nbcolumn, 16, 25, dist::DistL1{});
+    for i in 0..data.len() {
+        hnsw.insert((&data[i], i));
+    }
+    let (graph, data_bytes) = dump_in_memory(&hnsw);
+    let description = load_description(&mut io::Cursor::new(&graph)).unwrap();
+    assert_eq!(description.format_version, FORMAT_VERSION);
+    assert_eq!(description.dimension, 10);
+    assert_eq!(description.nb_point, nbcolumn);
+    let hnsw_loaded = load_in_memory::<DistL1>(&graph, &data_bytes).unwrap();
+    check_graph_equality(&hnsw_loaded, &hnsw);
+    // first byte of the first value of the first vector : header (20 bytes), magic, origin_id, length, bincode length
+    let mut corrupted = data_bytes.clone();
+    corrupted[20 + 4 + 8 + 8 + 8] ^= 0xff;
+    match load_in_memory::<DistL1>(&graph, &corrupted) {
+        Err(HnswIoError::ChecksumMismatch{section, ..}) => assert_eq!(section, "data"),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("corrupted dump loaded"),
+    }
+    match load_in_memory::<DistL1>(&graph[0..graph.len() / 2], &data_bytes) {
+        Err(HnswIoError::Truncated(section)) => assert_eq!(section, "graph"),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("truncated dump loaded"),
+    }
+    match load_in_memory::<DistL2>(&graph, &data_bytes) {
+        Err(HnswIoError::DistanceMismatch{..}) => (),
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("dump loaded with another distance"),
+    }
+}  // end of test_dump_checksums
+
+
+// a dump in format version 2 as written before format version 3, in native endianness:
+// two points of layer 0, neighbours of each other.
+fn dump_v2_by_hand() -> (Vec<u8>, Vec<u8>) {
+    let points : [(usize, Vec<f32>); 2] = [(10, vec![0., 0.]), (11, vec![1., 0.])];
+    let mut graph = Vec::<u8>::new();
+    graph.extend_from_slice(&MAGICDESCR_2.to_ne_bytes());
+    graph.extend_from_slice(&[1, 10, NB_LAYER_MAX]);
+    for v in [25usize, 2, 2].iter() {
+        graph.extend_from_slice(&v.to_ne_bytes());
+    }
+    for name in [type_name::<DistL1>(), type_name::<f32>()].iter() {
+        graph.extend_from_slice(&name.len().to_ne_bytes());
+        graph.extend_from_slice(name.as_bytes());
+    }
+    let mut data = Vec::<u8>::new();
+    data.extend_from_slice(&MAGICDATAP.to_ne_bytes());
+    data.extend_from_slice(&2usize.to_ne_bytes());
+    graph.push(NB_LAYER_MAX);
+    for l in 0..NB_LAYER_MAX {
+        graph.extend_from_slice(&MAGICLAYER.to_ne_bytes());
+        let nb_point : usize = if l == 0 { 2 } else { 0 };
+        graph.extend_from_slice(&nb_point.to_ne_bytes());
+        for r in 0..nb_point {
+            graph.extend_from_slice(&MAGICPOINT.to_ne_bytes());
+            graph.extend_from_slice(&points[r].0.to_ne_bytes());
+            graph.push(0);
+            graph.extend_from_slice(&(r as i32).to_ne_bytes());
+            for nl in 0..NB_LAYER_MAX {
+                if nl > 0 {
+                    graph.push(0);
+                    continue;
+                }
+                graph.push(1);
+                graph.extend_from_slice(&points[1 - r].0.to_ne_bytes());
+                graph.push(0);
+                graph.extend_from_slice(&((1 - r) as i32).to_ne_bytes());
+                graph.extend_from_slice(&1f32.to_ne_bytes());
+            }
+            let serialized = bincode::serialize(&points[r].1).unwrap();
+            data.extend_from_slice(&MAGICDATAP.to_ne_bytes());
+            data.extend_from_slice(&(points[r].0 as u64).to_ne_bytes());
+            data.extend_from_slice(&(serialized.len() as u64).to_ne_bytes());
+            data.extend_from_slice(&serialized);
+        }
+    }
+    // entry point
+    graph.extend_from_slice(&10usize.to_ne_bytes());
+    graph.push(0);
+    graph.extend_from_slice(&0i32.to_ne_bytes());
+    (graph, data)
+}
+
+
+#[test]
+fn test_reload_format_v2() {
+    let (graph, data) = dump_v2_by_hand();
+    let description = load_description(&mut io::Cursor::new(&graph)).unwrap();
+    assert_eq!(description.format_version, 2);
+    assert_eq!(description.nb_point, 2);
+    let hnsw = load_in_memory::<DistL1>(&graph, &data).unwrap();
+    assert_eq!(hnsw.get_nb_point(), 2);
+    assert_eq!(hnsw.get_nb_deleted(), 0);
+    let neighbours = hnsw.search(&vec![0.9, 0.], 2, 10);
+    assert_eq!(neighbours.iter().map(|n| n.d_id).collect::<Vec<usize>>(), vec![11, 10]);
+    // once reloaded it is dumped in the current format
+    let (graph_v3, data_v3) = dump_in_memory(&hnsw);
+    let hnsw_v3 = load_in_memory::<DistL1>(&graph_v3, &data_v3).unwrap();
+    check_graph_equality(&hnsw_v3, &hnsw);
+}  // end of test_reload_format_v2
+
+
+#[test]
+fn test_reload_format_v2_corrupted() {
+    let (graph, data) = dump_v2_by_hand();
+    let load_error = |graph : &[u8], data : &[u8]| load_in_memory::<DistL1>(graph, data).err().expect("corrupted dump loaded");
+    // offsets in the graph: description, number of layers, layer magic and size, first point
+    let distname_at = 4 + 3 + 3 * 8 + 8;
+    let description_len = distname_at + type_name::<DistL1>().len() + 8 + type_name::<f32>().len();
+    let first_point_at = description_len + 1 + 4 + 8;
+    // first neighbour of the first point: count, origin_id, layer, then its rank
+    let neighbour_rank_at = first_point_at + 4 + 8 + 1 + 4 + 1 + 8 + 1;
+    // first vector of the data: magic, origin_id, length, then the bincode length of the vector
+    let first_vector_at = 4 + 8;
+
+    let mut corrupted = graph.clone();
+    corrupted[distname_at] = 0xff;
+    match load_error(&corrupted, &data) {
+        HnswIoError::Corrupted(_) => (),
+        e => panic!("unexpected error {}", e),
+    }
+    let mut corrupted = graph.clone();
+    corrupted[first_point_at] ^= 0xff;
+    match load_error(&corrupted, &data) {
+        HnswIoError::BadMagic{section, ..} => assert_eq!(section, "graph"),
+        e => panic!("unexpected error {}", e),
+    }
+    let mut corrupted = graph.clone();
+    corrupted[neighbour_rank_at..neighbour_rank_at + 4].copy_from_slice(&7i32.to_ne_bytes());
+    match load_error(&corrupted, &data) {
+        HnswIoError::Corrupted(_) => (),
+        e => panic!("unexpected error {}", e),
+    }
+    // entry point at a rank not in the graph
+    let mut corrupted = graph.clone();
+    let len = corrupted.len();
+    corrupted[len - 4..].copy_from_slice(&7i32.to_ne_bytes());
+    match load_error(&corrupted, &data) {
+        HnswIoError::Corrupted(_) => (),
+        e => panic!("unexpected error {}", e),
+    }
+    let mut corrupted = data.clone();
+    corrupted[first_vector_at] ^= 0xff;
+    match load_error(&graph, &corrupted) {
+        HnswIoError::BadMagic{section, ..} => assert_eq!(section, "data"),
+        e => panic!("unexpected error {}", e),
+    }
+    let mut corrupted = data.clone();
+    corrupted[first_vector_at + 4] ^= 0xff;
+    match load_error(&graph, &corrupted) {
+        HnswIoError::Corrupted(_) => (),
+        e => panic!("unexpected error {}", e),
+    }
+    let mut corrupted = data.clone();
+    corrupted[first_vector_at + 4 + 8 + 8..first_vector_at + 4 + 8 + 8 + 8].copy_from_slice(&u64::MAX.to_ne_bytes());
+    match load_error(&graph, &corrupted) {
+        HnswIoError::Serialization(_) => (),
+        e => panic!("unexpected error {}", e),
+    }
+    match load_error(&graph[..graph.len() - 2], &data) {
+        HnswIoError::Io(_) => (),
+        e => panic!("unexpected error {}", e),
+    }
+}  // end of test_reload_format_v2_corrupted
+
+
+
+#[test]
+fn test_load_checked_and_any() {
+    let mut rng = rand::thread_rng();
+    let unif =  Uniform::<f32>::new(0.,1.);
+    let data : Vec<Vec<f32>> = (0..200).map(|_| (0..8).map(|_| unif.sample(&mut rng)).collect()).collect();
+    let hnsw = Hnsw::<f32, dist::DistL2>::new(10, data.len(), 16, 25, dist::DistL2{});
+    for i in 0..data.len() {
+        hnsw.insert((&data[i], i));
+    }
+    let basename = std::env::temp_dir().join(format!("thistle-checked-{}", std::process::id()))
+            .to_str().unwrap().to_string();
+    hnsw.file_dump(&basename).unwrap();
+    // another distance and type
+    match load_hnsw_checked::<f64, DistCosine>(&basename) {
+        Err(e @ HnswIoError::DistanceMismatch{..}) => {
+            let message = e.to_string();
+            assert!(message.contains("DistL2") && message.contains("DistCosine"), "{}", message);
+        },
+        _ => panic!("dump loaded with another distance"),
+    }
+    match load_hnsw_checked::<f64, DistL2>(&basename) {
+        Err(HnswIoError::TypeMismatch{dumped, asked}) => assert_eq!((dumped.as_str(), asked.as_str()), ("f32", "f64")),
+        _ => panic!("dump loaded with another type"),
+    }
+    let hnsw_checked = load_hnsw_checked::<f32, DistL2>(&basename).unwrap();
+    check_graph_equality(&hnsw_checked, &hnsw);
+    // the distance comes from the description
+    let boxed = load_hnsw_boxed::<f32>(&basename).unwrap();
+    assert_eq!(boxed.search_neighbours(&data[3], 1, 16)[0].d_id, 3);
+    assert!(load_hnsw_boxed::<u8>(&basename).is_err());
+    // and the type too
+    match load_hnsw_any(&basename).unwrap() {
+        AnyHnsw::F32(any) => assert_eq!(any.search_neighbours(&data[5], 1, 16)[0].d_id, 5),
+        other => panic!("reloaded with type {}", other.type_name()),
+    }
+    std::fs::remove_file(format!("{}.hnsw.graph", basename)).unwrap();
+    std::fs::remove_file(format!("{}.hnsw.data", basename)).unwrap();
+}  // end of test_load_checked_and_any
+
+
+
 #[test]
 fn test_bincode() {
     let mut rng = rand::thread_rng();
@@ -1,12 +1,15 @@
 // pub mod annhdf5;
 pub mod api;
+pub mod container;
 pub mod dist;
 pub mod flatten;
 pub mod hnsw;
 pub mod hnswio;
 pub mod libext;
+pub mod stats;
 
 // pub use annhdf5::*;
 pub use hnsw::*;
 pub use dist::*;
 pub use api::*;
+pub use stats::{CompactionReport, DegreeStats, GraphStats};
@@ -0,0 +1,241 @@
+//! Statistics on the graph of an Hnsw structure.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::hnswlib::hnsw::*;
+use crate::hnswlib::dist::Distance;
+
+/// Distribution of the number of neighbours of the points of a layer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DegreeStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+    /// histogram[d] is the number of points with d neighbours in the layer
+    pub histogram: Vec<usize>,
+}
+
+impl DegreeStats {
+    fn add(&mut self, degree: usize) {
+        if self.histogram.len() <= degree {
+            self.histogram.resize(degree + 1, 0);
+        }
+        self.histogram[degree] += 1;
+    }
+
+    // min, max and mean from the histogram
+    fn finish(&mut self) {
+        let nb_point: usize = self.histogram.iter().sum();
+        if nb_point == 0 {
+            return;
+        }
+        self.min = self.histogram.iter().position(|n| *n > 0).unwrap();
+        self.max = self.histogram.len() - 1;
+        let nb_links: usize = self.histogram.iter().enumerate().map(|(d, n)| d * n).sum();
+        self.mean = nb_links as f64 / nb_point as f64;
+    }
+}
+
+impl fmt::Display for DegreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "degree min {} mean {:.2} max {}, histogram", self.min, self.mean, self.max)?;
+        for (degree, nb_point) in self.histogram.iter().enumerate() {
+            if *nb_point > 0 {
+                write!(f, " {}:{}", degree, nb_point)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Summary of an Hnsw graph, as returned by Hnsw::get_graph_stats.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GraphStats {
+    /// number of points stored, deleted ones included
+    pub nb_point: usize,
+    /// number of points marked as deleted
+    pub nb_deleted: usize,
+    /// number of points in each layer, deleted ones included
+    pub points_by_layer: Vec<usize>,
+    /// number of neighbours in each layer of the points belonging to it
+    pub degree_by_layer: Vec<DegreeStats>,
+    /// number of live points no search can reach from the entry point
+    pub nb_unreachable: usize,
+    /// fraction of the neighbour lists holding the maximum number of neighbours
+    pub full_list_fraction: f64,
+    /// number of links from a live point to a deleted one
+    pub nb_links_to_deleted: usize,
+}
+
+impl GraphStats {
+    /// Signs of a badly built graph: unreachable points, isolated points.
+    /// Empty for a healthy graph.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.nb_unreachable > 0 {
+            problems.push(format!("{} live points cannot be reached from the entry point", self.nb_unreachable));
+        }
+        let nb_isolated = self.degree_by_layer.first().and_then(|degrees| degrees.histogram.first()).copied().unwrap_or(0);
+        if self.nb_point > 1 && nb_isolated > 0 {
+            problems.push(format!("{} points have no neighbour in layer 0", nb_isolated));
+        }
+        problems
+    }
+}
+
+impl fmt::Display for GraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "points: {}, deleted: {}", self.nb_point, self.nb_deleted)?;
+        for (layer, nb_point) in self.points_by_layer.iter().enumerate() {
+            if *nb_point > 0 {
+                writeln!(f, "layer {}: {} points, {}", layer, nb_point, self.degree_by_layer[layer])?;
+            }
+        }
+        writeln!(f, "unreachable points: {}", self.nb_unreachable)?;
+        writeln!(f, "full neighbour lists: {:.1}%", 100. * self.full_list_fraction)?;
+        writeln!(f, "links to deleted points: {}", self.nb_links_to_deleted)
+    }
+}
+
+impl <T:Clone+Send+Sync, D: Distance<T>+Send+Sync> Hnsw<T,D> {
+    /// Computes statistics on the graph. Takes a read lock on each point in turn.
+    pub fn get_graph_stats(&self) -> GraphStats {
+        // the entry point lock is taken before the points_by_layer one
+        let entry_point = self.layer_indexed_points.entry_point.read().clone();
+        let points_by_layer = self.layer_indexed_points.points_by_layer.read();
+        let nb_layer = points_by_layer.len();
+        let mut stats = GraphStats {
+            nb_point: self.get_nb_point(),
+            nb_deleted: self.get_nb_deleted(),
+            points_by_layer: points_by_layer.iter().map(|layer| layer.len()).collect(),
+            degree_by_layer: vec![DegreeStats::default(); nb_layer],
+            nb_unreachable: 0,
+            full_list_fraction: 0.,
+            nb_links_to_deleted: 0,
+        };
+        let mut nb_lists = 0usize;
+        let mut nb_full_lists = 0usize;
+        for layer in points_by_layer.iter() {
+            for point in layer.iter() {
+                let neighbours = point.neighbours_read();
+                // a point of layer i has neighbours in layers 0..=i
+                for l in 0..=(point.get_point_id().0 as usize).min(nb_layer - 1) {
+                    let degree = neighbours[l].len();
+                    stats.degree_by_layer[l].add(degree);
+                    let capacity = if l == 0 { 2 * self.max_nb_connection } else { self.max_nb_connection };
+                    nb_lists += 1;
+                    if degree >= capacity {
+                        nb_full_lists += 1;
+                    }
+                }
+                if !point.is_deleted() {
+                    for neighbours_l in neighbours.iter().take(nb_layer) {
+                        stats.nb_links_to_deleted += neighbours_l.iter().filter(|n| n.point_ref.is_deleted()).count();
+                    }
+                }
+            }
+        }
+        for degrees in stats.degree_by_layer.iter_mut() {
+            degrees.finish();
+        }
+        if nb_lists > 0 {
+            stats.full_list_fraction = nb_full_lists as f64 / nb_lists as f64;
+        }
+        // a search goes from the entry point through the links of all layers, deleted points included
+        let mut reached : Vec<Vec<bool>> = points_by_layer.iter().map(|layer| vec![false; layer.len()]).collect();
+        let mut to_visit = Vec::new();
+        if let Some(entry_point) = entry_point {
+            let p_id = entry_point.get_point_id();
+            reached[p_id.0 as usize][p_id.1 as usize] = true;
+            to_visit.push(entry_point);
+        }
+        while let Some(point) = to_visit.pop() {
+            let neighbours = point.neighbours_read();
+            for n in neighbours.iter().flat_map(|neighbours_l| neighbours_l.iter()) {
+                let p_id = n.point_ref.get_point_id();
+                if !reached[p_id.0 as usize][p_id.1 as usize] {
+                    reached[p_id.0 as usize][p_id.1 as usize] = true;
+                    to_visit.push(Arc::clone(&n.point_ref));
+                }
+            }
+        }
+        stats.nb_unreachable = points_by_layer.iter().zip(reached.iter())
+                .map(|(layer, reached)| layer.iter().zip(reached.iter()).filter(|(point, reached)| !**reached && !point.is_deleted()).count())
+                .sum();
+        stats
+    } // end of get_graph_stats
+}
+
+/// Graph statistics before and after Hnsw::compact.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactionReport {
+    pub before: GraphStats,
+    pub after: GraphStats,
+}
+
+impl CompactionReport {
+    /// number of points dropped by the compaction
+    pub fn nb_removed(&self) -> usize {
+        self.before.nb_point - self.after.nb_point
+    }
+}
+
+impl fmt::Display for CompactionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "removed {} points", self.nb_removed())?;
+        writeln!(f, "before:")?;
+        write!(f, "{}", self.before)?;
+        writeln!(f, "after:")?;
+        write!(f, "{}", self.after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+use super::*;
+use crate::hnswlib::dist;
+use rand::distributions::{Distribution, Uniform};
+
+#[test]
+fn test_graph_stats() {
+    let mut rng = rand::thread_rng();
+    let unif = Uniform::<f32>::new(0.,1.);
+    let nbcolumn = 2000;
+    let data : Vec<Vec<f32>> = (0..nbcolumn).map(|_| (0..10).map(|_| unif.sample(&mut rng)).collect()).collect();
+    let max_nb_connection = 12;
+    let hnsw = Hnsw::<f32, dist::DistL1>::new(max_nb_connection, nbcolumn, 16, 100, dist::DistL1{});
+    for i in 0..data.len() {
+        hnsw.insert((&data[i], i));
+    }
+    let stats = hnsw.get_graph_stats();
+    assert_eq!(stats.nb_point, nbcolumn);
+    assert_eq!(stats.points_by_layer.iter().sum::<usize>(), nbcolumn);
+    let layer0 = &stats.degree_by_layer[0];
+    assert_eq!(layer0.histogram.iter().sum::<usize>(), nbcolumn);
+    assert!(layer0.min > 0 && layer0.min as f64 <= layer0.mean && layer0.mean <= layer0.max as f64);
+    assert!(layer0.max <= 2 * max_nb_connection);
+    assert!(stats.full_list_fraction > 0. && stats.full_list_fraction <= 1.);
+    assert_eq!(stats.nb_unreachable, 0);
+    assert!(stats.problems().is_empty(), "{:?}", stats.problems());
+    // a point whose neighbours are all cut off is unreachable and isolated
+    {
+        let entry_point = hnsw.layer_indexed_points.entry_point.read().clone().unwrap();
+        let points_by_layer = hnsw.layer_indexed_points.points_by_layer.read();
+        let isolated = points_by_layer[0].iter().find(|p| !Arc::ptr_eq(p, &entry_point)).unwrap();
+        let isolated_id = isolated.get_point_id();
+        isolated.neighbours_write()[0].clear();
+        for layer in points_by_layer.iter() {
+            for point in layer.iter() {
+                point.neighbours_write()[0].retain(|n| n.point_ref.get_point_id() != isolated_id);
+            }
+        }
+    }
+    let stats = hnsw.get_graph_stats();
+    assert_eq!(stats.nb_unreachable, 1);
+    assert_eq!(stats.degree_by_layer[0].min, 0);
+    assert_eq!(stats.problems().len(), 2);
+} // end of test_graph_stats
+
+} // end of mod tests
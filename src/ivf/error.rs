@@ -0,0 +1,17 @@
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Index is not trained")]
+    NotTrained,
+    #[error("Vector has dimension {found}, the index expects {expected}")]
+    DimensionMismatch { found: usize, expected: usize },
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+    #[error(transparent)]
+    SerializationFailed(#[from] std::boxed::Box<bincode::ErrorKind>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
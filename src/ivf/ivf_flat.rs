@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use ndarray::prelude::*;
+use rand::seq::index::sample;
+use serde::{Deserialize, Serialize};
+
+use crate::ivf::error::{Error, Result};
+use crate::ivf::ivf_pq::{top_k, IvfNeighbor};
+use crate::ivf::kmeans::{l2_sq, nearest_row, KMeans};
+use crate::lsh::utils::create_rng;
+
+/// Vectors assigned to one centroid: their ids and their concatenated values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlatList {
+    pub ids: Vec<usize>,
+    pub vectors: Vec<f32>,
+}
+
+/// Inverted file index with full vectors (IVF-Flat).
+///
+/// Vectors are stored in the list of their closest k-means centroid and a query scans the
+/// `nprobe` closest lists exactly. Vectors can be inserted and deleted at any time without
+/// training again; the centroids are only recomputed by `retrain`, e.g. once the data has
+/// drifted away from the sample they were trained on.
+///
+/// Can be initialized following the Builder pattern.
+///
+/// # Example
+///
+/// ```
+/// let mut index = IvfFlat::new(768, 100).nprobe(8).seed(1);
+/// index.train(data.view())?;
+/// index.insert(42, &vector)?;
+/// index.delete(42);
+/// let neighbors = index.search(&query, 10)?;
+/// ```
+pub struct IvfFlat {
+    pub dim: usize,
+    /// number of centroids
+    pub nlist: usize,
+    /// lists scanned per query
+    pub nprobe: usize,
+    pub n_iter: usize,
+    pub centroids: Array2<f32>,
+    pub lists: Vec<FlatList>,
+    /// id -> (list, position in the list)
+    locations: HashMap<usize, (usize, usize)>,
+    /// seed for k-means. If 0, randomness is seeded from the os.
+    _seed: u64,
+}
+
+impl IvfFlat {
+    pub fn new(dim: usize, nlist: usize) -> IvfFlat {
+        IvfFlat {
+            dim,
+            nlist: nlist.max(1),
+            nprobe: 1,
+            n_iter: 20,
+            centroids: Array2::zeros((0, dim)),
+            lists: Vec::new(),
+            locations: HashMap::new(),
+            _seed: 0,
+        }
+    }
+
+    pub fn nprobe(mut self, nprobe: usize) -> Self {
+        self.nprobe = nprobe.max(1);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self._seed = seed;
+        self
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self._seed
+    }
+
+    pub fn n_iter(mut self, n_iter: usize) -> Self {
+        self.n_iter = n_iter;
+        self
+    }
+
+    pub fn is_trained(&self) -> bool {
+        self.centroids.nrows() > 0
+    }
+
+    /// Number of vectors stored.
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.locations.contains_key(&id)
+    }
+
+    fn check_dimension(&self, v: &[f32]) -> Result<()> {
+        if v.len() != self.dim {
+            return Err(Error::DimensionMismatch {
+                found: v.len(),
+                expected: self.dim,
+            });
+        }
+        Ok(())
+    }
+
+    /// Computes the centroids from the rows of `data`. Removes every stored vector.
+    pub fn train(&mut self, data: ArrayView2<f32>) -> Result<()> {
+        if data.nrows() == 0 {
+            return Err(Error::InvalidParameter(String::from("no training data")));
+        }
+        if data.ncols() != self.dim {
+            return Err(Error::DimensionMismatch {
+                found: data.ncols(),
+                expected: self.dim,
+            });
+        }
+        self.centroids = KMeans::new(self.nlist)
+            .n_iter(self.n_iter)
+            .seed(self._seed)
+            .fit(data);
+        self.lists = vec![FlatList::default(); self.centroids.nrows()];
+        self.locations.clear();
+        Ok(())
+    }
+
+    /// Trains `nlist` centroids again on at most `sample_size` of the stored vectors and
+    /// reassigns every vector to its new list. Ids are kept.
+    pub fn retrain(&mut self, nlist: usize, sample_size: usize) -> Result<()> {
+        let mut ids = Vec::with_capacity(self.len());
+        let mut vectors = Vec::with_capacity(self.len() * self.dim);
+        for list in self.lists.iter() {
+            ids.extend_from_slice(&list.ids);
+            vectors.extend_from_slice(&list.vectors);
+        }
+        if ids.is_empty() {
+            return Err(Error::InvalidParameter(String::from("no stored vector to retrain on")));
+        }
+        let data = Array2::from_shape_vec((ids.len(), self.dim), vectors).unwrap();
+        let mut rng = create_rng(self._seed);
+        let training = data.select(
+            Axis(0),
+            &sample(&mut rng, ids.len(), sample_size.max(1).min(ids.len())).into_vec(),
+        );
+
+        self.nlist = nlist.max(1);
+        self.train(training.view())?;
+        for (id, v) in ids.into_iter().zip(data.outer_iter()) {
+            self.insert(id, v.as_slice().unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Closest list.
+    fn assign(&self, v: &[f32]) -> usize {
+        nearest_row(self.centroids.view(), v).0
+    }
+
+    /// Stores `v` under `id`, replacing the vector previously stored under that id.
+    pub fn insert(&mut self, id: usize, v: &[f32]) -> Result<()> {
+        self.check_dimension(v)?;
+        if !self.is_trained() {
+            return Err(Error::NotTrained);
+        }
+        self.delete(id);
+        let cell = self.assign(v);
+        let list = &mut self.lists[cell];
+        self.locations.insert(id, (cell, list.ids.len()));
+        list.ids.push(id);
+        list.vectors.extend_from_slice(v);
+        Ok(())
+    }
+
+    /// Removes the vector stored under `id`. Returns false if there is none.
+    pub fn delete(&mut self, id: usize) -> bool {
+        let (cell, position) = match self.locations.remove(&id) {
+            Some(location) => location,
+            None => return false,
+        };
+        let dim = self.dim;
+        let list = &mut self.lists[cell];
+        let last = list.ids.len() - 1;
+        // the last vector of the list takes the place of the removed one
+        list.ids.swap_remove(position);
+        if position != last {
+            let (head, tail) = list.vectors.split_at_mut(last * dim);
+            head[position * dim..(position + 1) * dim].copy_from_slice(&tail[..dim]);
+            self.locations.insert(list.ids[position], (cell, position));
+        }
+        list.vectors.truncate(last * dim);
+        true
+    }
+
+    /// Vector stored under `id`.
+    pub fn get(&self, id: usize) -> Option<&[f32]> {
+        self.locations
+            .get(&id)
+            .map(|(cell, position)| &self.lists[*cell].vectors[position * self.dim..(position + 1) * self.dim])
+    }
+
+    /// The `k` nearest neighbours of `query` by squared L2 distance among the vectors of the
+    /// `nprobe` lists closest to it.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<IvfNeighbor>> {
+        self.check_dimension(query)?;
+        if !self.is_trained() {
+            return Err(Error::NotTrained);
+        }
+        let cells = self
+            .centroids
+            .outer_iter()
+            .enumerate()
+            .map(|(cell, centroid)| IvfNeighbor {
+                id: cell,
+                distance: l2_sq(centroid.as_slice().unwrap(), query),
+            });
+        let probed = top_k(cells, self.nprobe.min(self.centroids.nrows()));
+        let candidates = probed.into_iter().flat_map(|cell| {
+            let list = &self.lists[cell.id];
+            list.ids
+                .iter()
+                .zip(list.vectors.chunks_exact(self.dim))
+                .map(move |(id, v)| IvfNeighbor {
+                    id: *id,
+                    distance: l2_sq(v, query),
+                })
+        });
+        Ok(top_k(candidates, k))
+    }
+
+    /// Deserialize the index
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut f = File::open(path)?;
+        let mut buf: Vec<u8> = vec![];
+        f.read_to_end(&mut buf)?;
+
+        let ib: IntermediatBlob = bincode::deserialize(&buf)?;
+        self.centroids = bincode::deserialize(&ib.centroids)?;
+        self.lists = bincode::deserialize(&ib.lists)?;
+        self.dim = ib.dim;
+        self.nlist = ib.nlist;
+        self.nprobe = ib.nprobe;
+        self.n_iter = ib.n_iter;
+        self._seed = ib._seed;
+        self.locations = HashMap::new();
+        for (cell, list) in self.lists.iter().enumerate() {
+            for (position, id) in list.ids.iter().enumerate() {
+                self.locations.insert(*id, (cell, position));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Serialize the index
+    pub fn dump<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let centroids = bincode::serialize(&self.centroids)?;
+        let lists = bincode::serialize(&self.lists)?;
+
+        let ib = IntermediatBlob {
+            centroids,
+            lists,
+            dim: self.dim,
+            nlist: self.nlist,
+            nprobe: self.nprobe,
+            n_iter: self.n_iter,
+            _seed: self._seed,
+        };
+        let mut f = File::create(path)?;
+        let blob = bincode::serialize(&ib)?;
+        f.write_all(&blob)?;
+        Ok(())
+    }
+}
+
+/// Intermediate data structure for serialization, as for `LSH::dump`.
+#[derive(Serialize, Deserialize)]
+struct IntermediatBlob {
+    centroids: Vec<u8>,
+    lists: Vec<u8>,
+    dim: usize,
+    nlist: usize,
+    nprobe: usize,
+    n_iter: usize,
+    _seed: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::s;
+    use rand::Rng;
+
+    fn random_data(n: usize, dim: usize, seed: u64) -> Array2<f32> {
+        let mut rng = create_rng(seed);
+        Array2::from_shape_fn((n, dim), |_| rng.gen_range(-1f32, 1f32))
+    }
+
+    #[test]
+    fn test_ivf_flat_insert_delete_search() {
+        let data = random_data(500, 8, 3);
+        let mut index = IvfFlat::new(8, 10).nprobe(10).seed(1);
+        assert!(index.insert(0, &[0.; 8]).is_err());
+        index.train(data.view()).unwrap();
+        for (id, v) in data.outer_iter().enumerate() {
+            index.insert(id, v.as_slice().unwrap()).unwrap();
+        }
+        assert_eq!(500, index.len());
+
+        // every list is probed: the search is exact
+        let query = data.row(42).to_vec();
+        let neighbors = index.search(&query, 3).unwrap();
+        assert_eq!(42, neighbors[0].id);
+        assert_eq!(0., neighbors[0].distance);
+
+        assert!(index.delete(42));
+        assert!(!index.delete(42));
+        assert_eq!(499, index.len());
+        assert_ne!(42, index.search(&query, 1).unwrap()[0].id);
+        // the vectors moved by the deletion are still found under their id
+        for id in (0..500).filter(|id| *id != 42) {
+            assert_eq!(data.row(id).as_slice().unwrap(), index.get(id).unwrap());
+        }
+
+        index.insert(7, &query).unwrap();
+        assert_eq!(499, index.len());
+        assert_eq!(7, index.search(&query, 1).unwrap()[0].id);
+    }
+
+    #[test]
+    fn test_ivf_flat_retrain_and_dump() {
+        let data = random_data(300, 4, 5);
+        let mut index = IvfFlat::new(4, 2).nprobe(4).seed(1);
+        index.train(data.slice(s![..50, ..])).unwrap();
+        for (id, v) in data.outer_iter().enumerate() {
+            index.insert(id * 2, v.as_slice().unwrap()).unwrap();
+        }
+        index.retrain(4, 100).unwrap();
+        assert_eq!(4, index.lists.len());
+        assert_eq!(300, index.len());
+        assert_eq!(data.row(10).as_slice().unwrap(), index.get(20).unwrap());
+
+        let path = std::env::temp_dir().join(format!("thistle-ivf-flat-{}.bin", std::process::id()));
+        index.dump(&path).unwrap();
+        let mut loaded = IvfFlat::new(0, 1);
+        loaded.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(300, loaded.len());
+        let query = data.row(3).to_vec();
+        assert_eq!(index.search(&query, 5).unwrap(), loaded.search(&query, 5).unwrap());
+    }
+}
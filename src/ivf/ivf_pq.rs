@@ -0,0 +1,394 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use rand::seq::index::sample;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ivf::error::{Error, Result};
+use crate::ivf::kmeans::{kmeans, l2_sq};
+use crate::ivf::pq::ProductQuantizer;
+use crate::lsh::utils::create_rng;
+
+/// Vectors assigned to one coarse centroid: their ids and their concatenated PQ codes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InvertedList {
+    pub ids: Vec<usize>,
+    pub codes: Vec<u8>,
+}
+
+/// A search result, ordered by distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IvfNeighbor {
+    pub id: usize,
+    /// squared L2 distance, approximated from the codes
+    pub distance: f32,
+}
+
+impl Eq for IvfNeighbor {}
+
+impl PartialOrd for IvfNeighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IvfNeighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .partial_cmp(&other.distance)
+            .unwrap_or(Ordering::Equal)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+/// Keeps the `k` smallest neighbours seen, returns them sorted by increasing distance.
+pub(crate) fn top_k<I: Iterator<Item = IvfNeighbor>>(neighbors: I, k: usize) -> Vec<IvfNeighbor> {
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for neighbor in neighbors {
+        if heap.len() < k {
+            heap.push(neighbor);
+        } else if let Some(worst) = heap.peek() {
+            if neighbor < *worst {
+                heap.pop();
+                heap.push(neighbor);
+            }
+        }
+    }
+    heap.into_sorted_vec()
+}
+
+/// Inverted file index with product quantized residuals (IVF-PQ).
+///
+/// A coarse k-means quantizer splits the space in `nlist` cells. Each vector is stored in the
+/// list of its closest cell as the PQ code of its residual (vector - centroid). A query scans
+/// the `nprobe` closest cells, computing distances to the codes with a lookup table built
+/// once per cell (asymmetric distance computation, ADC).
+///
+/// Can be initialized following the Builder pattern.
+///
+/// # Example
+///
+/// ```
+/// let mut index = IvfPq::new(768, 100, 96).nprobe(8).seed(1);
+/// index.train(&vectors, 10_000)?;
+/// index.add_batch(&vectors)?;
+/// let neighbors = index.search(&query, 10)?;
+/// ```
+pub struct IvfPq {
+    pub dim: usize,
+    /// number of coarse cells
+    pub nlist: usize,
+    /// cells scanned per query
+    pub nprobe: usize,
+    pub n_subquantizers: usize,
+    /// centroids per PQ subspace, at most 256
+    pub ksub: usize,
+    pub n_iter: usize,
+    pub coarse_centroids: Vec<Vec<f32>>,
+    pub pq: Option<ProductQuantizer>,
+    pub lists: Vec<InvertedList>,
+    /// number of vectors added
+    pub ntotal: usize,
+    /// seed for k-means. If 0, randomness is seeded from the os.
+    _seed: u64,
+}
+
+impl IvfPq {
+    pub fn new(dim: usize, nlist: usize, n_subquantizers: usize) -> IvfPq {
+        IvfPq {
+            dim,
+            nlist: nlist.max(1),
+            nprobe: 1,
+            n_subquantizers,
+            ksub: 256,
+            n_iter: 20,
+            coarse_centroids: Vec::new(),
+            pq: None,
+            lists: Vec::new(),
+            ntotal: 0,
+            _seed: 0,
+        }
+    }
+
+    pub fn nprobe(mut self, nprobe: usize) -> Self {
+        self.nprobe = nprobe.max(1);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self._seed = seed;
+        self
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self._seed
+    }
+
+    pub fn ksub(mut self, ksub: usize) -> Self {
+        self.ksub = ksub;
+        self
+    }
+
+    pub fn n_iter(mut self, n_iter: usize) -> Self {
+        self.n_iter = n_iter;
+        self
+    }
+
+    pub fn is_trained(&self) -> bool {
+        self.pq.is_some()
+    }
+
+    fn check_dimension(&self, v: &[f32]) -> Result<()> {
+        if v.len() != self.dim {
+            return Err(Error::DimensionMismatch {
+                found: v.len(),
+                expected: self.dim,
+            });
+        }
+        Ok(())
+    }
+
+    /// Trains the coarse quantizer and the product quantizer on at most `sample_size`
+    /// vectors drawn from `data`. Empties the lists.
+    pub fn train(&mut self, data: &[Vec<f32>], sample_size: usize) -> Result<()> {
+        if data.is_empty() {
+            return Err(Error::InvalidParameter(String::from("no training data")));
+        }
+        for v in data.iter() {
+            self.check_dimension(v)?;
+        }
+        let sample_size = sample_size.max(1).min(data.len());
+        let mut rng = create_rng(self._seed);
+        let training: Vec<Vec<f32>> = sample(&mut rng, data.len(), sample_size)
+            .into_iter()
+            .map(|i| data[i].clone())
+            .collect();
+
+        self.coarse_centroids = kmeans(&training, self.nlist, self.n_iter, self._seed);
+        self.nlist = self.coarse_centroids.len();
+        let residuals: Vec<Vec<f32>> = training
+            .par_iter()
+            .map(|v| self.residual(v, self.assign(v)))
+            .collect();
+        self.pq = Some(ProductQuantizer::train(
+            &residuals,
+            self.dim,
+            self.n_subquantizers,
+            self.ksub.min(residuals.len()),
+            self.n_iter,
+            self._seed,
+        )?);
+        self.lists = vec![InvertedList::default(); self.nlist];
+        self.ntotal = 0;
+        Ok(())
+    }
+
+    /// Closest coarse cell.
+    pub(crate) fn assign(&self, v: &[f32]) -> usize {
+        crate::ivf::kmeans::nearest(&self.coarse_centroids, v).0
+    }
+
+    fn residual(&self, v: &[f32], cell: usize) -> Vec<f32> {
+        v.iter()
+            .zip(self.coarse_centroids[cell].iter())
+            .map(|(x, c)| x - c)
+            .collect()
+    }
+
+    pub fn add(&mut self, id: usize, v: &[f32]) -> Result<()> {
+        self.check_dimension(v)?;
+        let pq = match &self.pq {
+            Some(pq) => pq,
+            None => return Err(Error::NotTrained),
+        };
+        let cell = self.assign(v);
+        let codes = pq.encode(&self.residual(v, cell));
+        self.lists[cell].ids.push(id);
+        self.lists[cell].codes.extend_from_slice(&codes);
+        self.ntotal += 1;
+        Ok(())
+    }
+
+    /// Adds `data`, vector `i` getting the id `ntotal + i`.
+    pub fn add_batch(&mut self, data: &[Vec<f32>]) -> Result<()> {
+        for v in data.iter() {
+            self.check_dimension(v)?;
+        }
+        let pq = match &self.pq {
+            Some(pq) => pq,
+            None => return Err(Error::NotTrained),
+        };
+        let encoded: Vec<(usize, Vec<u8>)> = data
+            .par_iter()
+            .map(|v| {
+                let cell = self.assign(v);
+                (cell, pq.encode(&self.residual(v, cell)))
+            })
+            .collect();
+        for (cell, codes) in encoded {
+            self.lists[cell].ids.push(self.ntotal);
+            self.lists[cell].codes.extend_from_slice(&codes);
+            self.ntotal += 1;
+        }
+        Ok(())
+    }
+
+    /// The `nprobe` cells closest to `query`.
+    pub(crate) fn probe(&self, query: &[f32]) -> Vec<usize> {
+        let cells = self
+            .coarse_centroids
+            .iter()
+            .enumerate()
+            .map(|(cell, centroid)| IvfNeighbor {
+                id: cell,
+                distance: l2_sq(query, centroid),
+            });
+        top_k(cells, self.nprobe.min(self.nlist))
+            .into_iter()
+            .map(|neighbor| neighbor.id)
+            .collect()
+    }
+
+    /// The `k` approximate nearest neighbours of `query` by squared L2 distance.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<IvfNeighbor>> {
+        self.check_dimension(query)?;
+        let pq = match &self.pq {
+            Some(pq) => pq,
+            None => return Err(Error::NotTrained),
+        };
+        let code_size = pq.code_size();
+        let candidates = self.probe(query).into_iter().flat_map(|cell| {
+            let table = pq.distance_table(&self.residual(query, cell));
+            let list = &self.lists[cell];
+            list.ids
+                .iter()
+                .zip(list.codes.chunks_exact(code_size))
+                .map(|(id, codes)| IvfNeighbor {
+                    id: *id,
+                    distance: pq.table_distance(&table, codes),
+                })
+                .collect::<Vec<IvfNeighbor>>()
+        });
+        Ok(top_k(candidates, k))
+    }
+
+    /// Deserialize the index
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut f = File::open(path)?;
+        let mut buf: Vec<u8> = vec![];
+        f.read_to_end(&mut buf)?;
+
+        let ib: IntermediatBlob = bincode::deserialize(&buf)?;
+        self.coarse_centroids = bincode::deserialize(&ib.coarse_centroids)?;
+        self.pq = bincode::deserialize(&ib.pq)?;
+        self.lists = bincode::deserialize(&ib.lists)?;
+        self.dim = ib.dim;
+        self.nlist = ib.nlist;
+        self.nprobe = ib.nprobe;
+        self.n_subquantizers = ib.n_subquantizers;
+        self.ksub = ib.ksub;
+        self.n_iter = ib.n_iter;
+        self.ntotal = ib.ntotal;
+        self._seed = ib._seed;
+
+        Ok(())
+    }
+
+    /// Serialize the index
+    pub fn dump<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let coarse_centroids = bincode::serialize(&self.coarse_centroids)?;
+        let pq = bincode::serialize(&self.pq)?;
+        let lists = bincode::serialize(&self.lists)?;
+
+        let ib = IntermediatBlob {
+            coarse_centroids,
+            pq,
+            lists,
+            dim: self.dim,
+            nlist: self.nlist,
+            nprobe: self.nprobe,
+            n_subquantizers: self.n_subquantizers,
+            ksub: self.ksub,
+            n_iter: self.n_iter,
+            ntotal: self.ntotal,
+            _seed: self._seed,
+        };
+        let mut f = File::create(path)?;
+        let blob = bincode::serialize(&ib)?;
+        f.write_all(&blob)?;
+        Ok(())
+    }
+}
+
+/// Intermediate data structure for serialization, as for `LSH::dump`.
+#[derive(Serialize, Deserialize)]
+struct IntermediatBlob {
+    coarse_centroids: Vec<u8>,
+    pq: Vec<u8>,
+    lists: Vec<u8>,
+    dim: usize,
+    nlist: usize,
+    nprobe: usize,
+    n_subquantizers: usize,
+    ksub: usize,
+    n_iter: usize,
+    ntotal: usize,
+    _seed: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    fn random_data(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = create_rng(seed);
+        (0..n)
+            .map(|_| (0..dim).map(|_| rng.gen_range(-1f32, 1f32)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_ivf_pq_search() {
+        let data = random_data(1000, 16, 3);
+        let mut index = IvfPq::new(16, 10, 8).nprobe(10).seed(1);
+        assert!(index.search(&data[0], 1).is_err());
+        index.train(&data, 1000).unwrap();
+        index.add_batch(&data).unwrap();
+        assert_eq!(1000, index.ntotal);
+
+        // with every cell probed, a stored vector is (almost always) its own nearest neighbour
+        let mut found = 0;
+        for i in 0..100 {
+            let neighbors = index.search(&data[i], 5).unwrap();
+            assert_eq!(5, neighbors.len());
+            assert!(neighbors[0].distance <= neighbors[4].distance);
+            if neighbors.iter().any(|neighbor| neighbor.id == i) {
+                found += 1;
+            }
+        }
+        assert!(found >= 90, "found {} / 100", found);
+        assert!(index.search(&data[0][..8], 1).is_err());
+    }
+
+    #[test]
+    fn test_ivf_pq_dump_load() {
+        let data = random_data(300, 8, 5);
+        let mut index = IvfPq::new(8, 4, 4).nprobe(2).seed(1);
+        index.train(&data, 300).unwrap();
+        index.add_batch(&data).unwrap();
+        let path = std::env::temp_dir().join(format!("thistle-ivf-pq-{}.bin", std::process::id()));
+        index.dump(&path).unwrap();
+
+        let mut loaded = IvfPq::new(0, 1, 1);
+        loaded.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(index.ntotal, loaded.ntotal);
+        assert_eq!(2, loaded.nprobe);
+        assert_eq!(index.search(&data[7], 3).unwrap(), loaded.search(&data[7], 3).unwrap());
+    }
+}
@@ -0,0 +1,171 @@
+use rand::seq::index::sample;
+use rand::Rng;
+use ndarray::parallel::prelude::*;
+use ndarray::prelude::*;
+
+use crate::lsh::utils::create_rng;
+
+/// Squared L2 distance.
+pub fn l2_sq(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// Index of the centroid closest to `v` and its squared distance.
+pub fn nearest(centroids: &[Vec<f32>], v: &[f32]) -> (usize, f32) {
+    let mut best = (0, f32::MAX);
+    for (i, centroid) in centroids.iter().enumerate() {
+        let dist = l2_sq(centroid, v);
+        if dist < best.1 {
+            best = (i, dist);
+        }
+    }
+    best
+}
+
+/// Index of the row of `centroids` closest to `v` and its squared distance.
+pub fn nearest_row(centroids: ArrayView2<f32>, v: &[f32]) -> (usize, f32) {
+    let mut best = (0, f32::MAX);
+    for (i, centroid) in centroids.outer_iter().enumerate() {
+        let dist = match centroid.as_slice() {
+            Some(centroid) => l2_sq(centroid, v),
+            None => l2_sq(&centroid.to_vec(), v),
+        };
+        if dist < best.1 {
+            best = (i, dist);
+        }
+    }
+    best
+}
+
+/// Lloyd's k-means trainer.
+///
+/// Can be initialized following the Builder pattern.
+///
+/// # Example
+///
+/// ```
+/// let centroids = KMeans::new(100).n_iter(25).seed(1).fit(data.view());
+/// ```
+pub struct KMeans {
+    pub k: usize,
+    pub n_iter: usize,
+    /// If 0, randomness is seeded from the os.
+    _seed: u64,
+}
+
+impl KMeans {
+    pub fn new(k: usize) -> KMeans {
+        KMeans {
+            k,
+            n_iter: 20,
+            _seed: 0,
+        }
+    }
+
+    pub fn n_iter(mut self, n_iter: usize) -> Self {
+        self.n_iter = n_iter;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self._seed = seed;
+        self
+    }
+
+    /// Clusters the rows of `data`. Centroids start on `k` distinct random rows, a cluster
+    /// left empty by an iteration is moved to a random row. Stops early once no centroid moves.
+    ///
+    /// Returns `min(k, data.nrows())` centroids, one per row.
+    pub fn fit(&self, data: ArrayView2<f32>) -> Array2<f32> {
+        let (n, dim) = data.dim();
+        let k = self.k.min(n);
+        if k == 0 {
+            return Array2::zeros((0, dim));
+        }
+        let mut rng = create_rng(self._seed);
+        let mut centroids = data.select(Axis(0), &sample(&mut rng, n, k).into_vec());
+
+        for _ in 0..self.n_iter {
+            let assignments: Vec<usize> = data
+                .axis_iter(Axis(0))
+                .into_par_iter()
+                .map(|v| match v.as_slice() {
+                    Some(v) => nearest_row(centroids.view(), v).0,
+                    None => nearest_row(centroids.view(), &v.to_vec()).0,
+                })
+                .collect();
+
+            let mut sums = Array2::<f32>::zeros((k, dim));
+            let mut counts = vec![0usize; k];
+            for (v, cluster) in data.outer_iter().zip(assignments.iter()) {
+                counts[*cluster] += 1;
+                let mut sum = sums.row_mut(*cluster);
+                sum += &v;
+            }
+
+            let mut moved = false;
+            for cluster in 0..k {
+                let centroid = if counts[cluster] == 0 {
+                    data.row(rng.gen_range(0, n)).to_owned()
+                } else {
+                    sums.row(cluster).mapv(|x| x / counts[cluster] as f32)
+                };
+                if centroid != centroids.row(cluster) {
+                    moved = true;
+                    centroids.row_mut(cluster).assign(&centroid);
+                }
+            }
+            if !moved {
+                break;
+            }
+        }
+        centroids
+    }
+}
+
+/// `KMeans` on rows given as vectors of the same length.
+pub fn kmeans(data: &[Vec<f32>], k: usize, n_iter: usize, seed: u64) -> Vec<Vec<f32>> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let dim = data[0].len();
+    let flat: Vec<f32> = data.iter().flat_map(|v| v.iter().cloned()).collect();
+    let data = ArrayView2::from_shape((data.len(), dim), &flat).unwrap();
+    KMeans::new(k)
+        .n_iter(n_iter)
+        .seed(seed)
+        .fit(data)
+        .outer_iter()
+        .map(|centroid| centroid.to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kmeans_separates_clusters() {
+        let mut data = Vec::new();
+        for i in 0..50 {
+            let noise = (i % 5) as f32 * 0.01;
+            data.push(vec![noise, 1. + noise]);
+            data.push(vec![10. + noise, -noise]);
+        }
+        let mut centroids = kmeans(&data, 2, 20, 1);
+        centroids.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap());
+        assert!(l2_sq(&centroids[0], &[0.02, 1.02]) < 1e-3);
+        assert!(l2_sq(&centroids[1], &[10.02, -0.02]) < 1e-3);
+        assert_eq!(3, kmeans(&data[..3], 10, 5, 1).len());
+    }
+
+    #[test]
+    fn test_kmeans_is_seeded() {
+        let data = Array2::from_shape_fn((200, 4), |(i, j)| ((i * 7 + j * 13) % 17) as f32);
+        let a = KMeans::new(5).seed(3).fit(data.view());
+        let b = KMeans::new(5).seed(3).fit(data.view());
+        assert_eq!(a, b);
+        assert_eq!((5, 4), a.dim());
+        assert_eq!(0., nearest_row(a.view(), &a.row(2).to_vec()).1);
+    }
+}
@@ -0,0 +1,13 @@
+//! Inverted file indexes: vectors are grouped by their closest k-means centroid and a query
+//! only scans the lists of its `nprobe` closest centroids.
+pub mod ivf_flat;
+pub mod ivf_pq;
+pub mod kmeans;
+pub mod pq;
+mod error;
+
+pub use error::{Error, Result};
+pub use ivf_flat::IvfFlat;
+pub use ivf_pq::{IvfNeighbor, IvfPq};
+pub use kmeans::KMeans;
+pub use pq::ProductQuantizer;
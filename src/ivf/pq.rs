@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ivf::error::{Error, Result};
+use crate::ivf::kmeans::{kmeans, l2_sq, nearest};
+
+/// Product quantizer: vectors are cut in `n_subquantizers` sub-vectors of `dsub` dimensions,
+/// each one is replaced by the index (one byte) of its closest centroid in that subspace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductQuantizer {
+    pub dim: usize,
+    pub n_subquantizers: usize,
+    /// dimensions per sub-vector
+    pub dsub: usize,
+    /// centroids per subspace, at most 256
+    pub ksub: usize,
+    /// `centroids[m][c]` is centroid `c` of subspace `m`
+    pub centroids: Vec<Vec<Vec<f32>>>,
+}
+
+impl ProductQuantizer {
+    /// Trains the codebooks of every subspace with k-means on `data`.
+    pub fn train(
+        data: &[Vec<f32>],
+        dim: usize,
+        n_subquantizers: usize,
+        ksub: usize,
+        n_iter: usize,
+        seed: u64,
+    ) -> Result<ProductQuantizer> {
+        if n_subquantizers == 0 || dim % n_subquantizers != 0 {
+            return Err(Error::InvalidParameter(format!(
+                "dimension {} is not a multiple of the number of subquantizers {}",
+                dim, n_subquantizers
+            )));
+        }
+        if ksub == 0 || ksub > 256 {
+            return Err(Error::InvalidParameter(format!("ksub {} is not in 1..=256", ksub)));
+        }
+        let dsub = dim / n_subquantizers;
+        let centroids = (0..n_subquantizers)
+            .map(|m| {
+                let sub_data: Vec<Vec<f32>> = data
+                    .iter()
+                    .map(|v| v[m * dsub..(m + 1) * dsub].to_vec())
+                    .collect();
+                let seed = if seed == 0 { 0 } else { seed + m as u64 };
+                kmeans(&sub_data, ksub, n_iter, seed)
+            })
+            .collect::<Vec<Vec<Vec<f32>>>>();
+        let ksub = centroids.iter().map(|c| c.len()).min().unwrap_or(0);
+        Ok(ProductQuantizer {
+            dim,
+            n_subquantizers,
+            dsub,
+            ksub,
+            centroids,
+        })
+    }
+
+    /// Size of a code in bytes.
+    pub fn code_size(&self) -> usize {
+        self.n_subquantizers
+    }
+
+    pub fn encode(&self, v: &[f32]) -> Vec<u8> {
+        (0..self.n_subquantizers)
+            .map(|m| nearest(&self.centroids[m], &v[m * self.dsub..(m + 1) * self.dsub]).0 as u8)
+            .collect()
+    }
+
+    pub fn decode(&self, codes: &[u8]) -> Vec<f32> {
+        let mut v = Vec::with_capacity(self.dim);
+        for (m, code) in codes.iter().enumerate() {
+            v.extend_from_slice(&self.centroids[m][*code as usize]);
+        }
+        v
+    }
+
+    /// Asymmetric distance computation table: squared distances between each sub-vector of
+    /// `query` and each centroid of its subspace, `table[m * ksub + c]`.
+    pub fn distance_table(&self, query: &[f32]) -> Vec<f32> {
+        let mut table = vec![f32::MAX; self.n_subquantizers * self.ksub];
+        for m in 0..self.n_subquantizers {
+            let sub_query = &query[m * self.dsub..(m + 1) * self.dsub];
+            for (c, centroid) in self.centroids[m].iter().enumerate().take(self.ksub) {
+                table[m * self.ksub + c] = l2_sq(sub_query, centroid);
+            }
+        }
+        table
+    }
+
+    /// Squared distance between the query of `table` and the vector encoded as `codes`.
+    #[inline]
+    pub fn table_distance(&self, table: &[f32], codes: &[u8]) -> f32 {
+        codes
+            .iter()
+            .enumerate()
+            .map(|(m, code)| table[m * self.ksub + *code as usize])
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lsh::utils::create_rng;
+    use rand::Rng;
+
+    #[test]
+    fn test_pq_encode_and_adc() {
+        let mut rng = create_rng(7);
+        let data: Vec<Vec<f32>> = (0..500)
+            .map(|_| (0..16).map(|_| rng.gen_range(-1f32, 1f32)).collect())
+            .collect();
+        let pq = ProductQuantizer::train(&data, 16, 4, 64, 10, 1).unwrap();
+        assert_eq!(4, pq.code_size());
+
+        let codes = pq.encode(&data[0]);
+        let decoded = pq.decode(&codes);
+        // the reconstruction is closer to the vector than the average vector is
+        let mean_sq_norm = data.iter().map(|v| l2_sq(v, &[0.; 16])).sum::<f32>() / data.len() as f32;
+        assert!(l2_sq(&decoded, &data[0]) < mean_sq_norm);
+
+        let table = pq.distance_table(&data[1]);
+        let adc = pq.table_distance(&table, &codes);
+        assert!((adc - l2_sq(&data[1], &decoded)).abs() < 1e-3);
+
+        assert!(ProductQuantizer::train(&data, 16, 3, 64, 10, 1).is_err());
+    }
+}
@@ -1,7 +1,10 @@
 pub mod filemod;
 pub mod foldermodule;
 pub mod database;
+#[cfg(feature = "bert")]
 pub mod model;
 pub mod hnswlib;
+pub mod ivf;
 pub mod lsh;
 pub mod evaluation;
+pub mod role;
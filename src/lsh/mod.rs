@@ -18,7 +18,7 @@ mod error;
 #[cfg(feature = "workspace")]
 pub mod utils;
 #[cfg(not(feature = "workspace"))]
-mod utils;
+pub(crate) mod utils;
 pub use hash::VecHash;
 pub use multi_probe::{QueryDirectedProbe, StepWiseProbe};
 pub use table::{general::HashTables, mem::MemoryTable};
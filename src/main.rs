@@ -4,5 +4,10 @@ use std::fs;
 use thistle::evaluation::run_eval::{time_and_accuracy};
 
 fn main() {
-    time_and_accuracy();
+    // `--embedding-cache <path>` reuses the embeddings across methods, the times then exclude encoding
+    let args: Vec<String> = std::env::args().collect();
+    let embedding_cache = args.iter()
+        .position(|arg| arg == "--embedding-cache")
+        .and_then(|i| args.get(i + 1));
+    time_and_accuracy(embedding_cache.map(|path| path.as_str()));
 }
@@ -1,7 +1,6 @@
 use std::path::Path;
 
 use rust_bert::bert::{BertConfig, BertEmbeddings, BertModel};
-use rust_bert::Config;
 use tch::nn::VarStore;
 use tch::{nn, no_grad, Device, Tensor};
 
@@ -11,6 +10,8 @@ use rust_tokenizers::preprocessing::tokenizer::base_tokenizer::{
 use rust_tokenizers::{BertTokenizer, BertVocab};
 use tch::index::IndexOp;
 
+use crate::model::checkpoint::read_json_config;
+
 #[derive(Debug, Default)]
 pub struct Features {
     pub input_ids: Option<Tensor>,
@@ -62,6 +63,7 @@ pub struct Bert {
     max_seq_length: i64,
     cls_token_id: i64,
     sep_token_id: i64,
+    pub bert_config: BertConfig,
     pub vs: VarStore,
 }
 
@@ -71,7 +73,20 @@ impl Bert {
         max_seq_length: Option<i64>,
         do_lower_case: Option<bool>,
         device: Device,
-    ) -> Bert {
+    ) -> failure::Fallible<Bert> {
+        let mut bert = Bert::build(model_path, max_seq_length, do_lower_case, device)?;
+        bert.load_weights(model_path)?;
+        Ok(bert)
+    }
+
+    /// Builds the model graph and tokenizer without loading the weights, so that callers can
+    /// register extra heads in `vs` before calling `load_weights`.
+    pub fn build(
+        model_path: &Path,
+        max_seq_length: Option<i64>,
+        do_lower_case: Option<bool>,
+        device: Device,
+    ) -> failure::Fallible<Bert> {
         let max_seq_length = if let Some(value) = max_seq_length {
             value
         } else {
@@ -90,32 +105,53 @@ impl Bert {
             max_seq_length
         };
 
-        let mut vs = nn::VarStore::new(device);
+        let vs = nn::VarStore::new(device);
 
         let bert_config_path = model_path.join("config.json");
         let bert_vocab_path = model_path.join("vocab.txt");
-        let weights_path = model_path.join("rust_model.ot");
 
-        let bert_config = BertConfig::from_file(bert_config_path.as_path());
+        let bert_config: BertConfig = read_json_config(&bert_config_path)?;
         let bert: BertModel<BertEmbeddings> = BertModel::new(&(&vs.root() / "bert"), &bert_config);
 
-        let tokenizer = BertTokenizer::from_file(bert_vocab_path.to_str().unwrap(), do_lower_case);
+        // the tokenizer panics on a missing vocabulary
+        if !bert_vocab_path.is_file() {
+            failure::bail!("{:?} not found", bert_vocab_path);
+        }
+        let bert_vocab_path = bert_vocab_path
+            .to_str()
+            .ok_or_else(|| failure::format_err!("{:?} is not valid unicode", bert_vocab_path))?;
+        let tokenizer = BertTokenizer::from_file(bert_vocab_path, do_lower_case);
         let cls_token_id =
             tokenizer.convert_tokens_to_ids(&[String::from(BertVocab::cls_value())].to_vec())[0];
         let sep_token_id =
             tokenizer.convert_tokens_to_ids(&[String::from(BertVocab::sep_value())].to_vec())[0];
 
-        vs.load(Path::new(&weights_path))
-            .expect("Failed to load weights!");
-
-        Bert {
+        Ok(Bert {
             bert,
             tokenizer,
             max_seq_length,
             cls_token_id,
             sep_token_id,
+            bert_config,
             vs,
+        })
+    }
+
+    /// Maximum number of tokens per sequence, `[CLS]` and `[SEP]` excluded.
+    pub fn get_max_seq_length(&self) -> i64 {
+        self.max_seq_length
+    }
+
+    pub fn load_weights(&mut self, model_path: &Path) -> failure::Fallible<()> {
+        let weights_path = model_path.join("rust_model.ot");
+        if let Err(error) = self.vs.load(Path::new(&weights_path)) {
+            failure::bail!(
+                "Failed to load weights from {:?}: {}. `thistle-model inspect` on the model directory reports what does not match.",
+                weights_path,
+                error
+            );
         }
+        Ok(())
     }
 
     pub fn forward_t(&self, features: Features) -> Features {
@@ -160,7 +196,19 @@ impl Bert {
         tokens: &[i64],
         pad_seq_length: usize,
     ) -> (Vec<i64>, Vec<i64>, Vec<i64>, Vec<i64>) {
-        let mut pad_seq_length = pad_seq_length.min(self.max_seq_length as usize);
+        self.get_sentence_features_with_max(tokens, pad_seq_length, self.max_seq_length as usize)
+    }
+
+    /// Same as `get_sentence_features` with a caller provided max sequence length, which is
+    /// still capped by the model's own `max_seq_length`.
+    pub fn get_sentence_features_with_max(
+        &self,
+        tokens: &[i64],
+        pad_seq_length: usize,
+        max_seq_length: usize,
+    ) -> (Vec<i64>, Vec<i64>, Vec<i64>, Vec<i64>) {
+        let max_seq_length = max_seq_length.min(self.max_seq_length as usize);
+        let mut pad_seq_length = pad_seq_length.min(max_seq_length);
 
         let tokens = if pad_seq_length < tokens.len() {
             &tokens[..pad_seq_length as usize]
@@ -197,4 +245,50 @@ impl Bert {
             vec![sentence_length as i64],
         )
     }
+
+    /// Builds the features of a sentence pair as `[CLS] a [SEP] b [SEP]`, with token_type_ids
+    /// set to 1 on the second segment. The longest segment is truncated first so that the pair
+    /// fits in `max_seq_length`.
+    pub fn get_pair_features(
+        &self,
+        tokens_a: &[i64],
+        tokens_b: &[i64],
+        pad_seq_length: usize,
+    ) -> (Vec<i64>, Vec<i64>, Vec<i64>) {
+        // one more special token than for a single sentence
+        let max_pair_length = self.max_seq_length as usize - 1;
+        let mut len_a = tokens_a.len();
+        let mut len_b = tokens_b.len();
+        while len_a + len_b > max_pair_length {
+            if len_a > len_b {
+                len_a -= 1;
+            } else {
+                len_b -= 1;
+            }
+        }
+        let pad_seq_length = pad_seq_length.min(max_pair_length) + 3;
+
+        let mut input_ids: Vec<i64> = vec![self.cls_token_id]
+            .into_iter()
+            .chain(tokens_a[..len_a].iter().cloned())
+            .chain(vec![self.sep_token_id])
+            .collect();
+        let mut token_type_ids = vec![0; input_ids.len()];
+        input_ids.extend(&tokens_b[..len_b]);
+        input_ids.push(self.sep_token_id);
+        token_type_ids.extend(vec![1; len_b + 1]);
+        let mut input_mask = vec![1; input_ids.len()];
+
+        // Zero-pad up to the sequence length. Bert: Pad to the right
+        let padding = vec![0; pad_seq_length - input_ids.len()];
+        input_ids.extend(&padding);
+        token_type_ids.extend(&padding);
+        input_mask.extend(&padding);
+
+        assert_eq!(input_ids.len(), pad_seq_length);
+        assert_eq!(input_mask.len(), pad_seq_length);
+        assert_eq!(token_type_ids.len(), pad_seq_length);
+
+        (input_ids, token_type_ids, input_mask)
+    }
 }
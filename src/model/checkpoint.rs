@@ -0,0 +1,1081 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use rust_bert::bert::{BertConfig, BertEmbeddings, BertModel};
+use serde::de::DeserializeOwned;
+use tch::{nn, Device, Kind, Tensor};
+
+/// Magic number opening the legacy (pre torch 1.6) `torch.save` format.
+const LEGACY_MAGIC_NUMBER: i128 = 0x1950a86a20f9469cfc6c;
+
+/// A tensor of the checkpoint, before its storage is read.
+#[derive(Debug, Clone)]
+struct TensorRef {
+    storage_key: String,
+    storage_type: String,
+    storage_offset: i64,
+    size: Vec<i64>,
+    stride: Vec<i64>,
+}
+
+/// The subset of pickle values `torch.save` uses for a state dict.
+#[derive(Debug, Clone)]
+enum Value {
+    None,
+    Bool(bool),
+    Int(i64),
+    /// ints that do not fit in an i64, like the legacy magic number
+    Long(i128),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Value>),
+    List(Vec<Value>),
+    Dict(Vec<(Value, Value)>),
+    Global(String, String),
+    Reduce(Box<Value>, Box<Value>),
+    PersistentId(Box<Value>),
+    Tensor(TensorRef),
+    Mark,
+}
+
+/// A minimal pickle virtual machine, protocol 2 to 4. Objects are not instantiated: the
+/// only calls it understands are `OrderedDict()` and the torch tensor rebuild functions.
+struct Unpickler<'a> {
+    data: &'a [u8],
+    position: usize,
+    stack: Vec<Value>,
+    memo: HashMap<u32, Value>,
+}
+
+impl<'a> Unpickler<'a> {
+    fn new(data: &'a [u8], position: usize) -> Unpickler<'a> {
+        Unpickler {
+            data,
+            position,
+            stack: Vec::new(),
+            memo: HashMap::new(),
+        }
+    }
+
+    fn read(&mut self, length: usize) -> failure::Fallible<&'a [u8]> {
+        if self.position + length > self.data.len() {
+            failure::bail!("unexpected end of pickle at byte {}", self.position);
+        }
+        let data = self.data;
+        let bytes = &data[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> failure::Fallible<u8> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> failure::Fallible<u16> {
+        let bytes = self.read(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> failure::Fallible<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.read(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> failure::Fallible<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.read(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_line(&mut self) -> failure::Fallible<String> {
+        let start = self.position;
+        while self.read_u8()? != b'\n' {}
+        Ok(String::from_utf8_lossy(&self.data[start..self.position - 1]).to_string())
+    }
+
+    fn read_string(&mut self, length: usize) -> failure::Fallible<String> {
+        Ok(String::from_utf8(self.read(length)?.to_vec())?)
+    }
+
+    fn pop(&mut self) -> failure::Fallible<Value> {
+        match self.stack.pop() {
+            Some(value) => Ok(value),
+            None => failure::bail!("pickle stack underflow at byte {}", self.position),
+        }
+    }
+
+    fn top(&mut self) -> failure::Fallible<&mut Value> {
+        match self.stack.last_mut() {
+            Some(value) => Ok(value),
+            None => failure::bail!("pickle stack underflow at byte {}", self.position),
+        }
+    }
+
+    /// Pops the values pushed since the last `MARK`.
+    fn pop_mark(&mut self) -> failure::Fallible<Vec<Value>> {
+        let mut values = Vec::new();
+        loop {
+            match self.pop()? {
+                Value::Mark => break,
+                value => values.push(value),
+            }
+        }
+        values.reverse();
+        Ok(values)
+    }
+
+    fn memoize(&mut self, index: u32) -> failure::Fallible<()> {
+        let value = self.top()?.clone();
+        self.memo.insert(index, value);
+        Ok(())
+    }
+
+    fn memo_get(&mut self, index: u32) -> failure::Fallible<()> {
+        match self.memo.get(&index) {
+            Some(value) => {
+                let value = value.clone();
+                self.stack.push(value);
+                Ok(())
+            }
+            None => failure::bail!("missing pickle memo entry {}", index),
+        }
+    }
+
+    fn set_items(&mut self, items: Vec<Value>) -> failure::Fallible<()> {
+        match self.top()? {
+            Value::Dict(entries) => {
+                let mut items = items.into_iter();
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    entries.push((key, value));
+                }
+                Ok(())
+            }
+            other => failure::bail!("SETITEMS on a non dict value {:?}", other),
+        }
+    }
+
+    fn append_items(&mut self, items: Vec<Value>) -> failure::Fallible<()> {
+        match self.top()? {
+            Value::List(values) => {
+                values.extend(items);
+                Ok(())
+            }
+            other => failure::bail!("APPENDS on a non list value {:?}", other),
+        }
+    }
+
+    /// Runs the pickle starting at the current position until `STOP`.
+    fn load(&mut self) -> failure::Fallible<Value> {
+        loop {
+            let opcode = self.read_u8()?;
+            match opcode {
+                // PROTO
+                0x80 => {
+                    self.read_u8()?;
+                }
+                // FRAME
+                0x95 => {
+                    self.read_u64()?;
+                }
+                // STOP
+                b'.' => return self.pop(),
+                b'(' => self.stack.push(Value::Mark),
+                b'N' => self.stack.push(Value::None),
+                0x88 => self.stack.push(Value::Bool(true)),
+                0x89 => self.stack.push(Value::Bool(false)),
+                // BININT, BININT1, BININT2
+                b'J' => {
+                    let value = self.read_u32()? as i32;
+                    self.stack.push(Value::Int(value as i64));
+                }
+                b'K' => {
+                    let value = self.read_u8()?;
+                    self.stack.push(Value::Int(value as i64));
+                }
+                b'M' => {
+                    let value = self.read_u16()?;
+                    self.stack.push(Value::Int(value as i64));
+                }
+                // LONG1, little endian two's complement
+                0x8a => {
+                    let length = self.read_u8()? as usize;
+                    let bytes = self.read(length)?;
+                    let mut value: i128 = 0;
+                    for (i, byte) in bytes.iter().enumerate().take(16) {
+                        value |= (*byte as i128) << (8 * i);
+                    }
+                    if length > 0 && length < 16 && bytes[length - 1] & 0x80 != 0 {
+                        value -= 1i128 << (8 * length);
+                    }
+                    if value >= i64::MIN as i128 && value <= i64::MAX as i128 {
+                        self.stack.push(Value::Int(value as i64));
+                    } else {
+                        self.stack.push(Value::Long(value));
+                    }
+                }
+                // BINFLOAT, big endian
+                b'G' => {
+                    let mut bytes = [0u8; 8];
+                    bytes.copy_from_slice(self.read(8)?);
+                    self.stack.push(Value::Float(f64::from_be_bytes(bytes)));
+                }
+                // BINUNICODE, SHORT_BINUNICODE, BINUNICODE8
+                b'X' => {
+                    let length = self.read_u32()? as usize;
+                    let value = self.read_string(length)?;
+                    self.stack.push(Value::Str(value));
+                }
+                0x8c => {
+                    let length = self.read_u8()? as usize;
+                    let value = self.read_string(length)?;
+                    self.stack.push(Value::Str(value));
+                }
+                0x8d => {
+                    let length = self.read_u64()? as usize;
+                    let value = self.read_string(length)?;
+                    self.stack.push(Value::Str(value));
+                }
+                // BINSTRING, SHORT_BINSTRING (python 2 str)
+                b'T' => {
+                    let length = self.read_u32()? as usize;
+                    let value = String::from_utf8_lossy(self.read(length)?).to_string();
+                    self.stack.push(Value::Str(value));
+                }
+                b'U' => {
+                    let length = self.read_u8()? as usize;
+                    let value = String::from_utf8_lossy(self.read(length)?).to_string();
+                    self.stack.push(Value::Str(value));
+                }
+                // BINBYTES, SHORT_BINBYTES
+                b'B' => {
+                    let length = self.read_u32()? as usize;
+                    let value = self.read(length)?.to_vec();
+                    self.stack.push(Value::Bytes(value));
+                }
+                b'C' => {
+                    let length = self.read_u8()? as usize;
+                    let value = self.read(length)?.to_vec();
+                    self.stack.push(Value::Bytes(value));
+                }
+                b')' => self.stack.push(Value::Tuple(Vec::new())),
+                b']' => self.stack.push(Value::List(Vec::new())),
+                b'}' => self.stack.push(Value::Dict(Vec::new())),
+                b't' => {
+                    let values = self.pop_mark()?;
+                    self.stack.push(Value::Tuple(values));
+                }
+                0x85 => {
+                    let first = self.pop()?;
+                    self.stack.push(Value::Tuple(vec![first]));
+                }
+                0x86 => {
+                    let second = self.pop()?;
+                    let first = self.pop()?;
+                    self.stack.push(Value::Tuple(vec![first, second]));
+                }
+                0x87 => {
+                    let third = self.pop()?;
+                    let second = self.pop()?;
+                    let first = self.pop()?;
+                    self.stack.push(Value::Tuple(vec![first, second, third]));
+                }
+                b'l' => {
+                    let values = self.pop_mark()?;
+                    self.stack.push(Value::List(values));
+                }
+                b'd' => {
+                    let values = self.pop_mark()?;
+                    self.stack.push(Value::Dict(Vec::new()));
+                    self.set_items(values)?;
+                }
+                b'a' => {
+                    let value = self.pop()?;
+                    self.append_items(vec![value])?;
+                }
+                b'e' => {
+                    let values = self.pop_mark()?;
+                    self.append_items(values)?;
+                }
+                b's' => {
+                    let value = self.pop()?;
+                    let key = self.pop()?;
+                    self.set_items(vec![key, value])?;
+                }
+                b'u' => {
+                    let values = self.pop_mark()?;
+                    self.set_items(values)?;
+                }
+                // BINPUT, LONG_BINPUT, MEMOIZE
+                b'q' => {
+                    let index = self.read_u8()? as u32;
+                    self.memoize(index)?;
+                }
+                b'r' => {
+                    let index = self.read_u32()?;
+                    self.memoize(index)?;
+                }
+                0x94 => {
+                    let index = self.memo.len() as u32;
+                    self.memoize(index)?;
+                }
+                // BINGET, LONG_BINGET
+                b'h' => {
+                    let index = self.read_u8()? as u32;
+                    self.memo_get(index)?;
+                }
+                b'j' => {
+                    let index = self.read_u32()?;
+                    self.memo_get(index)?;
+                }
+                // GLOBAL, STACK_GLOBAL
+                b'c' => {
+                    let module = self.read_line()?;
+                    let name = self.read_line()?;
+                    self.stack.push(Value::Global(module, name));
+                }
+                0x93 => {
+                    let name = self.pop()?;
+                    let module = self.pop()?;
+                    match (module, name) {
+                        (Value::Str(module), Value::Str(name)) => {
+                            self.stack.push(Value::Global(module, name))
+                        }
+                        other => failure::bail!("STACK_GLOBAL expects two strings, got {:?}", other),
+                    }
+                }
+                // REDUCE, NEWOBJ
+                b'R' | 0x81 => {
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let value = reduce(callable, args)?;
+                    self.stack.push(value);
+                }
+                // BUILD, the state of the objects we keep does not matter
+                b'b' => {
+                    self.pop()?;
+                }
+                b'Q' => {
+                    let persistent_id = self.pop()?;
+                    self.stack.push(Value::PersistentId(Box::new(persistent_id)));
+                }
+                // POP, POP_MARK, DUP
+                b'0' => {
+                    self.pop()?;
+                }
+                b'1' => {
+                    self.pop_mark()?;
+                }
+                b'2' => {
+                    let value = self.top()?.clone();
+                    self.stack.push(value);
+                }
+                opcode => failure::bail!(
+                    "unsupported pickle opcode 0x{:02x} at byte {}",
+                    opcode,
+                    self.position - 1
+                ),
+            }
+        }
+    }
+}
+
+fn reduce(callable: Value, args: Value) -> failure::Fallible<Value> {
+    let name = match &callable {
+        Value::Global(module, name) => format!("{}.{}", module, name),
+        _ => String::new(),
+    };
+    let mut args = match args {
+        Value::Tuple(args) => args,
+        args => return Ok(Value::Reduce(Box::new(callable), Box::new(args))),
+    };
+    match name.as_str() {
+        "collections.OrderedDict" => Ok(Value::Dict(Vec::new())),
+        // (storage, storage_offset, size, stride, requires_grad, backward_hooks)
+        "torch._utils._rebuild_tensor" | "torch._utils._rebuild_tensor_v2" => {
+            if args.len() < 4 {
+                failure::bail!("{} expects at least 4 arguments", name);
+            }
+            let (storage_key, storage_type) = storage_of(&args[0])?;
+            Ok(Value::Tensor(TensorRef {
+                storage_key,
+                storage_type,
+                storage_offset: as_int(&args[1])?,
+                size: as_ints(&args[2])?,
+                stride: as_ints(&args[3])?,
+            }))
+        }
+        // (tensor, requires_grad, backward_hooks)
+        "torch._utils._rebuild_parameter" if !args.is_empty() => Ok(args.swap_remove(0)),
+        _ => Ok(Value::Reduce(Box::new(callable), Box::new(Value::Tuple(args)))),
+    }
+}
+
+/// The persistent id of a storage is `('storage', storage_type, key, location, numel, ...)`.
+fn storage_of(value: &Value) -> failure::Fallible<(String, String)> {
+    if let Value::PersistentId(persistent_id) = value {
+        if let Value::Tuple(fields) = persistent_id.as_ref() {
+            if let (Some(Value::Global(_, storage_type)), Some(Value::Str(key))) =
+                (fields.get(1), fields.get(2))
+            {
+                return Ok((key.clone(), storage_type.clone()));
+            }
+        }
+    }
+    failure::bail!("expected a tensor storage, got {:?}", value)
+}
+
+fn as_int(value: &Value) -> failure::Fallible<i64> {
+    match value {
+        Value::Int(value) => Ok(*value),
+        Value::Bool(value) => Ok(*value as i64),
+        other => failure::bail!("expected an int, got {:?}", other),
+    }
+}
+
+fn as_ints(value: &Value) -> failure::Fallible<Vec<i64>> {
+    match value {
+        Value::Tuple(values) | Value::List(values) => values.iter().map(as_int).collect(),
+        other => failure::bail!("expected a tuple of ints, got {:?}", other),
+    }
+}
+
+fn element_size(storage_type: &str) -> failure::Fallible<usize> {
+    match storage_type {
+        "DoubleStorage" | "LongStorage" => Ok(8),
+        "FloatStorage" | "IntStorage" => Ok(4),
+        "HalfStorage" => Ok(2),
+        other => failure::bail!("unsupported storage type {}", other),
+    }
+}
+
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // subnormal, renormalize the mantissa
+            let mut exponent = 127 - 15 + 1;
+            let mut mantissa = mantissa;
+            while mantissa & 0x400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (exponent << 23) | ((mantissa & 0x3ff) << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+/// Copies the elements of `tensor` out of its storage in row-major order and builds a
+/// contiguous tch tensor. Half tensors are widened to f32, as `Bert` runs in f32.
+fn materialize(tensor: &TensorRef, storage: &[u8]) -> failure::Fallible<Tensor> {
+    let element_size = element_size(&tensor.storage_type)?;
+    let numel: i64 = tensor.size.iter().product();
+    let mut elements = Vec::with_capacity(numel as usize * element_size);
+    if numel > 0 {
+        let mut index = vec![0i64; tensor.size.len()];
+        loop {
+            let offset = tensor.storage_offset
+                + index
+                    .iter()
+                    .zip(tensor.stride.iter())
+                    .map(|(i, stride)| i * stride)
+                    .sum::<i64>();
+            let start = offset as usize * element_size;
+            if offset < 0 || start + element_size > storage.len() {
+                failure::bail!(
+                    "storage {} is too small for a tensor of size {:?}",
+                    tensor.storage_key,
+                    tensor.size
+                );
+            }
+            elements.extend_from_slice(&storage[start..start + element_size]);
+
+            // next multi-index, last dimension first
+            let mut dim = index.len();
+            while dim > 0 {
+                dim -= 1;
+                index[dim] += 1;
+                if index[dim] < tensor.size[dim] {
+                    break;
+                }
+                index[dim] = 0;
+            }
+            if index.iter().all(|i| *i == 0) {
+                break;
+            }
+        }
+    }
+
+    let values = elements.chunks_exact(element_size);
+    let flat = match tensor.storage_type.as_str() {
+        "FloatStorage" => {
+            let values: Vec<f32> = values
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect();
+            Tensor::of_slice(&values)
+        }
+        "HalfStorage" => {
+            let values: Vec<f32> = values
+                .map(|bytes| half_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])))
+                .collect();
+            Tensor::of_slice(&values)
+        }
+        "DoubleStorage" => {
+            let values: Vec<f64> = values
+                .map(|bytes| {
+                    let mut array = [0u8; 8];
+                    array.copy_from_slice(bytes);
+                    f64::from_le_bytes(array)
+                })
+                .collect();
+            Tensor::of_slice(&values)
+        }
+        "LongStorage" => {
+            let values: Vec<i64> = values
+                .map(|bytes| {
+                    let mut array = [0u8; 8];
+                    array.copy_from_slice(bytes);
+                    i64::from_le_bytes(array)
+                })
+                .collect();
+            Tensor::of_slice(&values)
+        }
+        _ => {
+            let values: Vec<i32> = values
+                .map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect();
+            Tensor::of_slice(&values)
+        }
+    };
+    Ok(flat.reshape(&tensor.size))
+}
+
+/// Collects the `(name, tensor)` entries of an unpickled state dict.
+fn state_dict_entries(value: Value) -> failure::Fallible<Vec<(String, TensorRef)>> {
+    let entries = match value {
+        Value::Dict(entries) => entries,
+        other => failure::bail!("expected a state dict, got {:?}", other),
+    };
+    let mut tensors = Vec::new();
+    for (key, value) in entries {
+        match (key, value) {
+            (Value::Str(name), Value::Tensor(tensor)) => tensors.push((name, tensor)),
+            // nested `{"state_dict": {...}}` checkpoints
+            (Value::Str(_), value @ Value::Dict(_)) => tensors.extend(state_dict_entries(value)?),
+            (key, _) => log::warn!("skipping non tensor entry {:?}", key),
+        }
+    }
+    Ok(tensors)
+}
+
+/// Reads a torch >= 1.6 checkpoint: a zip with `<archive>/data.pkl` and one
+/// `<archive>/data/<key>` file per storage.
+fn read_zip_checkpoint<R: Read + Seek>(reader: R, path: &Path) -> failure::Fallible<Vec<(String, Tensor)>> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    let pickle_name = (0..archive.len())
+        .filter_map(|i| archive.by_index(i).ok().map(|file| file.name().to_string()))
+        .find(|name| name.ends_with("data.pkl"));
+    let pickle_name = match pickle_name {
+        Some(name) => name,
+        None => failure::bail!("{:?} has no data.pkl", path),
+    };
+    let prefix = pickle_name.trim_end_matches("data.pkl").to_string();
+
+    let mut pickle = Vec::new();
+    archive.by_name(&pickle_name)?.read_to_end(&mut pickle)?;
+    let entries = state_dict_entries(Unpickler::new(&pickle, 0).load()?)?;
+
+    let mut storages: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut tensors = Vec::with_capacity(entries.len());
+    for (name, tensor) in entries {
+        if !storages.contains_key(&tensor.storage_key) {
+            let mut storage = Vec::new();
+            archive
+                .by_name(&format!("{}data/{}", prefix, tensor.storage_key))?
+                .read_to_end(&mut storage)?;
+            storages.insert(tensor.storage_key.clone(), storage);
+        }
+        let value = materialize(&tensor, &storages[&tensor.storage_key])?;
+        tensors.push((name, value));
+    }
+    Ok(tensors)
+}
+
+/// Reads a legacy checkpoint: five consecutive pickles (magic number, protocol version,
+/// system info, state dict, storage keys) followed by the raw storages, each prefixed by
+/// its number of elements.
+fn read_legacy_checkpoint(data: &[u8]) -> failure::Fallible<Vec<(String, Tensor)>> {
+    let mut unpickler = Unpickler::new(data, 0);
+    match unpickler.load()? {
+        Value::Long(LEGACY_MAGIC_NUMBER) => {}
+        _ => failure::bail!("not a pytorch checkpoint (bad magic number)"),
+    }
+    let mut position = unpickler.position;
+    for _ in 0..2 {
+        let mut unpickler = Unpickler::new(data, position);
+        unpickler.load()?;
+        position = unpickler.position;
+    }
+    let mut unpickler = Unpickler::new(data, position);
+    let entries = state_dict_entries(unpickler.load()?)?;
+    let mut unpickler = Unpickler::new(data, unpickler.position);
+    let keys = match unpickler.load()? {
+        Value::List(keys) => keys,
+        other => failure::bail!("expected the list of storage keys, got {:?}", other),
+    };
+    position = unpickler.position;
+
+    let storage_types: HashMap<&str, &str> = entries
+        .iter()
+        .map(|(_, tensor)| (tensor.storage_key.as_str(), tensor.storage_type.as_str()))
+        .collect();
+    let mut storages: HashMap<String, &[u8]> = HashMap::new();
+    for key in keys {
+        let key = match key {
+            Value::Str(key) => key,
+            other => failure::bail!("expected a storage key, got {:?}", other),
+        };
+        let storage_type = match storage_types.get(key.as_str()) {
+            Some(storage_type) => *storage_type,
+            None => failure::bail!("storage {} is not used by any tensor", key),
+        };
+        let mut header = Unpickler::new(data, position);
+        let numel = header.read_u64()? as usize;
+        let length = numel * element_size(storage_type)?;
+        storages.insert(key, header.read(length)?);
+        position = header.position;
+    }
+
+    let mut tensors = Vec::with_capacity(entries.len());
+    for (name, tensor) in entries {
+        let value = match storages.get(&tensor.storage_key) {
+            Some(storage) => materialize(&tensor, storage)?,
+            None => failure::bail!("missing storage {}", tensor.storage_key),
+        };
+        tensors.push((name, value));
+    }
+    Ok(tensors)
+}
+
+/// Reads the tensors of a `pytorch_model.bin` written by `torch.save(state_dict)`, in either
+/// the zip format (torch >= 1.6) or the legacy one, without needing python or torch.
+pub fn read_pytorch_checkpoint(path: &Path) -> failure::Fallible<Vec<(String, Tensor)>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let is_zip = file.read_exact(&mut magic).is_ok() && &magic == b"PK\x03\x04";
+    file.seek(SeekFrom::Start(0))?;
+    if is_zip {
+        // the archive reads the storages from the file as they are needed
+        read_zip_checkpoint(file, path)
+    } else {
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        read_legacy_checkpoint(&data)
+    }
+}
+
+/// Maps a checkpoint parameter name to the name `Bert::new` loads: old TensorFlow style
+/// `gamma`/`beta` layer norm names become `weight`/`bias`, and the encoder parameters of a
+/// bare `BertModel` checkpoint get the `bert.` prefix. Head parameters (`classifier.`, ...)
+/// are kept as they are.
+pub fn remap_parameter_name(name: &str) -> String {
+    let name = name.trim_start_matches("module.");
+    let name = if name.ends_with(".gamma") {
+        format!("{}.weight", name.trim_end_matches(".gamma"))
+    } else if name.ends_with(".beta") {
+        format!("{}.bias", name.trim_end_matches(".beta"))
+    } else {
+        name.to_string()
+    };
+    if name.starts_with("embeddings.") || name.starts_with("encoder.") || name.starts_with("pooler.") {
+        format!("bert.{}", name)
+    } else {
+        name
+    }
+}
+
+/// Differences between the converted tensors and the variables of a `BertModel` built from
+/// `config.json`.
+#[derive(Debug, Default)]
+pub struct ManifestCheck {
+    pub missing: Vec<String>,
+    pub unexpected: Vec<String>,
+    /// (name, shape in the checkpoint, shape expected by the model)
+    pub mismatched: Vec<(String, Vec<i64>, Vec<i64>)>,
+}
+
+impl ManifestCheck {
+    /// Unexpected tensors are allowed, they are ignored when the weights are loaded.
+    pub fn is_valid(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// Parses a JSON config file. Unlike `rust_bert::Config::from_file`, a missing or malformed
+/// file is an error instead of a panic.
+pub fn read_json_config<T: DeserializeOwned>(path: &Path) -> failure::Fallible<T> {
+    let content = fs::read_to_string(path)
+        .map_err(|error| failure::format_err!("cannot read {:?}: {}", path, error))?;
+    let config = serde_json::from_str(&content)
+        .map_err(|error| failure::format_err!("cannot parse {:?}: {}", path, error))?;
+    Ok(config)
+}
+
+pub fn check_manifest(tensors: &[(String, Tensor)], config: &BertConfig) -> ManifestCheck {
+    let vs = nn::VarStore::new(Device::Cpu);
+    let _bert: BertModel<BertEmbeddings> = BertModel::new(&(&vs.root() / "bert"), config);
+    let expected = vs.variables();
+
+    let mut check = ManifestCheck::default();
+    let mut found = HashSet::new();
+    for (name, tensor) in tensors.iter() {
+        match expected.get(name) {
+            Some(variable) => {
+                found.insert(name.as_str());
+                if variable.size() != tensor.size() {
+                    check.mismatched.push((name.clone(), tensor.size(), variable.size()));
+                }
+            }
+            None => check.unexpected.push(name.clone()),
+        }
+    }
+    check.missing = expected
+        .keys()
+        .filter(|name| !found.contains(name.as_str()))
+        .cloned()
+        .collect();
+    check.missing.sort();
+    check
+}
+
+/// What `convert_pytorch_checkpoint` read from the checkpoint and checked.
+#[derive(Debug, Default)]
+pub struct ConversionReport {
+    /// (remapped name, kind in the checkpoint, shape) of every tensor
+    pub tensors: Vec<(String, Kind, Vec<i64>)>,
+    /// false when the tensors were not checked against a config.json
+    pub validated: bool,
+    pub check: ManifestCheck,
+}
+
+impl ConversionReport {
+    /// Whether the destination file was written.
+    pub fn is_valid(&self) -> bool {
+        self.check.is_valid()
+    }
+}
+
+/// Converts `source` (`pytorch_model.bin`) to `destination` (`rust_model.ot`) and returns the
+/// manifest of tensor names and shapes. The manifest is checked against the model described
+/// by the `config.json` next to `source`, and nothing is written if a parameter is missing or
+/// has the wrong shape. A missing `config.json` is an error unless `skip_validation` is set.
+pub fn convert_pytorch_checkpoint(
+    source: &Path,
+    destination: &Path,
+    skip_validation: bool,
+) -> failure::Fallible<ConversionReport> {
+    let config_path = source.with_file_name("config.json");
+    if !skip_validation && !config_path.exists() {
+        failure::bail!(
+            "no config.json next to {:?}, the converted tensors cannot be validated",
+            source
+        );
+    }
+    let tensors: Vec<(String, Tensor)> = read_pytorch_checkpoint(source)?
+        .into_iter()
+        .map(|(name, tensor)| (remap_parameter_name(&name), tensor))
+        .collect();
+
+    let check = if skip_validation {
+        ManifestCheck::default()
+    } else {
+        check_manifest(&tensors, &read_json_config(&config_path)?)
+    };
+    let report = ConversionReport {
+        tensors: tensors
+            .iter()
+            .map(|(name, tensor)| (name.clone(), tensor.kind(), tensor.size()))
+            .collect(),
+        validated: !skip_validation,
+        check,
+    };
+
+    if report.is_valid() {
+        let tensors: Vec<(String, Tensor)> = tensors
+            .into_iter()
+            .map(|(name, tensor)| match tensor.kind() {
+                Kind::Double => (name, tensor.to_kind(Kind::Float)),
+                _ => (name, tensor),
+            })
+            .collect();
+        Tensor::save_multi(&tensors, destination)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn unpickle(data: &[u8]) -> Value {
+        Unpickler::new(data, 0).load().unwrap()
+    }
+
+    fn values(tensor: &Tensor) -> Vec<f64> {
+        let flat = tensor.view(&[-1]);
+        (0..flat.size()[0]).map(|i| flat.double_value(&[i])).collect()
+    }
+
+    /// `{"weight": tensor}`, the tensor of size (2, 2) and stride (2, 1) in float storage "0"
+    const STATE_DICT: &[u8] = b"\x80\x02}(X\x06\x00\x00\x00weightctorch._utils\n_rebuild_tensor_v2\n\
+        (X\x07\x00\x00\x00storagectorch\nFloatStorage\nX\x01\x00\x00\x000X\x03\x00\x00\x00cpuK\x04tQ\
+        K\x00K\x02K\x02\x86K\x02K\x01\x86\x89ctorch\nOrderedDict\n)Rtq\x01Ru.";
+
+    #[test]
+    fn test_long1_sign() {
+        match unpickle(b"\x80\x02\x8a\x01\xff.") {
+            Value::Int(value) => assert_eq!(-1, value),
+            other => panic!("{:?}", other),
+        }
+        match unpickle(b"\x80\x02\x8a\x02\xff\x00.") {
+            Value::Int(value) => assert_eq!(255, value),
+            other => panic!("{:?}", other),
+        }
+        match unpickle(b"\x80\x02\x8a\x02\x00\x80.") {
+            Value::Int(value) => assert_eq!(-32768, value),
+            other => panic!("{:?}", other),
+        }
+        match unpickle(b"\x80\x02\x8a\x00.") {
+            Value::Int(value) => assert_eq!(0, value),
+            other => panic!("{:?}", other),
+        }
+        match unpickle(b"\x80\x02\x8a\x0a\x6c\xfc\x9c\x46\xf9\x20\x6a\xa8\x50\x19.") {
+            Value::Long(value) => assert_eq!(LEGACY_MAGIC_NUMBER, value),
+            other => panic!("{:?}", other),
+        }
+        match unpickle(b"\x80\x02\x8a\x09\x00\x00\x00\x00\x00\x00\x00\x00\xff.") {
+            Value::Long(value) => assert_eq!(-(1i128 << 64), value),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_containers_and_memo() {
+        // {"a": 1, "b": [2, 256]}
+        let value = unpickle(b"\x80\x02}q\x00(X\x01\x00\x00\x00aK\x01X\x01\x00\x00\x00b]q\x01(K\x02M\x00\x01eu.");
+        let entries = match value {
+            Value::Dict(entries) => entries,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(2, entries.len());
+        match &entries[0] {
+            (Value::Str(key), Value::Int(1)) => assert_eq!("a", key),
+            other => panic!("{:?}", other),
+        }
+        match &entries[1] {
+            (Value::Str(key), Value::List(values)) => {
+                assert_eq!("b", key);
+                assert_eq!(vec![2, 256], values.iter().map(|v| as_int(v).unwrap()).collect::<Vec<_>>());
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // (x, x, True) with x = -2 memoized by MEMOIZE and read back by BINGET
+        match unpickle(b"\x80\x04J\xfe\xff\xff\xff\x94h\x00\x88\x87.") {
+            Value::Tuple(values) => {
+                assert_eq!(-2, as_int(&values[0]).unwrap());
+                assert_eq!(-2, as_int(&values[1]).unwrap());
+                assert_eq!(1, as_int(&values[2]).unwrap());
+            }
+            other => panic!("{:?}", other),
+        }
+        // short strings, floats, STACK_GLOBAL
+        match unpickle(b"\x80\x04\x8c\x05torch\x8c\x0cFloatStorage\x93G?\xf8\x00\x00\x00\x00\x00\x00\x86.") {
+            Value::Tuple(values) => match (&values[0], &values[1]) {
+                (Value::Global(module, name), Value::Float(value)) => {
+                    assert_eq!(("torch", "FloatStorage"), (module.as_str(), name.as_str()));
+                    assert_eq!(1.5, *value);
+                }
+                other => panic!("{:?}", other),
+            },
+            other => panic!("{:?}", other),
+        }
+        assert!(Unpickler::new(b"\x80\x02t.", 0).load().is_err());
+        assert!(Unpickler::new(b"\x80\x02\xff.", 0).load().is_err());
+        assert!(Unpickler::new(b"\x80\x02K", 0).load().is_err());
+    }
+
+    #[test]
+    fn test_rebuild_tensor() {
+        let entries = state_dict_entries(unpickle(STATE_DICT)).unwrap();
+        assert_eq!(1, entries.len());
+        let (name, tensor) = &entries[0];
+        assert_eq!("weight", name);
+        assert_eq!("0", tensor.storage_key);
+        assert_eq!("FloatStorage", tensor.storage_type);
+        assert_eq!(0, tensor.storage_offset);
+        assert_eq!(vec![2, 2], tensor.size);
+        assert_eq!(vec![2, 1], tensor.stride);
+    }
+
+    #[test]
+    fn test_half_to_f32() {
+        assert_eq!(1.0, half_to_f32(0x3c00));
+        assert_eq!(-2.0, half_to_f32(0xc000));
+        assert_eq!(65504.0, half_to_f32(0x7bff));
+        assert_eq!(0.333251953125, half_to_f32(0x3555));
+        // smallest normal and subnormal numbers
+        assert_eq!(2f32.powi(-14), half_to_f32(0x0400));
+        assert_eq!(2f32.powi(-24), half_to_f32(0x0001));
+        assert_eq!(3. * 2f32.powi(-18), half_to_f32(0x00c0));
+        assert_eq!(0x8000_0000, half_to_f32(0x8000).to_bits());
+        assert_eq!(f32::INFINITY, half_to_f32(0x7c00));
+        assert_eq!(f32::NEG_INFINITY, half_to_f32(0xfc00));
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_materialize_strided() {
+        let storage: Vec<u8> = (0..6).flat_map(|i| (i as f32).to_le_bytes().to_vec()).collect();
+        // transposed view starting at the second element
+        let tensor = TensorRef {
+            storage_key: "0".to_string(),
+            storage_type: "FloatStorage".to_string(),
+            storage_offset: 1,
+            size: vec![2, 2],
+            stride: vec![1, 2],
+        };
+        let value = materialize(&tensor, &storage).unwrap();
+        assert_eq!(vec![2, 2], value.size());
+        assert_eq!(Kind::Float, value.kind());
+        assert_eq!(vec![1., 3., 2., 4.], values(&value));
+
+        // broadcast with a zero stride
+        let tensor = TensorRef { storage_offset: 5, size: vec![3], stride: vec![0], ..tensor };
+        assert_eq!(vec![5., 5., 5.], values(&materialize(&tensor, &storage).unwrap()));
+
+        // out of the storage
+        let tensor = TensorRef { storage_offset: 4, size: vec![2, 2], stride: vec![1, 2], ..tensor };
+        assert!(materialize(&tensor, &storage).is_err());
+
+        // half storage is widened to f32
+        let storage: Vec<u8> = [0x3c00u16, 0xc000].iter().flat_map(|h| h.to_le_bytes().to_vec()).collect();
+        let tensor = TensorRef {
+            storage_type: "HalfStorage".to_string(),
+            storage_offset: 0,
+            size: vec![2],
+            stride: vec![1],
+            ..tensor
+        };
+        let value = materialize(&tensor, &storage).unwrap();
+        assert_eq!(Kind::Float, value.kind());
+        assert_eq!(vec![1., -2.], values(&value));
+    }
+
+    #[test]
+    fn test_read_zip_checkpoint() {
+        let storage: Vec<u8> = (0..4).flat_map(|i| (i as f32).to_le_bytes().to_vec()).collect();
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        writer.start_file("archive/data.pkl", options).unwrap();
+        writer.write_all(STATE_DICT).unwrap();
+        writer.start_file("archive/data/0", options).unwrap();
+        writer.write_all(&storage).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        let tensors = read_zip_checkpoint(Cursor::new(archive), Path::new("test.bin")).unwrap();
+        assert_eq!(1, tensors.len());
+        assert_eq!("weight", tensors[0].0);
+        assert_eq!(vec![2, 2], tensors[0].1.size());
+        assert_eq!(vec![0., 1., 2., 3.], values(&tensors[0].1));
+    }
+
+    #[test]
+    fn test_remap_parameter_name() {
+        assert_eq!(
+            "bert.encoder.layer.0.output.LayerNorm.weight",
+            remap_parameter_name("bert.encoder.layer.0.output.LayerNorm.gamma")
+        );
+        assert_eq!(
+            "bert.embeddings.LayerNorm.bias",
+            remap_parameter_name("module.embeddings.LayerNorm.beta")
+        );
+        assert_eq!(
+            "bert.embeddings.word_embeddings.weight",
+            remap_parameter_name("embeddings.word_embeddings.weight")
+        );
+        assert_eq!("bert.pooler.dense.bias", remap_parameter_name("pooler.dense.bias"));
+        assert_eq!("classifier.weight", remap_parameter_name("classifier.weight"));
+        // only the suffixes are renamed
+        assert_eq!(
+            "bert.encoder.layer.0.gamma_proj.weight",
+            remap_parameter_name("encoder.layer.0.gamma_proj.weight")
+        );
+        assert_eq!("alphabeta.betas", remap_parameter_name("alphabeta.betas"));
+    }
+
+    #[test]
+    fn test_convert_requires_config() {
+        let directory = std::env::temp_dir().join(format!("thistle-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("pytorch_model.bin");
+        let destination = directory.join("rust_model.ot");
+        File::create(&source).unwrap().write_all(STATE_DICT).unwrap();
+
+        let error = convert_pytorch_checkpoint(&source, &destination, false).unwrap_err();
+        assert!(error.to_string().contains("config.json"), "{}", error);
+        assert!(!destination.exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_convert_report() {
+        let directory = std::env::temp_dir().join(format!("thistle-convert-report-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("pytorch_model.bin");
+        let destination = directory.join("rust_model.ot");
+        let storage: Vec<u8> = (0..4).flat_map(|i| (i as f32).to_le_bytes().to_vec()).collect();
+        let mut writer = zip::ZipWriter::new(File::create(&source).unwrap());
+        let options = zip::write::FileOptions::default();
+        writer.start_file("archive/data.pkl", options).unwrap();
+        writer.write_all(STATE_DICT).unwrap();
+        writer.start_file("archive/data/0", options).unwrap();
+        writer.write_all(&storage).unwrap();
+        writer.finish().unwrap();
+
+        let report = convert_pytorch_checkpoint(&source, &destination, true).unwrap();
+        assert!(!report.validated);
+        assert!(report.is_valid());
+        assert_eq!(vec![("weight".to_string(), Kind::Float, vec![2, 2])], report.tensors);
+        assert!(destination.exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_read_json_config() {
+        let directory = std::env::temp_dir().join(format!("thistle-config-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.json");
+
+        std::fs::write(&path, r#"{"word_embedding_dimension": 8, "pooling_mode_cls_token": true}"#).unwrap();
+        let config: crate::model::PoolingConfig = read_json_config(&path).unwrap();
+        assert_eq!(8, config.word_embedding_dimension);
+        assert_eq!(Some(true), config.pooling_mode_cls_token);
+        assert_eq!(None, config.pooling_mode_mean_tokens);
+
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(read_json_config::<crate::model::PoolingConfig>(&path).is_err());
+        assert!(read_json_config::<crate::model::PoolingConfig>(&directory.join("missing.json")).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use tch::{nn, no_grad, Device, Tensor};
+
+use crate::model::Bert;
+
+/// Number of query/passage pairs scored in one forward pass.
+pub const DEFAULT_BATCH_SIZE: usize = 16;
+
+/// A BERT model with a classification head on top of the pooled output, scoring a
+/// query/passage pair jointly instead of comparing two independent embeddings.
+///
+/// The model directory is expected to contain `config.json`, `vocab.txt` and a
+/// `rust_model.ot` converted from a `BertForSequenceClassification` checkpoint
+/// (`bert.*` and `classifier.*` tensors).
+pub struct CrossEncoder {
+    pub bert: Bert,
+    classifier: nn::Linear,
+    batch_size: usize,
+}
+
+impl CrossEncoder {
+    pub fn new(
+        model_path: &Path,
+        num_labels: Option<i64>,
+        device: Device,
+    ) -> failure::Fallible<CrossEncoder> {
+        let num_labels = if let Some(value) = num_labels {
+            value
+        } else {
+            1
+        };
+
+        let mut bert = Bert::build(model_path, Some(510), None, device)?;
+        let hidden_size = bert.bert_config.hidden_size;
+        let classifier = nn::linear(
+            &(&bert.vs.root() / "classifier"),
+            hidden_size,
+            num_labels,
+            Default::default(),
+        );
+        bert.load_weights(model_path)?;
+
+        Ok(CrossEncoder {
+            bert,
+            classifier,
+            batch_size: DEFAULT_BATCH_SIZE,
+        })
+    }
+
+    pub fn set_batch_size(&mut self, batch_size: usize) -> &mut Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Scores each (query, passage) pair. With a single label the raw logit is returned,
+    /// otherwise the logit of the last label (the "relevant" class for two-label models).
+    pub fn predict(&self, pairs: &[(&str, &str)]) -> Vec<f64> {
+        let mut scores = Vec::with_capacity(pairs.len());
+        for batch in pairs.chunks(self.batch_size) {
+            scores.extend(self.predict_batch(batch));
+        }
+        scores
+    }
+
+    fn predict_batch(&self, pairs: &[(&str, &str)]) -> Vec<f64> {
+        let tokenized: Vec<(Vec<i64>, Vec<i64>)> = pairs
+            .iter()
+            .map(|(query, passage)| (self.bert.tokenize(query), self.bert.tokenize(passage)))
+            .collect();
+        let longest_pair = tokenized
+            .iter()
+            .map(|(a, b)| a.len() + b.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut input_ids_feature = Vec::new();
+        let mut token_type_ids_feature = Vec::new();
+        let mut input_mask_feature = Vec::new();
+        for (tokens_a, tokens_b) in tokenized.iter() {
+            let (input_ids, token_type_ids, input_mask) =
+                self.bert.get_pair_features(tokens_a, tokens_b, longest_pair);
+            input_ids_feature.push(Tensor::of_slice(&input_ids));
+            token_type_ids_feature.push(Tensor::of_slice(&token_type_ids));
+            input_mask_feature.push(Tensor::of_slice(&input_mask));
+        }
+
+        let device = self.bert.vs.device();
+        let input_ids = Tensor::stack(input_ids_feature.as_slice(), 0).to(device);
+        let token_type_ids = Tensor::stack(token_type_ids_feature.as_slice(), 0).to(device);
+        let input_mask = Tensor::stack(input_mask_feature.as_slice(), 0).to(device);
+
+        let logits = no_grad(|| {
+            let (_, pooled_output, _, _) = self
+                .bert
+                .bert
+                .forward_t(
+                    Some(input_ids),
+                    Some(input_mask),
+                    Some(token_type_ids),
+                    None,
+                    None,
+                    &None,
+                    &None,
+                    false,
+                )
+                .unwrap();
+            pooled_output.apply(&self.classifier)
+        });
+
+        let num_labels = logits.size()[1];
+        Vec::<f64>::from(&logits.select(1, num_labels - 1))
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rust_bert::bert::BertConfig;
+use tch::{nn, Device, Tensor};
+
+use crate::model::checkpoint::{check_manifest, read_json_config, ManifestCheck};
+use crate::model::{Pooling, PoolingConfig, SentenceTransformer};
+
+pub const SMOKE_TEST_SENTENCE: &str = "The quick brown fox jumps over the lazy dog.";
+
+/// What `inspect_model` found in a sentence transformer directory.
+#[derive(Debug, Default)]
+pub struct ModelReport {
+    pub hidden_size: i64,
+    pub num_hidden_layers: i64,
+    pub num_attention_heads: i64,
+    pub intermediate_size: i64,
+    pub max_position_embeddings: i64,
+    /// `vocab_size` of config.json
+    pub vocab_size: i64,
+    /// number of entries in vocab.txt
+    pub vocab_entries: usize,
+    pub pooling_modes: Vec<String>,
+    /// output dimension declared by the pooling config
+    pub output_dimension: i64,
+    pub nb_tensors: usize,
+    pub tensors: ManifestCheck,
+    /// length and L2 norm of the embedding of `SMOKE_TEST_SENTENCE`, when the weights load
+    pub smoke_encode: Option<(usize, f64)>,
+    pub problems: Vec<String>,
+}
+
+impl ModelReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for ModelReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "architecture: BERT, {} layers, {} heads", self.num_hidden_layers, self.num_attention_heads)?;
+        writeln!(f, "hidden size: {}, intermediate size: {}", self.hidden_size, self.intermediate_size)?;
+        writeln!(f, "max position embeddings: {}", self.max_position_embeddings)?;
+        writeln!(f, "vocab size: {} (vocab.txt: {} entries)", self.vocab_size, self.vocab_entries)?;
+        writeln!(f, "pooling modes: {}", self.pooling_modes.join(", "))?;
+        writeln!(f, "output dimension: {}", self.output_dimension)?;
+        writeln!(
+            f,
+            "tensors: {} in rust_model.ot, {} missing, {} unexpected, {} with a wrong shape",
+            self.nb_tensors,
+            self.tensors.missing.len(),
+            self.tensors.unexpected.len(),
+            self.tensors.mismatched.len()
+        )?;
+        for name in self.tensors.missing.iter() {
+            writeln!(f, "  missing: {}", name)?;
+        }
+        for name in self.tensors.unexpected.iter() {
+            writeln!(f, "  unexpected: {}", name)?;
+        }
+        for (name, found, expected) in self.tensors.mismatched.iter() {
+            writeln!(f, "  shape mismatch: {} is {:?}, expected {:?}", name, found, expected)?;
+        }
+        match self.smoke_encode {
+            Some((length, norm)) => writeln!(f, "smoke encode: dimension {}, norm {:.4}", length, norm)?,
+            None => writeln!(f, "smoke encode: skipped")?,
+        }
+        if self.is_ok() {
+            writeln!(f, "OK")
+        } else {
+            for problem in self.problems.iter() {
+                writeln!(f, "ERROR: {}", problem)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Checks a sentence transformer directory (`0_BERT/` and `1_Pooling/`, as prepared in the
+/// README) without panicking on a broken conversion: the configs are read, the tensors of
+/// `rust_model.ot` are compared to the ones the model expects and, if they all match, a
+/// sentence is encoded.
+pub fn inspect_model(model_path: &Path) -> failure::Fallible<ModelReport> {
+    let bert_path = model_path.join("0_BERT");
+    let config_path = bert_path.join("config.json");
+    let vocab_path = bert_path.join("vocab.txt");
+    let weights_path = bert_path.join("rust_model.ot");
+    let pooling_config_path = model_path.join("1_Pooling/config.json");
+    for path in [&config_path, &vocab_path, &weights_path, &pooling_config_path].iter() {
+        if !path.exists() {
+            failure::bail!("{:?} not found", path);
+        }
+    }
+
+    let mut report = ModelReport::default();
+
+    let config: BertConfig = read_json_config(&config_path)?;
+    report.hidden_size = config.hidden_size;
+    report.num_hidden_layers = config.num_hidden_layers;
+    report.num_attention_heads = config.num_attention_heads;
+    report.intermediate_size = config.intermediate_size;
+    report.max_position_embeddings = config.max_position_embeddings;
+    report.vocab_size = config.vocab_size;
+    report.vocab_entries = fs::read_to_string(&vocab_path)?.lines().count();
+    if report.vocab_entries as i64 != report.vocab_size {
+        report.problems.push(format!(
+            "vocab.txt has {} entries but config.json declares {}",
+            report.vocab_entries, report.vocab_size
+        ));
+    }
+
+    let pooling_config: PoolingConfig = read_json_config(&pooling_config_path)?;
+    let modes = [
+        ("cls_token", pooling_config.pooling_mode_cls_token.unwrap_or(false)),
+        ("max_tokens", pooling_config.pooling_mode_max_tokens.unwrap_or(false)),
+        // same default as `Pooling::new`
+        ("mean_tokens", pooling_config.pooling_mode_mean_tokens.unwrap_or(true)),
+        (
+            "mean_sqrt_len_tokens",
+            pooling_config.pooling_mode_mean_sqrt_len_tokens.unwrap_or(false),
+        ),
+    ];
+    report.pooling_modes = modes
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    let vs = nn::VarStore::new(Device::Cpu);
+    report.output_dimension = Pooling::new(&vs.root(), &pooling_config).get_output_dimension();
+    if pooling_config.word_embedding_dimension as i64 != config.hidden_size {
+        report.problems.push(format!(
+            "pooling word_embedding_dimension {} does not match hidden size {}",
+            pooling_config.word_embedding_dimension, config.hidden_size
+        ));
+    }
+
+    let tensors = Tensor::load_multi(&weights_path)?;
+    report.nb_tensors = tensors.len();
+    report.tensors = check_manifest(&tensors, &config);
+    if !report.tensors.is_valid() {
+        report.problems.push(String::from(
+            "rust_model.ot does not match config.json, convert the checkpoint again",
+        ));
+        return Ok(report);
+    }
+
+    let model = SentenceTransformer::new(model_path, Device::Cpu)?;
+    let embedding = model.encode(SMOKE_TEST_SENTENCE);
+    let norm = embedding.iter().map(|x| (x * x) as f64).sum::<f64>().sqrt();
+    report.smoke_encode = Some((embedding.len(), norm));
+    if !norm.is_finite() || norm == 0. {
+        report.problems.push(format!("smoke encode produced an embedding of norm {}", norm));
+    }
+    if embedding.len() as i64 != report.output_dimension {
+        report.problems.push(format!(
+            "smoke encode produced {} values, the pooling declares {}",
+            embedding.len(),
+            report.output_dimension
+        ));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_malformed_config() {
+        let model_path = std::env::temp_dir().join(format!("thistle-inspect-{}", std::process::id()));
+        fs::create_dir_all(model_path.join("0_BERT")).unwrap();
+        fs::create_dir_all(model_path.join("1_Pooling")).unwrap();
+        fs::write(model_path.join("0_BERT/config.json"), r#"{"hidden_size": "#).unwrap();
+        fs::write(model_path.join("0_BERT/vocab.txt"), "[PAD]\n").unwrap();
+        fs::write(model_path.join("0_BERT/rust_model.ot"), "").unwrap();
+        fs::write(model_path.join("1_Pooling/config.json"), r#"{"word_embedding_dimension": 8}"#).unwrap();
+
+        // an error, not a panic
+        let error = inspect_model(&model_path).unwrap_err();
+        assert!(error.to_string().contains("config.json"), "{}", error);
+        fs::remove_dir_all(&model_path).unwrap();
+    }
+}
@@ -1,7 +1,17 @@
 pub mod bert;
+pub mod checkpoint;
+pub mod cross_encoder;
+pub mod inspect;
+pub mod pool;
 pub mod pooling;
+pub mod quantized;
 pub mod sentence_transformer;
 
 pub use bert::{Bert, Features};
+pub use cross_encoder::CrossEncoder;
+pub use inspect::{inspect_model, ModelReport};
+pub use pool::EncoderPool;
 pub use pooling::{Pooling, PoolingConfig};
-pub use sentence_transformer::SentenceTransformer;
+pub use quantized::{QuantizationReport, QuantizedBert};
+pub use sentence_transformer::{EncodeConfig, SentenceTransformer, WindowCombine};
+pub use crate::role::EncodeRole;
@@ -0,0 +1,227 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::{Condvar, Mutex};
+use rayon::prelude::*;
+use tch::Device;
+
+use crate::model::{EncodeConfig, EncodeRole, Pooling, SentenceTransformer};
+
+/// The model of an `EncoderPool`, only reachable through the pool's methods.
+///
+/// SAFETY: `SentenceTransformer` is made of plain Rust data (the tokenizer, `Pooling` and
+/// `EncodeConfig`, whose `Sync` is checked by `assert_plain_data_sync` below) and of libtorch
+/// tensors (the weights in `bert`, `quantized` and the `VarStore` holding them), plus
+/// the boxed activation functions of rust-bert, which capture nothing. tch does not mark
+/// tensors `Sync`, and the boxed functions hide their auto traits, hence the manual impls.
+/// They are sound because:
+/// - the model is moved into the pool at construction and never handed out, neither by
+///   value nor by reference, so no `&mut` access and no `&self` call other than the
+///   ones below can happen once it is shared;
+/// - the pool only calls the `encode*` methods, which tokenize (read-only on the
+///   vocabulary), then run the forward pass under `no_grad`: the weight tensors are only
+///   read, every intermediate tensor is created by and owned by the calling thread, and
+///   reading the same tensor from several threads is supported by libtorch (its reference
+///   counts are atomic and no autograd graph is recorded);
+/// - the only shared mutable state of `VarStore`, its variable map, is behind a mutex and
+///   the encode path only reads the device from it.
+struct SharedModel(SentenceTransformer);
+
+unsafe impl Send for SharedModel {}
+unsafe impl Sync for SharedModel {}
+
+#[allow(dead_code)]
+fn assert_plain_data_sync() {
+    fn is_sync<T: Send + Sync>() {}
+    is_sync::<rust_tokenizers::BertTokenizer>();
+    is_sync::<Pooling>();
+    is_sync::<EncodeConfig>();
+}
+
+/// A `SentenceTransformer` loaded once and shared by any number of threads.
+///
+/// At most `max_workers` encodings run at the same time, the other callers block until a
+/// worker is released. Each running encoding uses libtorch's intra-op threads, so
+/// `max_workers * threads_per_worker` should not exceed the number of cores. The pool does not
+/// change the libtorch thread count, which is global to the process: `threads_per_worker` is
+/// the count in effect, one thread per core unless `tch::set_num_threads` was called at startup.
+///
+/// # Example
+///
+/// ```
+/// tch::set_num_threads(1);
+/// let pool = EncoderPool::new(Path::new("models/bert-base-nli-stsb-mean-tokens"), Device::Cpu, 4, 1).unwrap();
+/// let embeddings: Vec<Vec<f32>> = texts.par_iter().map(|text| pool.encode(text)).collect();
+/// ```
+pub struct EncoderPool {
+    model: Arc<SharedModel>,
+    workers: WorkerSlots,
+}
+
+/// Counts the running encodings and blocks callers beyond `max_workers`.
+struct WorkerSlots {
+    max_workers: usize,
+    busy_workers: Mutex<usize>,
+    worker_released: Condvar,
+}
+
+/// Releases the worker slot when dropped, also on panic.
+struct WorkerGuard<'a> {
+    slots: &'a WorkerSlots,
+}
+
+impl<'a> Drop for WorkerGuard<'a> {
+    fn drop(&mut self) {
+        let mut busy_workers = self.slots.busy_workers.lock();
+        *busy_workers -= 1;
+        self.slots.worker_released.notify_one();
+    }
+}
+
+impl WorkerSlots {
+    fn new(max_workers: usize) -> WorkerSlots {
+        WorkerSlots {
+            max_workers,
+            busy_workers: Mutex::new(0),
+            worker_released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> WorkerGuard {
+        let mut busy_workers = self.busy_workers.lock();
+        while *busy_workers >= self.max_workers {
+            self.worker_released.wait(&mut busy_workers);
+        }
+        *busy_workers += 1;
+        WorkerGuard { slots: self }
+    }
+}
+
+impl EncoderPool {
+    /// Loads the model weights. `threads_per_worker` is the libtorch intra-op thread count in
+    /// effect, it only sizes the pool: `max_workers == 0` picks `num_cpus / threads_per_worker`,
+    /// a single worker with libtorch's default of one thread per core.
+    pub fn new(
+        model_path: &Path,
+        device: Device,
+        max_workers: usize,
+        threads_per_worker: usize,
+    ) -> failure::Fallible<EncoderPool> {
+        let model = SentenceTransformer::new(model_path, device)?;
+        Ok(EncoderPool::from_model(model, max_workers, threads_per_worker))
+    }
+
+    pub fn from_model(
+        model: SentenceTransformer,
+        max_workers: usize,
+        threads_per_worker: usize,
+    ) -> EncoderPool {
+        let threads_per_worker = threads_per_worker.max(1);
+        let max_workers = if max_workers == 0 {
+            (num_cpus::get() / threads_per_worker).max(1)
+        } else {
+            max_workers
+        };
+        log::info!(
+            "EncoderPool max_workers {:?} threads_per_worker {:?}",
+            max_workers,
+            threads_per_worker
+        );
+        EncoderPool {
+            model: Arc::new(SharedModel(model)),
+            workers: WorkerSlots::new(max_workers),
+        }
+    }
+
+    pub fn get_max_workers(&self) -> usize {
+        self.workers.max_workers
+    }
+
+    pub fn encode_config(&self) -> &EncodeConfig {
+        &self.model.0.encode_config
+    }
+
+    /// Size of the embeddings returned by the `encode` methods.
+    pub fn get_output_dimension(&self) -> i64 {
+        self.model.0.pooling.get_output_dimension()
+    }
+
+    fn acquire(&self) -> WorkerGuard {
+        self.workers.acquire()
+    }
+
+    pub fn encode(&self, text: &str) -> Vec<f32> {
+        let _worker = self.acquire();
+        self.model.0.encode(text)
+    }
+
+    pub fn encode_with_role(&self, text: &str, role: EncodeRole) -> Vec<f32> {
+        let _worker = self.acquire();
+        self.model.0.encode_with_role(text, role)
+    }
+
+    /// Encodes `texts` as a single batch on one worker.
+    pub fn encode_batch(&self, texts: &[&str], role: EncodeRole) -> Vec<Vec<f32>> {
+        let _worker = self.acquire();
+        self.model.0.encode_batch_with_role(texts, role)
+    }
+
+    /// Splits `texts` in batches of `batch_size` and encodes them in parallel with rayon,
+    /// keeping the input order.
+    pub fn par_encode_batch(&self, texts: &[&str], role: EncodeRole, batch_size: usize) -> Vec<Vec<f32>> {
+        texts
+            .par_chunks(batch_size.max(1))
+            .map(|batch| self.encode_batch(batch, role))
+            .collect::<Vec<Vec<Vec<f32>>>>()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_max_workers_enforced() {
+        let slots = Arc::new(WorkerSlots::new(3));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..12)
+            .map(|_| {
+                let (slots, running, max_running) = (slots.clone(), running.clone(), max_running.clone());
+                thread::spawn(move || {
+                    let _worker = slots.acquire();
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(3, max_running.load(Ordering::SeqCst));
+        assert_eq!(0, *slots.busy_workers.lock());
+    }
+
+    #[test]
+    fn test_worker_released_on_panic() {
+        let slots = Arc::new(WorkerSlots::new(1));
+        let panicking = slots.clone();
+        let result = thread::spawn(move || {
+            let _worker = panicking.acquire();
+            panic!("encoding failed");
+        })
+        .join();
+        assert!(result.is_err());
+        // would block forever if the slot was not released
+        let _worker = slots.acquire();
+        assert_eq!(1, *slots.busy_workers.lock());
+    }
+}
@@ -64,6 +64,11 @@ impl Pooling {
         }
     }
 
+    /// Size of the sentence embedding produced by `forward_t`.
+    pub fn get_output_dimension(&self) -> i64 {
+        self.pooling_output_dimension as i64
+    }
+
     pub fn forward_t(&self, features: Features) -> Features {
         let mut output_vectors = Vec::new();
 
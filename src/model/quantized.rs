@@ -0,0 +1,294 @@
+use std::time::Duration;
+
+use tch::{Kind, Tensor};
+
+use crate::model::{Bert, Features};
+
+/// Sentences used to measure how far the int8 encoder drifts from the f32 one.
+pub const REFERENCE_SENTENCES: [&str; 8] = [
+    "Do not go gentle into that good night",
+    "Shall I compare thee to a summer's day",
+    "What happens to a dream deferred?",
+    "The quick brown fox jumps over the lazy dog.",
+    "How many people live in New York City?",
+    "Quantization trades a little accuracy for a lot of speed.",
+    "The patient was given 5 mg of the drug twice a day.",
+    "Rust guarantees memory safety without a garbage collector.",
+];
+
+// rust-bert does not expose it in BertConfig, BertLayerNorm uses this value.
+const LAYER_NORM_EPS: f64 = 1e-12;
+
+/// Accuracy drift of the int8 encoder measured against the f32 encoder on the same sentences.
+#[derive(Debug, Clone)]
+pub struct QuantizationReport {
+    pub nb_sentences: usize,
+    /// mean cosine similarity between f32 and int8 embeddings of the same sentence
+    pub mean_cosine: f64,
+    /// worst cosine similarity over the sentences
+    pub min_cosine: f64,
+    /// mean of |e_int8 - e_f32| / |e_f32|
+    pub mean_relative_l2: f64,
+    pub f32_time: Duration,
+    pub int8_time: Duration,
+}
+
+impl QuantizationReport {
+    pub fn speedup(&self) -> f64 {
+        self.f32_time.as_secs_f64() / self.int8_time.as_secs_f64().max(1e-9)
+    }
+}
+
+/// A linear layer with int8 weights and f32 activations, running on the fbgemm kernels of libtorch.
+/// Weights are quantized per tensor when the layer is built.
+pub struct QuantizedLinear {
+    weight: Tensor,
+    packed: Tensor,
+    col_offsets: Tensor,
+    scale: f64,
+    zero_point: i64,
+    bias: Tensor,
+}
+
+impl QuantizedLinear {
+    pub fn new(weight: &Tensor, bias: &Tensor) -> QuantizedLinear {
+        let weight = weight.to_kind(Kind::Float).contiguous();
+        let (q_weight, col_offsets, scale, zero_point) = weight.fbgemm_linear_quantize_weight();
+        let packed = q_weight.fbgemm_pack_quantized_matrix();
+        QuantizedLinear {
+            weight: q_weight,
+            packed,
+            col_offsets,
+            scale,
+            zero_point,
+            bias: bias.to_kind(Kind::Float).contiguous(),
+        }
+    }
+
+    pub fn forward(&self, xs: &Tensor) -> Tensor {
+        xs.contiguous().fbgemm_linear_int8_weight_fp32_activation(
+            &self.weight,
+            &self.packed,
+            &self.col_offsets,
+            self.scale,
+            self.zero_point as f64,
+            &self.bias,
+        )
+    }
+}
+
+struct LayerNorm {
+    weight: Tensor,
+    bias: Tensor,
+    hidden_size: i64,
+}
+
+impl LayerNorm {
+    fn forward(&self, xs: &Tensor) -> Tensor {
+        xs.layer_norm(
+            &[self.hidden_size],
+            Some(&self.weight),
+            Some(&self.bias),
+            LAYER_NORM_EPS,
+            false,
+        )
+    }
+}
+
+struct QuantizedBertLayer {
+    query: QuantizedLinear,
+    key: QuantizedLinear,
+    value: QuantizedLinear,
+    attention_output: QuantizedLinear,
+    attention_layer_norm: LayerNorm,
+    intermediate: QuantizedLinear,
+    output: QuantizedLinear,
+    output_layer_norm: LayerNorm,
+}
+
+/// The BERT encoder of a loaded `Bert` re-run with int8 linear layers.
+/// Embeddings and layer norms stay in f32, they are a small fraction of the compute.
+pub struct QuantizedBert {
+    word_embeddings: Tensor,
+    position_embeddings: Tensor,
+    token_type_embeddings: Tensor,
+    embeddings_layer_norm: LayerNorm,
+    layers: Vec<QuantizedBertLayer>,
+    num_attention_heads: i64,
+    hidden_size: i64,
+}
+
+impl QuantizedBert {
+    /// Quantizes the weights found in `bert.vs`. The f32 weights are left untouched.
+    pub fn new(bert: &Bert) -> QuantizedBert {
+        let variables = bert.vs.variables();
+        let hidden_size = bert.bert_config.hidden_size;
+        let get = |name: &str| -> Tensor {
+            variables
+                .get(&format!("bert.{}", name))
+                .unwrap_or_else(|| panic!("missing tensor bert.{} in var store", name))
+                .shallow_clone()
+        };
+        let linear = |name: &str| -> QuantizedLinear {
+            QuantizedLinear::new(&get(&format!("{}.weight", name)), &get(&format!("{}.bias", name)))
+        };
+        let layer_norm = |name: &str| -> LayerNorm {
+            LayerNorm {
+                weight: get(&format!("{}.weight", name)),
+                bias: get(&format!("{}.bias", name)),
+                hidden_size,
+            }
+        };
+
+        let mut layers = Vec::with_capacity(bert.bert_config.num_hidden_layers as usize);
+        for i in 0..bert.bert_config.num_hidden_layers {
+            let prefix = format!("encoder.layer.{}", i);
+            layers.push(QuantizedBertLayer {
+                query: linear(&format!("{}.attention.self.query", prefix)),
+                key: linear(&format!("{}.attention.self.key", prefix)),
+                value: linear(&format!("{}.attention.self.value", prefix)),
+                attention_output: linear(&format!("{}.attention.output.dense", prefix)),
+                attention_layer_norm: layer_norm(&format!("{}.attention.output.LayerNorm", prefix)),
+                intermediate: linear(&format!("{}.intermediate.dense", prefix)),
+                output: linear(&format!("{}.output.dense", prefix)),
+                output_layer_norm: layer_norm(&format!("{}.output.LayerNorm", prefix)),
+            });
+        }
+
+        QuantizedBert {
+            word_embeddings: get("embeddings.word_embeddings.weight"),
+            position_embeddings: get("embeddings.position_embeddings.weight"),
+            token_type_embeddings: get("embeddings.token_type_embeddings.weight"),
+            embeddings_layer_norm: layer_norm("embeddings.LayerNorm"),
+            layers,
+            num_attention_heads: bert.bert_config.num_attention_heads,
+            hidden_size,
+        }
+    }
+
+    /// Same contract as `Bert::forward_t`: fills token_embeddings and cls_token_embeddings.
+    pub fn forward_t(&self, features: Features) -> Features {
+        let input_ids = features.input_ids.as_ref().unwrap();
+        let token_type_ids = features.token_type_ids.as_ref().unwrap();
+        let input_mask = features.input_mask.as_ref().unwrap();
+        let size = input_ids.size();
+        let (batch_size, seq_length) = (size[0], size[1]);
+
+        let position_ids = Tensor::arange(seq_length, (Kind::Int64, input_ids.device()))
+            .unsqueeze(0)
+            .expand(&[batch_size, seq_length], false);
+        let embeddings = Tensor::embedding(&self.word_embeddings, input_ids, -1, false, false)
+            + Tensor::embedding(&self.position_embeddings, &position_ids, -1, false, false)
+            + Tensor::embedding(&self.token_type_embeddings, token_type_ids, -1, false, false);
+        let mut hidden_states = self.embeddings_layer_norm.forward(&embeddings);
+
+        // masked positions get a large negative score before the softmax
+        let extended_mask =
+            (input_mask.unsqueeze(1).unsqueeze(2).to_kind(Kind::Float) * -1.0 + 1.0) * -10000.0;
+        let head_size = self.hidden_size / self.num_attention_heads;
+        let split_heads = |xs: Tensor| -> Tensor {
+            xs.view(&[batch_size, seq_length, self.num_attention_heads, head_size])
+                .transpose(1, 2)
+        };
+
+        for layer in self.layers.iter() {
+            let query = split_heads(layer.query.forward(&hidden_states));
+            let key = split_heads(layer.key.forward(&hidden_states));
+            let value = split_heads(layer.value.forward(&hidden_states));
+
+            let scores = query.matmul(&key.transpose(-1, -2)) / (head_size as f64).sqrt()
+                + &extended_mask;
+            let context = scores
+                .softmax(-1, Kind::Float)
+                .matmul(&value)
+                .transpose(1, 2)
+                .contiguous()
+                .view(&[batch_size, seq_length, self.hidden_size]);
+
+            let attention_output = layer
+                .attention_layer_norm
+                .forward(&(layer.attention_output.forward(&context) + &hidden_states));
+            let intermediate = layer.intermediate.forward(&attention_output).gelu();
+            hidden_states = layer
+                .output_layer_norm
+                .forward(&(layer.output.forward(&intermediate) + &attention_output));
+        }
+
+        let cls_token = hidden_states.select(1, 0); //CLS token is first token
+
+        Features {
+            token_embeddings: Some(hidden_states),
+            cls_token_embeddings: Some(cls_token),
+            ..features
+        }
+    }
+}
+
+pub(crate) fn compare_embeddings(
+    reference: &[Vec<f32>],
+    quantized: &[Vec<f32>],
+    f32_time: Duration,
+    int8_time: Duration,
+) -> QuantizationReport {
+    let mut cosines = Vec::with_capacity(reference.len());
+    let mut relative_l2 = Vec::with_capacity(reference.len());
+    for (a, b) in reference.iter().zip(quantized.iter()) {
+        // accumulate in f64, the drift is small
+        let a: Vec<f64> = a.iter().map(|x| *x as f64).collect();
+        let b: Vec<f64> = b.iter().map(|x| *x as f64).collect();
+        let dot: f64 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+        let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let diff = a
+            .iter()
+            .zip(b.iter())
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum::<f64>()
+            .sqrt();
+        cosines.push(dot / (norm_a * norm_b).max(1e-12));
+        relative_l2.push(diff / norm_a.max(1e-12));
+    }
+    let nb_sentences = cosines.len();
+    QuantizationReport {
+        nb_sentences,
+        mean_cosine: cosines.iter().sum::<f64>() / nb_sentences.max(1) as f64,
+        min_cosine: cosines.iter().cloned().fold(f64::INFINITY, f64::min),
+        mean_relative_l2: relative_l2.iter().sum::<f64>() / nb_sentences.max(1) as f64,
+        f32_time,
+        int8_time,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tch::Device;
+
+    #[test]
+    fn test_quantized_linear_close_to_f32() {
+        tch::manual_seed(0);
+        let weight = Tensor::randn(&[64, 32], (Kind::Float, Device::Cpu));
+        let bias = Tensor::randn(&[64], (Kind::Float, Device::Cpu));
+        let xs = Tensor::randn(&[4, 32], (Kind::Float, Device::Cpu));
+
+        let expected = xs.matmul(&weight.tr()) + &bias;
+        let output = QuantizedLinear::new(&weight, &bias).forward(&xs);
+        assert_eq!(expected.size(), output.size());
+
+        // int8 weights and activations, per tensor: about 1% of the output norm
+        let relative_l2 = (&output - &expected).norm().double_value(&[]) / expected.norm().double_value(&[]);
+        assert!(relative_l2 < 0.05, "relative L2 error {}", relative_l2);
+    }
+
+    #[test]
+    fn test_compare_embeddings() {
+        let reference = vec![vec![1., 0.], vec![0., 2.]];
+        let quantized = vec![vec![1., 0.], vec![0., 1.]];
+        let report = compare_embeddings(&reference, &quantized, Duration::from_millis(20), Duration::from_millis(10));
+        assert_eq!(2, report.nb_sentences);
+        assert!((report.min_cosine - 1.).abs() < 1e-9);
+        assert!((report.mean_relative_l2 - 0.25).abs() < 1e-9);
+        assert!((report.speedup() - 2.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,10 @@
+/// What a text is encoded for. Asymmetric models (E5, BGE, ...) are trained with a
+/// different prefix for queries and for the documents they are matched against.
+///
+/// Shared by `model`, which builds the prefixes, and `database`, which picks the role;
+/// it does not depend on the `bert` feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncodeRole {
+    Query,
+    Document,
+}
@@ -1,4 +1,5 @@
 #![feature(array_map)]
+#![cfg(feature = "bert")]
 use thistle::database::Operations;
 
 #[test]
@@ -17,7 +18,7 @@ fn run_cosine_db() {
     ]
     .map(|x| x.to_string())
     .to_vec();
-    let mut db = thistle::database::new("Cosine");
+    let mut db = thistle::database::new("Cosine").unwrap();
     db.load(texts);
     let result = db.query("stay strong as you grow older".to_string(), 1);
     // println!("{:?}", result);
@@ -0,0 +1,79 @@
+#![feature(array_map)]
+use thistle::database::{DBConfig, Operations};
+// runs without any model file: cargo test --test hashing_embedder_test
+
+fn poems() -> Vec<String> {
+    [
+        "Do not go gentle into that good night",
+        "Shall I compare thee to a summer's day",
+        "What happens to a dream deferred?"
+    ]
+    .map(|x| x.to_string())
+    .to_vec()
+}
+
+#[test]
+fn run_hashing_embedder_dbs() {
+    for method in ["Cosine", "Euclidean", "Hnsw_Euclidean", "Hnsw_Cosine"].iter() {
+        let mut db = thistle::database::new_with_config(&DBConfig::new(method).hashing(0)).unwrap();
+        db.load(poems());
+        let result = db.query("Don't go gentle into the night".to_string(), 1);
+        println!("{} {:?}", method, result[0].text);
+        assert_eq!("Do not go gentle into that good night", result[0].text, "method {}", method);
+    }
+}
+
+#[test]
+fn run_hashing_embedder_cosine_query_past_len() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("Cosine").hashing(0)).unwrap();
+    db.load(poems());
+    // asking for more docs than loaded returns them all
+    let result = db.query("Don't go gentle into the night".to_string(), 10);
+    assert_eq!(3, result.len());
+    assert_eq!("Do not go gentle into that good night", result[0].text);
+}
+
+#[test]
+fn run_hashing_embedder_lsh_db() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("LSH").hashing(0)).unwrap();
+    db.load(poems());
+    let result = db.query("Do not go gentle into that good night".to_string(), 1);
+    assert_eq!(1, result.len());
+}
+
+#[test]
+fn run_hashing_embedder_query_threshold() {
+    for method in ["Cosine", "Hnsw_Cosine"].iter() {
+        let mut db = thistle::database::new_with_config(&DBConfig::new(method).hashing(0)).unwrap();
+        db.load(poems());
+        // a text is its own most similar document
+        let result = db.query_threshold("Do not go gentle into that good night".to_string(), 0.99).unwrap();
+        assert_eq!(1, result.len(), "method {}", method);
+        assert_eq!("Do not go gentle into that good night", result[0].text, "method {}", method);
+        let result = db.query_threshold("Do not go gentle into that good night".to_string(), -1.).unwrap();
+        assert_eq!(3, result.len(), "method {}", method);
+    }
+    let db = thistle::database::new_with_config(&DBConfig::new("LSH").hashing(0)).unwrap();
+    assert!(db.query_threshold("gentle".to_string(), 0.5).is_err());
+}
+
+#[test]
+fn run_hashing_embedder_cache_error() {
+    // the cache directory would be inside a file
+    let config = DBConfig::new("Cosine").hashing(0).cache("Cargo.toml/embedding.cache", 0);
+    assert!(thistle::database::new_with_config(&config).is_err());
+}
+
+#[test]
+#[cfg(not(feature = "bert"))]
+fn run_without_bert_requires_hashing() {
+    assert!(thistle::database::new("Cosine").is_err());
+    assert!(thistle::database::new_with_config(&DBConfig::new("Cosine").hashing(0)).is_ok());
+}
+
+#[test]
+fn run_missing_model_error() {
+    // with or without the `bert` feature, an error and not a panic
+    let config = DBConfig::new("Cosine").model_path("models/does-not-exist");
+    assert!(thistle::database::new_with_config(&config).is_err());
+}
@@ -1,4 +1,5 @@
 #![feature(array_map)]
+#![cfg(feature = "bert")]
 use thistle::database::Operations;
 
 #[test]
@@ -10,7 +11,7 @@ fn run_hnsw_cosine_db() {
     ]
     .map(|x| x.to_string())
     .to_vec();
-    let mut db = thistle::database::new("Hnsw_Cosine");
+    let mut db = thistle::database::new("Hnsw_Cosine").unwrap();
     db.load(texts);
     let result = db.query("stay strong as you grow older".to_string(), 1);
     println!("{:?}", result);
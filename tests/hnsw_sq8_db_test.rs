@@ -0,0 +1,37 @@
+#![feature(array_map)]
+use thistle::database::{DBConfig, Operations};
+// runs without any model file: cargo test --test hnsw_sq8_db_test
+
+fn poems() -> Vec<String> {
+    [
+        "Do not go gentle into that good night",
+        "Shall I compare thee to a summer's day",
+        "What happens to a dream deferred?"
+    ]
+    .map(|x| x.to_string())
+    .to_vec()
+}
+
+#[test]
+fn run_hnsw_sq8_db() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("Hnsw_Sq8").hashing(0)).unwrap();
+    db.load(poems());
+    let result = db.query("Don't go gentle into the night".to_string(), 1);
+    assert_eq!("Do not go gentle into that good night", result[0].text);
+    assert!(result[0].embedding.is_empty());
+}
+
+#[test]
+fn run_hnsw_sq8_db_with_rescoring() {
+    let path = std::env::temp_dir().join(format!("thistle-sq8-{}.f32", std::process::id()));
+    let config = DBConfig::new("Hnsw_Sq8").hashing(0).rescore_from_disk(path.to_str().unwrap());
+    let mut db = thistle::database::new_with_config(&config).unwrap();
+    db.load(poems());
+    let result = db.query("Don't go gentle into the night".to_string(), 2);
+    assert_eq!(2, result.len());
+    assert_eq!("Do not go gentle into that good night", result[0].text);
+    assert!(result[0].score <= result[1].score);
+    let norm: f32 = result[0].embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    assert!((norm - 1.).abs() < 1e-4);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use thistle::database::error::Error;
+use thistle::database::hashing::HashingEmbedder;
+use thistle::database::import::read_jsonl;
+use thistle::database::{DBConfig, Operations};
+// runs without any model file: cargo test --test import_test
+
+fn poems() -> Vec<&'static str> {
+    vec![
+        "Do not go gentle into that good night",
+        "Shall I compare thee to a summer's day",
+        "What happens to a dream deferred?",
+    ]
+}
+
+#[test]
+fn run_load_with_embeddings_from_jsonl() {
+    let embedder = HashingEmbedder::new(64);
+    let lines: Vec<String> = poems()
+        .iter()
+        .map(|text| serde_json::json!({"text": text, "embedding": embedder.embed(text)}).to_string())
+        .collect();
+    let path = std::env::temp_dir().join(format!("thistle-import-test-{}.jsonl", std::process::id()));
+    fs::write(&path, lines.join("\n")).unwrap();
+    let items = read_jsonl(&path, "text", "embedding").unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(3, items.len());
+
+    for method in ["Cosine", "Euclidean", "Hnsw_Euclidean", "Hnsw_Cosine"].iter() {
+        let mut db = thistle::database::new_with_config(&DBConfig::new(method).hashing(64)).unwrap();
+        db.load_with_embeddings(items.clone()).unwrap();
+        let result = db.query("Don't go gentle into the night".to_string(), 1);
+        assert_eq!("Do not go gentle into that good night", result[0].text, "method {}", method);
+    }
+}
+
+#[test]
+fn run_load_with_embeddings_dimension_mismatch() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("Hnsw_Cosine").hashing(64)).unwrap();
+    let items = vec![
+        ("a".to_string(), vec![0.; 64]),
+        ("b".to_string(), vec![0.; 32]),
+    ];
+    match db.load_with_embeddings(items) {
+        Err(Error::DimensionMismatch { index, found, expected }) => {
+            assert_eq!((1, 32, 64), (index, found, expected));
+        }
+        other => panic!("expected a dimension mismatch, got {:?}", other),
+    }
+}
@@ -0,0 +1,65 @@
+#![feature(array_map)]
+use thistle::database::db::DB;
+use thistle::database::{DBConfig, Operations};
+// runs without any model file: cargo test --test ivf_flat_db_test
+
+fn poems() -> Vec<String> {
+    [
+        "Do not go gentle into that good night",
+        "Shall I compare thee to a summer's day",
+        "What happens to a dream deferred?"
+    ]
+    .map(|x| x.to_string())
+    .to_vec()
+}
+
+#[test]
+fn run_ivf_flat_db() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("IVF_Flat").hashing(0).nprobe(2)).unwrap();
+    db.load(poems());
+    let result = db.query("Don't go gentle into the night".to_string(), 2);
+    assert_eq!(2, result.len());
+    assert_eq!("Do not go gentle into that good night", result[0].text);
+    assert!(result[0].score <= result[1].score);
+    let norm: f32 = result[0].embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    assert!((norm - 1.).abs() < 1e-4);
+}
+
+#[test]
+fn run_ivf_flat_db_insert_delete_retrain() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("IVF_Flat").hashing(0).nprobe(8)).unwrap();
+    db.load(poems());
+    let ivf = match &mut db {
+        DB::IvfFlatDB(ivf) => ivf,
+        _ => panic!("IVF_Flat should build an IvfFlatDB"),
+    };
+    let id = ivf.insert("The woods are lovely, dark and deep".to_string()).unwrap();
+    assert_eq!(3, id);
+    let result = ivf.query("lovely dark woods".to_string(), 1);
+    assert_eq!("The woods are lovely, dark and deep", result[0].text);
+
+    assert!(ivf.delete(id));
+    assert!(!ivf.delete(id));
+    let result = ivf.query("lovely dark woods".to_string(), 4);
+    assert_eq!(3, result.len());
+    assert!(result.iter().all(|doc| doc.text != "The woods are lovely, dark and deep"));
+
+    ivf.retrain().unwrap();
+    let result = ivf.query("Don't go gentle into the night".to_string(), 1);
+    assert_eq!("Do not go gentle into that good night", result[0].text);
+}
+
+#[test]
+fn run_ivf_flat_db_seeded() {
+    let texts: Vec<String> = (0..50).map(|i| format!("poem number {} about the sea and the night {}", i, i % 7)).collect();
+    let centroids = |seed: u64| {
+        let mut db = thistle::database::new_with_config(&DBConfig::new("IVF_Flat").hashing(0).seed(seed)).unwrap();
+        db.load(texts.clone());
+        match db {
+            DB::IvfFlatDB(ivf) => ivf.index.centroids,
+            _ => panic!("IVF_Flat should build an IvfFlatDB"),
+        }
+    };
+    // same seed, same training sample and k-means
+    assert_eq!(centroids(7), centroids(7));
+}
@@ -0,0 +1,49 @@
+#![feature(array_map)]
+use thistle::database::db::DB;
+use thistle::database::{DBConfig, Operations};
+// runs without any model file: cargo test --test ivf_pq_db_test
+
+fn poems() -> Vec<String> {
+    [
+        "Do not go gentle into that good night",
+        "Shall I compare thee to a summer's day",
+        "What happens to a dream deferred?"
+    ]
+    .map(|x| x.to_string())
+    .to_vec()
+}
+
+#[test]
+fn run_ivf_pq_db() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("IVF_PQ").hashing(0).nprobe(2)).unwrap();
+    db.load(poems());
+    let result = db.query("Don't go gentle into the night".to_string(), 2);
+    assert_eq!(2, result.len());
+    assert_eq!("Do not go gentle into that good night", result[0].text);
+    assert!(result[0].score <= result[1].score);
+    assert!(result[0].embedding.is_empty());
+}
+
+#[test]
+fn run_ivf_pq_db_with_embeddings() {
+    let mut db = thistle::database::new_with_config(&DBConfig::new("IVF_PQ").hashing(0)).unwrap();
+    assert!(db.load_with_embeddings(vec![("too short".to_string(), vec![1., 0.])]).is_err());
+}
+
+#[test]
+fn run_ivf_pq_db_seeded() {
+    let texts: Vec<String> = (0..50).map(|i| format!("poem number {} about the sea and the night {}", i, i % 7)).collect();
+    let codes = |seed: u64| {
+        let mut db = thistle::database::new_with_config(&DBConfig::new("IVF_PQ").hashing(0).seed(seed)).unwrap();
+        db.load(texts.clone());
+        match db {
+            DB::IvfPqDB(ivf) => {
+                let codes: Vec<Vec<u8>> = ivf.index.lists.into_iter().map(|list| list.codes).collect();
+                (ivf.index.coarse_centroids, codes)
+            }
+            _ => panic!("IVF_PQ should build an IvfPqDB"),
+        }
+    };
+    // same seed, same coarse centroids and PQ codes
+    assert_eq!(codes(7), codes(7));
+}
@@ -1,4 +1,5 @@
 #![feature(array_map)]
+#![cfg(feature = "bert")]
 use thistle::database::Operations;
 // cargo test --test lsh_db_test
 #[test]
@@ -10,7 +11,7 @@ fn run_lsh_db() {
     ]
     .map(|x| x.to_string())
     .to_vec();
-    let mut db = thistle::database::new("LSH");
+    let mut db = thistle::database::new("LSH").unwrap();
     db.load(texts);
     let result = db.query("Don't go into the night".to_string(), 1);
     println!("{:?}", result[0].text);
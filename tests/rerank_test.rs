@@ -0,0 +1,23 @@
+#![feature(array_map)]
+#![cfg(feature = "bert")]
+use thistle::database::Operations;
+
+#[test]
+fn run_rerank_after_query() {
+    let texts = [
+        "Do not go gentle into that good night",
+        "Shall I compare thee to a summer's day",
+        "What happens to a dream deferred?"
+    ]
+    .map(|x| x.to_string())
+    .to_vec();
+    let mut db = thistle::database::new("Cosine").unwrap();
+    db.load(texts);
+    let query = "stay strong as you grow older";
+    let candidates = db.query(query.to_string(), 3);
+    let cross_encoder = thistle::database::get_cross_encoder().unwrap();
+    let result = thistle::database::rerank_with(&cross_encoder, query, candidates, 1);
+    println!("{:?}", result);
+    assert_eq!(1, result.len());
+    assert_eq!("Do not go gentle into that good night", result[0].text);
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "bert")]
+use std::path::Path;
+use tch::Device;
+use thistle::model::{EncodeRole, SentenceTransformer, WindowCombine};
+
+#[test]
+fn run_encode_windowed() {
+    let mut model = SentenceTransformer::new(Path::new("models/bert-base-nli-stsb-mean-tokens"), Device::Cpu).unwrap();
+    model.encode_config.document_max_seq_length = Some(16);
+    let dimension = model.encode("Do not go gentle into that good night").len();
+
+    // about 80 tokens, longer than max_seq_length
+    let text = "Do not go gentle into that good night, old age should burn and rave at close of day. ".repeat(4);
+    let (mean, nb_windows) = model.encode_windowed(&text, EncodeRole::Document, 0, WindowCombine::Mean).unwrap();
+    assert!(nb_windows > 1, "{} windows", nb_windows);
+    assert_eq!(dimension, mean.len());
+
+    // overlapping windows advance by fewer tokens
+    let (_, nb_overlapping) = model.encode_windowed(&text, EncodeRole::Document, 8, WindowCombine::Mean).unwrap();
+    assert!(nb_overlapping > nb_windows, "{} <= {}", nb_overlapping, nb_windows);
+
+    let (max, _) = model.encode_windowed(&text, EncodeRole::Document, 0, WindowCombine::Max).unwrap();
+    let (weighted, _) = model.encode_windowed(&text, EncodeRole::Document, 0, WindowCombine::WeightedMean).unwrap();
+    assert_eq!(dimension, max.len());
+    assert_eq!(dimension, weighted.len());
+    // the element-wise max bounds the mean of the same windows
+    assert!(max.iter().zip(mean.iter()).all(|(m, a)| m + 1e-5 >= *a));
+
+    // a text that fits is one window, whatever the mode
+    let short = "Do not go gentle";
+    let (short_mean, nb_short) = model.encode_windowed(short, EncodeRole::Document, 4, WindowCombine::Mean).unwrap();
+    let (short_max, _) = model.encode_windowed(short, EncodeRole::Document, 4, WindowCombine::Max).unwrap();
+    assert_eq!(1, nb_short);
+    assert_eq!(short_mean, short_max);
+
+    // a prefix filling max_seq_length leaves no room for the text
+    model.encode_config.document_prefix = Some("passage: ".repeat(20));
+    assert!(model.encode_windowed(&text, EncodeRole::Document, 0, WindowCombine::Mean).is_err());
+}